@@ -0,0 +1,214 @@
+/*
+ * Benchmarks of aggregating a file on a single thread, which is where the work of every line is
+ * done, however many threads share it out. Run them with
+ *
+ *   cargo bench --bench aggregate
+ *
+ * Several of the features are alternatives for the same step, so they're compared by running the
+ * same benchmarks with and without them, such as the standard library's SipHash against FxHash:
+ *
+ *   cargo bench --bench aggregate --no-default-features --features swar
+ *
+ * or the simd feature's searches 16 bytes at a time against searching a byte at a time:
+ *
+ *   cargo bench --bench aggregate --features simd
+ *
+ * or prefetching each name while the one before it is merged against not prefetching at all:
+ *
+ *   cargo bench --bench aggregate --features prefetch
+ *
+ * The file is read from the page cache after the first iteration, so these measure the CPU's work
+ * rather than the disk's.
+ */
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use common::{generated, long_names, many_stations, TempFile};
+use one_brc::{aggregate_file_with, Measurement, Options};
+use test::Bencher;
+
+const ROWS: u64 = 1_000_000;
+
+fn aggregate(b: &mut Bencher, name: &str, contents: &[u8]) {
+    let options = Options {
+        parallelism: 1,
+        ..Options::default()
+    };
+
+    aggregate_with(b, name, contents, options);
+}
+
+fn aggregate_with(b: &mut Bencher, name: &str, contents: &[u8], options: Options) {
+    let file = TempFile::new(name, contents);
+
+    b.bytes = contents.len() as u64;
+    b.iter(|| aggregate_file_with(&file.0, &options).unwrap());
+}
+
+// Every line hashes its station's name to find it, which a slow hasher makes the largest cost
+#[bench]
+fn a_million_lines_of_413_stations(b: &mut Bencher) {
+    aggregate(b, "413-stations", &generated(ROWS));
+}
+
+#[bench]
+fn a_million_lines_of_10_000_stations(b: &mut Bencher) {
+    aggregate(b, "10000-stations", &many_stations(ROWS, 25));
+}
+
+// Asked for 1024 chunks, the file is split into as many as their minimum size allows, over 250.
+// Merging each chunk's 10,000 stations into the rest then takes a good part of the run, which is
+// all that prefetching can speed up
+#[bench]
+fn a_million_lines_of_10_000_stations_in_small_chunks(b: &mut Bencher) {
+    let options = Options {
+        parallelism: 1,
+        chunks_per_thread: 1024,
+        min_parallel_bytes: 0,
+        ..Options::default()
+    };
+
+    aggregate_with(
+        b,
+        "10000-stations-small-chunks",
+        &many_stations(ROWS, 25),
+        options,
+    );
+}
+
+// With names this long, most of each line is searched through for its end, which is where
+// searching several bytes at a time makes the most difference
+#[bench]
+fn a_million_lines_of_long_names(b: &mut Bencher) {
+    aggregate(b, "long-names", &long_names(ROWS, 64));
+}
+
+/*
+ * Recording a measurement updates the min and max with conditional moves, which cost the same
+ * whatever order the measurements come in, against the branches they replaced, which are only
+ * cheap while they're predicted. Sorted input changes the max on every line, and a station whose
+ * min and max keep growing in turn has the branches go a different way every time.
+ */
+fn measurements(order: &str) -> Vec<i32> {
+    let mut state = 1u64;
+    let mut measurements = Vec::from_iter((0..ROWS).map(|_| {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((state >> 33) % 199_999) as i32 - 99_999
+    }));
+
+    match order {
+        "shuffled" => {}
+        "sorted" => measurements.sort_unstable(),
+        "growing" => {
+            let bound = measurements.len() as i32 / 2;
+            measurements = (0..bound).flat_map(|i| [-i, i]).collect();
+        }
+        _ => unreachable!(),
+    }
+
+    measurements
+}
+
+// The two ways of updating the min and max, on their own, so that nothing else recorded for a
+// measurement dilutes the difference between them
+#[derive(Clone, Copy)]
+struct Extremes {
+    min: i64,
+    max: i64,
+}
+
+#[inline(never)]
+fn with_conditional_moves(extremes: &mut Extremes, measurements: &[i32]) {
+    for &m in measurements {
+        let m = m as i64;
+        extremes.min = extremes.min.min(m);
+        extremes.max = extremes.max.max(m);
+    }
+}
+
+#[inline(never)]
+fn with_branches(extremes: &mut Extremes, measurements: &[i32]) {
+    for &m in measurements {
+        let m = m as i64;
+        if m < extremes.min {
+            extremes.min = m;
+        }
+        if m > extremes.max {
+            extremes.max = m;
+        }
+    }
+}
+
+fn update(b: &mut Bencher, order: &str, update: fn(&mut Extremes, &[i32])) {
+    let measurements = measurements(order);
+
+    b.iter(|| {
+        let mut extremes = test::black_box(Extremes {
+            min: i64::MAX,
+            max: i64::MIN,
+        });
+        update(&mut extremes, test::black_box(&measurements));
+
+        extremes.min + extremes.max
+    });
+}
+
+#[bench]
+fn updating_the_min_and_max_of_shuffled_measurements(b: &mut Bencher) {
+    update(b, "shuffled", with_conditional_moves);
+}
+
+#[bench]
+fn updating_the_min_and_max_of_sorted_measurements(b: &mut Bencher) {
+    update(b, "sorted", with_conditional_moves);
+}
+
+#[bench]
+fn updating_a_growing_min_and_max(b: &mut Bencher) {
+    update(b, "growing", with_conditional_moves);
+}
+
+#[bench]
+fn updating_the_min_and_max_of_shuffled_measurements_with_branches(b: &mut Bencher) {
+    update(b, "shuffled", with_branches);
+}
+
+#[bench]
+fn updating_the_min_and_max_of_sorted_measurements_with_branches(b: &mut Bencher) {
+    update(b, "sorted", with_branches);
+}
+
+#[bench]
+fn updating_a_growing_min_and_max_with_branches(b: &mut Bencher) {
+    update(b, "growing", with_branches);
+}
+
+// Everything else that's recorded for a measurement is the same whatever order they come in, so
+// recording them into a measurement should take as long sorted as shuffled too
+fn record(b: &mut Bencher, order: &str) {
+    let measurements = measurements(order);
+
+    b.iter(|| {
+        let mut measurement = Measurement::default();
+        for &m in test::black_box(&measurements) {
+            measurement.record(m);
+        }
+
+        test::black_box(measurement)
+    });
+}
+
+#[bench]
+fn recording_shuffled_measurements(b: &mut Bencher) {
+    record(b, "shuffled");
+}
+
+#[bench]
+fn recording_sorted_measurements(b: &mut Bencher) {
+    record(b, "sorted");
+}
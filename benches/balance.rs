@@ -0,0 +1,70 @@
+/*
+ * Benchmarks of how evenly the work of a file is shared out between threads, which only means
+ * anything on a machine with at least as many cores as THREADS. Run them with
+ *
+ *   cargo bench --bench balance
+ */
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use common::{generated, long_names, TempFile};
+use one_brc::{aggregate_file_with, Options};
+use test::Bencher;
+
+const ROWS: u64 = 2_000_000;
+const THREADS: usize = 4;
+
+fn aggregate(b: &mut Bencher, name: &str, contents: &[u8], chunks_per_thread: usize) {
+    let file = TempFile::new(name, contents);
+    let options = Options {
+        parallelism: THREADS,
+        chunks_per_thread,
+        ..Options::default()
+    };
+
+    b.bytes = contents.len() as u64;
+    b.iter(|| aggregate_file_with(&file.0, &options).unwrap());
+}
+
+/*
+ * The first part of the file has names 200 bytes longer than the rest, which makes for fewer
+ * lines to a byte, and so less work. Split in equal parts one for each thread, the threads given
+ * the rest of the file take longer than the one given the first part. Splitting it into more
+ * chunks than there are threads lets that one go on to take some of theirs.
+ */
+fn skewed() -> Vec<u8> {
+    let mut contents = long_names(ROWS / 4, 200);
+    contents.extend_from_slice(&generated(ROWS));
+
+    contents
+}
+
+#[bench]
+fn skewed_with_one_chunk_per_thread(b: &mut Bencher) {
+    aggregate(b, "skewed-1", &skewed(), 1);
+}
+
+#[bench]
+fn skewed_with_4_chunks_per_thread(b: &mut Bencher) {
+    aggregate(b, "skewed-4", &skewed(), 4);
+}
+
+#[bench]
+fn skewed_with_16_chunks_per_thread(b: &mut Bencher) {
+    aggregate(b, "skewed-16", &skewed(), 16);
+}
+
+// With every part of the file the same amount of work, sharing out more chunks has nothing to
+// balance, so this is what taking each of them from the shared index and merging its results costs
+#[bench]
+fn even_with_one_chunk_per_thread(b: &mut Bencher) {
+    aggregate(b, "even-1", &generated(ROWS), 1);
+}
+
+#[bench]
+fn even_with_16_chunks_per_thread(b: &mut Bencher) {
+    aggregate(b, "even-16", &generated(ROWS), 16);
+}
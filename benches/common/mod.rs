@@ -0,0 +1,67 @@
+// Each of the benchmarks only uses some of these
+#![allow(dead_code)]
+
+use std::{io::Write, path::PathBuf};
+
+// A file in the temporary directory that's removed again once the benchmark is done with it
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("one-brc-bench-{}-{}.txt", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// A deterministic input of the challenge's 413 stations, the same on every run and machine
+pub fn generated(rows: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    one_brc::generate(&mut contents, rows, 1).unwrap();
+
+    contents
+}
+
+/*
+ * Like `generated`, but with each of the challenge's stations split into `copies` of itself, as in
+ * "Hamburg 17", for the 10,000 station variant of the challenge, where the map of stations no
+ * longer fits in the faster caches.
+ */
+pub fn many_stations(rows: u64, copies: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    for (i, line) in generated(rows).split(|&byte| byte == b'\n').enumerate() {
+        let Some(delimiter) = line.iter().rposition(|&byte| byte == b';') else {
+            continue;
+        };
+
+        let copy = (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32;
+        contents.extend_from_slice(&line[..delimiter]);
+        write!(contents, " {}", copy % copies).unwrap();
+        contents.extend_from_slice(&line[delimiter..]);
+        contents.push(b'\n');
+    }
+
+    contents
+}
+
+// Like `generated`, but with every station's name made `extra` bytes longer, so that finding the
+// end of each line takes up more of the work than parsing it
+pub fn long_names(rows: u64, extra: usize) -> Vec<u8> {
+    let prefix = "Weather station of ".repeat(extra.div_ceil(19))[..extra].to_owned();
+
+    let mut contents = Vec::new();
+    for line in generated(rows).split_inclusive(|&byte| byte == b'\n') {
+        contents.extend_from_slice(prefix.as_bytes());
+        contents.extend_from_slice(line);
+    }
+
+    contents
+}
@@ -0,0 +1,155 @@
+/*
+ * Benchmarks of the options for how a file is read, each against the same file read the usual
+ * way, mapped and split between every core. Run them with
+ *
+ *   cargo bench --bench io
+ *
+ * The file is read from the page cache after the first iteration, so these can only show what an
+ * option costs or saves once the file is in memory, such as fewer TLB misses with huge pages. What
+ * an option does for a cold read from the disk has to be measured one run at a time, with the
+ * cache dropped before each, which the command line's --bench is for:
+ *
+ *   sync && echo 3 | sudo tee /proc/sys/vm/drop_caches
+ *   one-brc measurements.txt --bench --no-result --hugepages
+ */
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use common::{generated, TempFile};
+use one_brc::{aggregate_file_with, aggregate_stream, Options, Readahead};
+use test::Bencher;
+
+// Enough for the mapping to take tens of thousands of the usual 4KB pages
+const ROWS: u64 = 4_000_000;
+
+fn aggregate(b: &mut Bencher, name: &str, options: Options) {
+    let contents = generated(ROWS);
+    let file = TempFile::new(name, &contents);
+
+    b.bytes = contents.len() as u64;
+    b.iter(|| aggregate_file_with(&file.0, &options).unwrap());
+}
+
+#[bench]
+fn mapped(b: &mut Bencher) {
+    aggregate(b, "mapped", Options::default());
+}
+
+// Only does anything on Linux, for a file on a filesystem that can map it with huge pages, such as
+// a tmpfs mounted with huge=always
+#[bench]
+fn mapped_with_huge_pages(b: &mut Bencher) {
+    let options = Options {
+        huge_pages: true,
+        ..Options::default()
+    };
+
+    aggregate(b, "huge-pages", options);
+}
+
+// Readahead only changes how the file comes off the disk, so warm these should all be within noise
+// of the usual read, which is what they cost when they don't help
+#[bench]
+fn mapped_with_sequential_readahead(b: &mut Bencher) {
+    let options = Options {
+        readahead: Readahead::Sequential,
+        ..Options::default()
+    };
+
+    aggregate(b, "sequential", options);
+}
+
+#[bench]
+fn mapped_with_the_whole_file_read_ahead(b: &mut Bencher) {
+    let options = Options {
+        readahead: Readahead::WillNeed,
+        ..Options::default()
+    };
+
+    aggregate(b, "willneed", options);
+}
+
+// On a machine with a single NUMA node there's no remote memory for pinning to avoid, so this is
+// what pinning costs by keeping the scheduler from moving threads onto idle cores
+#[bench]
+fn mapped_with_threads_pinned_to_cores(b: &mut Bencher) {
+    let options = Options {
+        numa: true,
+        ..Options::default()
+    };
+
+    aggregate(b, "numa", options);
+}
+
+// A stream is aggregated a block at a time while the next block is read, against reading the
+// whole file first and only then aggregating it, which a file too small to split is. Warm, the
+// reads are copies out of the page cache, which is the least there is to overlap.
+#[bench]
+fn streamed_while_aggregating(b: &mut Bencher) {
+    let contents = generated(ROWS);
+    let file = TempFile::new("streamed", &contents);
+
+    b.bytes = contents.len() as u64;
+    b.iter(|| {
+        let reader = std::io::BufReader::new(std::fs::File::open(&file.0).unwrap());
+        aggregate_stream(reader).unwrap()
+    });
+}
+
+#[bench]
+fn read_then_aggregated(b: &mut Bencher) {
+    let options = Options {
+        min_parallel_bytes: usize::MAX,
+        ..Options::default()
+    };
+
+    aggregate(b, "read-then-aggregated", options);
+}
+
+// Aligning chunks to 64KB stripes only helps reads off a striped array. Warm, the first is what the
+// slightly unequal chunks cost, and the second what interleaving the stripes between the threads
+// with many more chunks per thread costs
+#[bench]
+fn mapped_with_chunks_aligned_to_stripes(b: &mut Bencher) {
+    let options = Options {
+        stripe_size: Some(64 * 1024),
+        ..Options::default()
+    };
+
+    aggregate(b, "stripes", options);
+}
+
+#[bench]
+fn mapped_with_interleaved_stripes(b: &mut Bencher) {
+    let options = Options {
+        stripe_size: Some(64 * 1024),
+        chunks_per_thread: 64,
+        ..Options::default()
+    };
+
+    aggregate(b, "interleaved-stripes", options);
+}
+
+// Unlike the others, every iteration of this one reads the file from the device itself, which
+// makes it the only one to measure a cold read. Where direct reads aren't supported, such as on a
+// tmpfs, there's nothing to measure.
+#[bench]
+fn read_directly(b: &mut Bencher) {
+    let contents = generated(ROWS);
+    let file = TempFile::new("direct", &contents);
+    let options = Options {
+        direct: true,
+        ..Options::default()
+    };
+
+    match aggregate_file_with(&file.0, &options) {
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => return,
+        result => result.unwrap(),
+    };
+
+    b.bytes = contents.len() as u64;
+    b.iter(|| aggregate_file_with(&file.0, &options).unwrap());
+}
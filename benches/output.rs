@@ -0,0 +1,117 @@
+/*
+ * Benchmarks of what happens to the results once every thread is done with the file: merging each
+ * thread's results into one, ordering them, and formatting them. Run them with
+ *
+ *   cargo bench --bench output
+ *
+ * None of it depends on the input's lines, only on how many stations there are, so the results
+ * are built up front and only what's after them is measured.
+ */
+#![feature(test)]
+
+extern crate test;
+
+mod common;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Write,
+};
+
+use common::many_stations;
+use one_brc::{aggregate_stream, merge, Accumulator, Format, Measurement, OutputOptions};
+use test::Bencher;
+
+// One set of results for each of this many threads, every one of which has seen most of the
+// 10,000 stations
+const THREADS: usize = 16;
+
+fn partials() -> Vec<BTreeMap<String, Measurement>> {
+    let contents = many_stations(1_000_000, 25);
+    let lines = Vec::from_iter(contents.split_inclusive(|&byte| byte == b'\n'));
+
+    lines
+        .chunks(lines.len().div_ceil(THREADS))
+        .map(|lines| aggregate_stream(lines.concat().as_slice()).unwrap())
+        .collect()
+}
+
+// Merging them straight into a map that's already in order once they're all in
+#[bench]
+fn merged_into_a_btree_map(b: &mut Bencher) {
+    let partials = partials();
+
+    b.iter(|| {
+        let mut results = BTreeMap::new();
+        for partial in &partials {
+            merge(&mut results, partial);
+        }
+
+        results.len()
+    });
+}
+
+// Against merging them into a hash map, which is then sorted by name into the map that's returned,
+// as the threads' results are
+#[bench]
+fn merged_into_a_hash_map_then_sorted(b: &mut Bencher) {
+    let partials = partials();
+
+    b.iter(|| {
+        let mut results = HashMap::<String, Measurement>::new();
+        for partial in &partials {
+            for (city, measurement) in partial {
+                match results.get_mut(city) {
+                    Some(item) => item.merge(measurement),
+                    None => {
+                        results.insert(city.clone(), measurement.clone());
+                    }
+                }
+            }
+        }
+
+        BTreeMap::from_iter(results).len()
+    });
+}
+
+// Nearly a million stations picked at random from 2^20, most of them seen a few times
+fn a_million_stations() -> BTreeMap<String, Measurement> {
+    let mut contents = Vec::new();
+    for i in 0..3_000_000u64 {
+        let station = i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 44;
+        writeln!(contents, "Station {};{}.{}", station, i % 99, i % 10).unwrap();
+    }
+
+    aggregate_stream(contents.as_slice()).unwrap()
+}
+
+fn write(b: &mut Bencher, parallelism: usize) {
+    let results = a_million_stations();
+    let results = Vec::from_iter(&results);
+    let mut output = Vec::new();
+
+    b.iter(|| {
+        output.clear();
+        Format::OneBrc
+            .write_parallel(
+                &mut output,
+                &results,
+                &OutputOptions::default(),
+                parallelism,
+            )
+            .unwrap();
+        output.len()
+    });
+    b.bytes = output.len() as u64;
+}
+
+#[bench]
+fn a_million_stations_written_on_one_thread(b: &mut Bencher) {
+    write(b, 1);
+}
+
+// Every thread formats its own range of the stations, which are then written out in order
+#[bench]
+fn a_million_stations_written_on_4_threads(b: &mut Bencher) {
+    write(b, 4);
+}
@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+/*
+ * The first byte is the delimiter and the rest is the line, so that delimiters other than ';' are
+ * covered too, including ones outside of ASCII. Whatever the bytes, parsing has to either fail
+ * with an error or split the line at a delimiter, never panic.
+ */
+fuzz_target!(|data: &[u8]| {
+    let Some((&delimiter, line)) = data.split_first() else {
+        return;
+    };
+
+    if let Ok((station, _)) = one_brc::try_parse_line(line, delimiter) {
+        assert_eq!(&line[..station.len()], station.as_bytes());
+        assert_eq!(line[station.len()], delimiter);
+    }
+});
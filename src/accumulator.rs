@@ -0,0 +1,56 @@
+use crate::{parse::first_measurement, Measurement, Options};
+
+/// A statistic gathered over every measurement of a station, which the engine that reads and
+/// splits up the input is generic over. [`Measurement`] is the usual one, and what everything but
+/// [`aggregate_with`](crate::aggregate_with) gathers, but anything that can record measurements
+/// and be merged with another of its kind can be plugged in there instead, such as a count alone.
+///
+/// # Units
+///
+/// Measurements are given in **thousandths** of a degree, not tenths, so `12.3` is recorded as
+/// `12_300` and `-0.05` as `-50`. The input can have up to three decimal places, which tenths
+/// would have to round away before an accumulator ever saw them, and this is what the parser
+/// produces and [`Measurement::record`] takes. Divide by 100, rounding as you see fit, to get
+/// tenths.
+///
+/// Each thread gathers its own statistics for its share of the input, so merging has to give the
+/// same result as if every measurement had been recorded by one of them.
+pub trait Accumulator: Send + Sized {
+    /// Starts the statistic of a station with its first measurement, in thousandths of a degree,
+    /// which is on the line at byte `offset` of the input.
+    fn first(thousandths: i32, offset: usize, options: &Options) -> Self;
+
+    /// Records another measurement of the station, in thousandths of a degree.
+    fn record(&mut self, thousandths: i32);
+
+    /// Combines `other`, gathered over another part of the input, into this one.
+    fn merge(&mut self, other: &Self);
+
+    /// Counts everything recorded so far `factor` times over, for when only every `factor`th line
+    /// was [sampled](Options::sample). Nothing is scaled unless this is implemented, which leaves
+    /// the statistic covering the sampled lines alone.
+    fn scale(&mut self, _factor: u64) {}
+}
+
+// Goes straight through to the measurement's own methods, which are what the engine was built on
+impl Accumulator for Measurement {
+    #[inline(always)]
+    fn first(thousandths: i32, offset: usize, options: &Options) -> Self {
+        first_measurement(thousandths, offset, options)
+    }
+
+    #[inline(always)]
+    fn record(&mut self, thousandths: i32) {
+        Measurement::record(self, thousandths)
+    }
+
+    #[inline(always)]
+    fn merge(&mut self, other: &Self) {
+        self.aggregate(other)
+    }
+
+    #[inline(always)]
+    fn scale(&mut self, factor: u64) {
+        Measurement::scale(self, factor)
+    }
+}
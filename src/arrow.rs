@@ -0,0 +1,70 @@
+use std::{
+    io::{self, Write},
+    sync::Arc,
+};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+
+use crate::{Measurement, OutputOptions};
+
+/*
+ * The results are written as a Parquet file holding a single Arrow table, with a row per station
+ * and these columns:
+ *
+ *   station  utf8     never null
+ *   min      float64  null for a station without any measurements, which --only can ask for
+ *   max      float64  likewise
+ *   mean     float64  likewise
+ *   count    uint64   never null
+ *
+ * Whatever other statistics were asked for, these are the only columns there are, so that a
+ * pipeline reading the file can rely on its schema. The min, max and mean are rounded to the
+ * precision just like in every other format.
+ *
+ * Parquet keeps the metadata describing the file at its end, after every row, so the file is put
+ * together in memory and then written out in one go. With a row per station that's never much.
+ */
+fn schema() -> Schema {
+    Schema::new(vec![
+        Field::new("station", DataType::Utf8, false),
+        Field::new("min", DataType::Float64, true),
+        Field::new("max", DataType::Float64, true),
+        Field::new("mean", DataType::Float64, true),
+        Field::new("count", DataType::UInt64, false),
+    ])
+}
+
+pub(crate) fn write_parquet<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    let (mut stations, mut mins, mut maxes, mut means, mut counts) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for (city, measurement) in results {
+        stations.push(city.as_str());
+        mins.push(measurement.min_fixed(options.precision).map(f64::from));
+        maxes.push(measurement.max_fixed(options.precision).map(f64::from));
+        means.push(measurement.mean_fixed(options.precision).map(f64::from));
+        counts.push(measurement.count());
+    }
+
+    let schema = Arc::new(schema());
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(stations)),
+        Arc::new(Float64Array::from(mins)),
+        Arc::new(Float64Array::from(maxes)),
+        Arc::new(Float64Array::from(means)),
+        Arc::new(UInt64Array::from(counts)),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns).map_err(io::Error::other)?;
+
+    let mut file = Vec::new();
+    let mut table = ArrowWriter::try_new(&mut file, schema, None).map_err(io::Error::other)?;
+    table.write(&batch).map_err(io::Error::other)?;
+    table.close().map_err(io::Error::other)?;
+
+    writer.write_all(&file)
+}
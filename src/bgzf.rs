@@ -0,0 +1,281 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    path::Path,
+};
+
+use flate2::read::MultiGzDecoder;
+
+use crate::{
+    chunk::{memory_map, process_mapped_lines, share},
+    hash::{station_map, StationMap},
+    search::{find, rfind},
+    Measurement, Options,
+};
+
+/*
+ * A gzip file can be made up of many members one after another, each compressed on its own, which
+ * is how bgzip writes them: blocks of at most 64KB of text each. Alongside it, bgzip can write an
+ * index of where each block starts, named after the file with a ".gzi" suffix, which is all we
+ * need to decompress the blocks in parallel. Everything is little endian:
+ *
+ *   count      u64       how many blocks are listed, which leaves out the first
+ *   blocks     16 bytes  each where it starts in the compressed file as a u64, then where its
+ *                        text starts once decompressed as a u64, in order
+ *
+ * The first block starts at the start of both, so it isn't listed. Nothing here depends on the
+ * extra fields bgzip gives each member, so any gzip file of many members can be indexed this way.
+ *
+ * The blocks are shared out between the threads in runs, each decompressed into a buffer of its
+ * own. Blocks don't end on lines, so every run's whole lines are aggregated where they are, and
+ * what comes before its first newline and after its last is kept aside. Once every run is done,
+ * the end of each run is put back together with the start of the next, which makes the lines
+ * that straddled them whole again.
+ */
+pub(crate) struct Index {
+    // Where each block starts, compressed and decompressed, starting with the first at 0
+    blocks: Vec<(usize, usize)>,
+}
+
+// Enough that a run's blocks are far more work than setting it up, without holding much in memory
+const MAX_RUN_LEN: usize = 16 << 20;
+
+impl Index {
+    // The index alongside the file at `path`, if there is one
+    pub(crate) fn find(path: &Path) -> io::Result<Option<Self>> {
+        let mut index_path = OsString::from(path);
+        index_path.push(".gzi");
+        let index_path = Path::new(&index_path);
+
+        let bytes = match std::fs::read(index_path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Self::parse(&bytes).map(Some).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a valid gzip index", index_path.display()),
+            )
+        })
+    }
+
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        let (count, entries) = bytes.split_first_chunk::<8>()?;
+        let count = usize::try_from(u64::from_le_bytes(*count)).ok()?;
+        if entries.len() != count.checked_mul(16)? {
+            return None;
+        }
+
+        let mut blocks = Vec::with_capacity(count + 1);
+        blocks.push((0, 0));
+        for entry in entries.chunks_exact(16) {
+            let compressed = u64::from_le_bytes(entry[..8].try_into().unwrap()) as usize;
+            let text = u64::from_le_bytes(entry[8..].try_into().unwrap()) as usize;
+
+            // Every block takes up some of the file, though it can decompress to nothing
+            let &(last_compressed, last_text) = blocks.last().unwrap();
+            if compressed <= last_compressed || text < last_text {
+                return None;
+            }
+            blocks.push((compressed, text));
+        }
+
+        Some(Self { blocks })
+    }
+
+    // Groups the blocks into runs of consecutive ones, for the threads to share out
+    fn runs(&self, options: &Options) -> Vec<Range<usize>> {
+        let total = self.blocks.last().unwrap().1;
+        let wanted = options
+            .parallelism
+            .saturating_mul(options.chunks_per_thread.max(1));
+        let run_len = (total / wanted.max(1)).clamp(1, MAX_RUN_LEN);
+
+        let mut runs = Vec::new();
+        let mut start = 0;
+        for (i, &(_, text)) in self.blocks.iter().enumerate().skip(1) {
+            if text - self.blocks[start].1 >= run_len {
+                runs.push(start..i);
+                start = i;
+            }
+        }
+        runs.push(start..self.blocks.len());
+
+        runs
+    }
+}
+
+// What a run of blocks couldn't aggregate by itself, for putting back together with its neighbours
+struct Fragment {
+    run: usize,
+    // Everything up to and including its first newline, or all of it if there isn't one
+    head: Vec<u8>,
+    // Everything after its last newline and where that starts, if there's a newline at all
+    tail: Option<(Vec<u8>, usize)>,
+}
+
+// Aggregates a gzip file of many members, decompressing them in parallel by the index of them
+pub(crate) fn aggregate(
+    file: &File,
+    index: &Index,
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    let mmap = memory_map(file, options)?;
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    if index.blocks.last().unwrap().0 >= mmap.len().max(1) {
+        return Err(invalid(
+            "the gzip index lists blocks past the end of the file".to_owned(),
+        ));
+    }
+
+    // Progress is counted in compressed bytes as each run is finished, as that's the size we know
+    let progress = options.progress.clone();
+    if let Some(progress) = &progress {
+        progress.add_total(mmap.len() as u64);
+    }
+    let options = &Options {
+        progress: None,
+        ..options.clone()
+    };
+
+    let runs = index.runs(options);
+    let compressed = |block: usize| {
+        index.blocks[block].0..index.blocks.get(block + 1).map_or(mmap.len(), |next| next.0)
+    };
+
+    let mut measurements = station_map::<String, Measurement>(options.stations_hint);
+    let mut fragments = Vec::with_capacity(runs.len());
+    share(
+        runs.len(),
+        options,
+        || (station_map::<String, Measurement>(options.stations_hint), Vec::new()),
+        |(thread_measurements, thread_fragments), i| {
+            let blocks = runs[i].clone();
+            let bytes = compressed(blocks.start).start..compressed(blocks.end - 1).end;
+            let offset = index.blocks[blocks.start].1;
+
+            let mut text = Vec::new();
+            MultiGzDecoder::new(&mmap[bytes.clone()])
+                .read_to_end(&mut text)
+                .map_err(|e| {
+                    invalid(format!(
+                        "failed to decompress the blocks at bytes {}..{}, which the gzip index may not match: {}",
+                        bytes.start, bytes.end, e
+                    ))
+                })?;
+
+            // The index says how long every run but the last decompresses to
+            if let Some(&(_, end)) = index.blocks.get(blocks.end) {
+                if text.len() != end - offset {
+                    return Err(invalid(format!(
+                        "the blocks at bytes {}..{} decompress to {} bytes, but the gzip index says {}",
+                        bytes.start,
+                        bytes.end,
+                        text.len(),
+                        end - offset
+                    )));
+                }
+            }
+
+            let fragment = match (find(&text, b'\n'), rfind(&text, b'\n')) {
+                (Some(first), Some(last)) => {
+                    let lines = &text[first + 1..last + 1];
+                    merge_into(
+                        thread_measurements,
+                        process_mapped_lines(lines, offset + first + 1, options)?,
+                    );
+
+                    Fragment {
+                        run: i,
+                        head: text[..first + 1].to_vec(),
+                        tail: Some((text[last + 1..].to_vec(), offset + last + 1)),
+                    }
+                }
+                _ => Fragment {
+                    run: i,
+                    head: text,
+                    tail: None,
+                },
+            };
+            thread_fragments.push(fragment);
+
+            if let Some(progress) = &progress {
+                progress.advance(bytes.len() as u64);
+            }
+
+            Ok(())
+        },
+        |(thread_measurements, thread_fragments)| {
+            merge_into(&mut measurements, thread_measurements);
+            fragments.extend(thread_fragments);
+        },
+    )?;
+
+    /*
+     * Each stitched line is only a line long, so its timings aren't worth printing or collecting.
+     * Every one of them is aggregated, rather than one in every so many, so each only stands for
+     * itself and isn't scaled up by the sample rate, which would count it as many times over.
+     */
+    let options = &Options {
+        verbose: false,
+        chunk_stats: None,
+        sample: None,
+        ..options.clone()
+    };
+    fragments.sort_unstable_by_key(|fragment| fragment.run);
+
+    let mut line = Vec::new();
+    let mut line_offset = 0;
+    // Stopping early can leave runs out, and a line that straddles a gap can't be put together
+    let mut whole = true;
+    let mut next = 0;
+    for fragment in fragments {
+        if fragment.run != next {
+            whole = false;
+        }
+        next = fragment.run + 1;
+
+        line.extend_from_slice(&fragment.head);
+        let Some((tail, tail_offset)) = fragment.tail else {
+            continue;
+        };
+
+        if whole {
+            merge_into(
+                &mut measurements,
+                process_mapped_lines(&line, line_offset, options)?,
+            );
+        }
+        (line, line_offset, whole) = (tail, tail_offset, true);
+    }
+
+    // The last line doesn't have to end with a newline
+    if whole && !line.is_empty() {
+        merge_into(
+            &mut measurements,
+            process_mapped_lines(&line, line_offset, options)?,
+        );
+    }
+
+    Ok(measurements.into_iter().collect())
+}
+
+fn merge_into(
+    measurements: &mut StationMap<String, Measurement>,
+    other: impl IntoIterator<Item = (impl AsRef<str> + Into<String>, Measurement)>,
+) {
+    for (city, measurement) in other {
+        match measurements.get_mut(city.as_ref()) {
+            Some(item) => item.aggregate(&measurement),
+            None => {
+                measurements.insert(city.into(), measurement);
+            }
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+
+use crate::{
+    chunk::share,
+    hash::station_map,
+    parse::{first_measurement, malformed, parse},
+    Measurement, Options,
+};
+
+/*
+ * A packed binary format for measurements, for files that get aggregated again and again. Every
+ * record is the same size and needs no parsing, so a file can be split anywhere on a record
+ * boundary and each record is just two loads. Everything is little endian:
+ *
+ *   magic     8 bytes    "1BRCBIN\0"
+ *   version   u32        bumped whenever the layout changes
+ *   records   4 bytes    each the station's id as a u16, then the measurement in tenths as an i16
+ *   stations             each name as a u32 length followed by its bytes, in order of id
+ *   footer    12 bytes   where the stations start as a u64, then how many there are as a u32
+ *
+ * The station table comes after the records rather than before them, so that a file can be
+ * converted in a single pass, even straight to a stream, without knowing every station up front.
+ *
+ * Storing tenths keeps records small, but means that a measurement with more than one decimal
+ * place, or outside of -3276.8..=3276.7, can't be converted.
+ */
+const MAGIC: &[u8; 8] = b"1BRCBIN\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 4;
+const RECORD_LEN: usize = 4;
+const FOOTER_LEN: usize = 12;
+
+// Below this many records it costs more to hand a chunk to a thread than it does to aggregate them
+const MIN_RECORDS_PER_CHUNK: usize = 16 * 1024;
+
+#[inline(always)]
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Converts the measurements read from `reader` into the packed binary format, which
+/// [`aggregate_file_with`](crate::aggregate_file_with) detects and reads far faster than text.
+/// Every measurement has to have at most one decimal place, and there can be at most 65,536
+/// stations. Malformed lines are dealt with according to [`Options::on_error`].
+pub fn convert(
+    mut reader: impl BufRead,
+    writer: &mut impl Write,
+    options: &Options,
+) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut ids = station_map::<String, u16>(options.stations_hint);
+    let mut names = Vec::new();
+    let mut line = String::new();
+    let mut offset = 0;
+    let mut written = 0;
+
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    loop {
+        let read = reader.read_line(&mut line).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => {
+                invalid(format!("invalid UTF-8 in the line at byte {}", offset))
+            }
+            _ => e,
+        })?;
+        if read == 0 {
+            break;
+        }
+
+        let trimmed = line.trim_end_matches('\n');
+        match parse(trimmed, options) {
+            Some((city, measurement)) => {
+                let tenths = match i16::try_from(measurement / 100) {
+                    Ok(tenths) if measurement % 100 == 0 => tenths,
+                    _ => {
+                        return Err(invalid(format!(
+                            "the measurement at byte {} can't be stored in tenths: {:?}",
+                            offset, trimmed
+                        )))
+                    }
+                };
+
+                let id = match ids.get(city) {
+                    Some(&id) => id,
+                    None => {
+                        let id = u16::try_from(names.len()).map_err(|_| {
+                            invalid(format!(
+                                "more than {} stations can't be converted",
+                                u16::MAX as usize + 1
+                            ))
+                        })?;
+                        ids.insert(city.to_owned(), id);
+                        names.push(city.to_owned());
+
+                        id
+                    }
+                };
+
+                writer.write_all(&id.to_le_bytes())?;
+                writer.write_all(&tenths.to_le_bytes())?;
+                written += 1;
+            }
+            None => malformed(trimmed, offset, options)?,
+        }
+
+        offset += read;
+        line.clear();
+    }
+
+    for name in &names {
+        writer.write_all(&(name.len() as u32).to_le_bytes())?;
+        writer.write_all(name.as_bytes())?;
+    }
+
+    let stations = (HEADER_LEN + written * RECORD_LEN) as u64;
+    writer.write_all(&stations.to_le_bytes())?;
+    writer.write_all(&(names.len() as u32).to_le_bytes())
+}
+
+// Splits a file in the binary format into its records and the names of its stations by id
+fn layout(bytes: &[u8]) -> io::Result<(&[u8], Vec<&str>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_owned());
+
+    if bytes.len() < HEADER_LEN + FOOTER_LEN {
+        return Err(invalid("the binary file is too short to have a footer"));
+    }
+
+    let version = u32::from_le_bytes(bytes[MAGIC.len()..HEADER_LEN].try_into().unwrap());
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "binary files of version {} aren't supported, only version {}",
+            version, VERSION
+        )));
+    }
+
+    let footer = &bytes[bytes.len() - FOOTER_LEN..];
+    let start = u64::from_le_bytes(footer[..8].try_into().unwrap()) as usize;
+    let count = u32::from_le_bytes(footer[8..].try_into().unwrap());
+
+    if start < HEADER_LEN
+        || start > bytes.len() - FOOTER_LEN
+        || !(start - HEADER_LEN).is_multiple_of(RECORD_LEN)
+    {
+        return Err(invalid("the binary file's footer doesn't match its length"));
+    }
+
+    let mut table = &bytes[start..bytes.len() - FOOTER_LEN];
+    let mut names = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = table
+            .get(..4)
+            .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+            .and_then(|len| table.get(4..4 + len))
+            .ok_or_else(|| invalid("the binary file's station table is cut short"))?;
+        table = &table[4 + name.len()..];
+
+        names.push(
+            std::str::from_utf8(name)
+                .map_err(|_| invalid("a station name in the binary file isn't valid UTF-8"))?,
+        );
+    }
+
+    Ok((&bytes[HEADER_LEN..start], names))
+}
+
+// Aggregates a file in the binary format, splitting its records between the threads
+pub(crate) fn aggregate<'a>(
+    bytes: &'a [u8],
+    options: &Options,
+) -> io::Result<BTreeMap<&'a str, Measurement>> {
+    let (records, names) = layout(bytes)?;
+
+    // The limit counts the header, but not the station table, which has to be read regardless
+    let records = match options.limit_bytes {
+        Some(limit) => {
+            let whole = limit.saturating_sub(HEADER_LEN) / RECORD_LEN * RECORD_LEN;
+            &records[..whole.min(records.len())]
+        }
+        None => records,
+    };
+    let record_count = records.len() / RECORD_LEN;
+
+    // Only the records are counted as they're aggregated, so the rest of the file is done already
+    if let Some(progress) = &options.progress {
+        progress.add_total(bytes.len() as u64);
+        progress.advance((bytes.len() - records.len()) as u64);
+    }
+
+    let chunk_len = chunk_len(record_count, options);
+    let chunks = records.chunks(chunk_len).collect::<Vec<_>>();
+
+    // Stations are identified by their index, so merging is an element-wise aggregate
+    let merge = |measurements: &mut Vec<Option<Measurement>>, other: Vec<Option<Measurement>>| {
+        for (item, measurement) in measurements.iter_mut().zip(other) {
+            match (item, measurement) {
+                (Some(item), Some(measurement)) => item.aggregate(&measurement),
+                (item @ None, measurement) => *item = measurement,
+                _ => {}
+            }
+        }
+    };
+    let empty = || {
+        let mut measurements = Vec::<Option<Measurement>>::new();
+        measurements.resize_with(names.len(), || None);
+
+        measurements
+    };
+
+    let mut measurements = empty();
+    share(
+        chunks.len(),
+        options,
+        empty,
+        |thread_measurements, i| {
+            let chunk = aggregate_records(chunks[i], i * chunk_len, names.len(), options)?;
+            merge(thread_measurements, chunk);
+
+            Ok(())
+        },
+        |thread_measurements| merge(&mut measurements, thread_measurements),
+    )?;
+
+    // Converted files never name a station twice, but one that does still has every record counted
+    let mut results = BTreeMap::<&str, Measurement>::new();
+    for (name, measurement) in names.into_iter().zip(measurements) {
+        let Some(measurement) = measurement else {
+            continue;
+        };
+
+        match results.get_mut(name) {
+            Some(item) => item.aggregate(&measurement),
+            None => {
+                results.insert(name, measurement);
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+// Calls `f` for every record of a file in the binary format, splitting the records between the
+// threads like `aggregate` does
+pub(crate) fn for_each_record(
+    bytes: &[u8],
+    options: &Options,
+    f: &(impl Fn(&str, i32) + Sync),
+) -> io::Result<()> {
+    let (records, names) = layout(bytes)?;
+    let chunk_len = chunk_len(records.len() / RECORD_LEN, options);
+    let chunks = records.chunks(chunk_len).collect::<Vec<_>>();
+
+    share(
+        chunks.len(),
+        options,
+        || (),
+        |(), i| {
+            for (j, record) in chunks[i].chunks_exact(RECORD_LEN).enumerate() {
+                let id = u16::from_le_bytes([record[0], record[1]]) as usize;
+                let measurement = i16::from_le_bytes([record[2], record[3]]) as i32 * 100;
+
+                let Some(name) = names.get(id) else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "the record at byte {} is for station {}, but there are only {}",
+                            HEADER_LEN + i * chunk_len + j * RECORD_LEN,
+                            id,
+                            names.len()
+                        ),
+                    ));
+                };
+                f(name, measurement);
+            }
+
+            Ok(())
+        },
+        |()| {},
+    )
+}
+
+// How long each chunk of records should be, in bytes, for the threads to share out
+fn chunk_len(record_count: usize, options: &Options) -> usize {
+    let chunks = options
+        .parallelism
+        .saturating_mul(options.chunks_per_thread.max(1))
+        .clamp(1, (record_count / MIN_RECORDS_PER_CHUNK).max(1));
+
+    // Rounded up, so that the records are never split into more chunks than that
+    record_count.div_ceil(chunks).max(1) * RECORD_LEN
+}
+
+// Aggregates a chunk of records, which starts `offset` bytes into the records, by station id
+fn aggregate_records(
+    chunk: &[u8],
+    offset: usize,
+    stations: usize,
+    options: &Options,
+) -> io::Result<Vec<Option<Measurement>>> {
+    let start = Instant::now();
+
+    let mut measurements = Vec::<Option<Measurement>>::new();
+    measurements.resize_with(stations, || None);
+
+    let every = options.sample.unwrap_or(1).max(1);
+    for (i, record) in chunk.chunks_exact(RECORD_LEN).enumerate().step_by(every) {
+        let id = u16::from_le_bytes([record[0], record[1]]) as usize;
+        let measurement = i16::from_le_bytes([record[2], record[3]]) as i32 * 100;
+
+        match measurements.get_mut(id) {
+            Some(Some(item)) => item.record(measurement),
+            Some(item) => {
+                let offset = HEADER_LEN + offset + i * RECORD_LEN;
+                *item = Some(first_measurement(measurement, offset, options))
+            }
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "the record at byte {} is for station {}, but there are only {}",
+                        HEADER_LEN + offset + i * RECORD_LEN,
+                        id,
+                        stations
+                    ),
+                ))
+            }
+        }
+    }
+
+    if every > 1 {
+        for measurement in measurements.iter_mut().flatten() {
+            measurement.scale(every as u64);
+        }
+    }
+
+    if let Some(progress) = &options.progress {
+        progress.advance(chunk.len() as u64);
+    }
+
+    if let Some(stats) = &options.chunk_stats {
+        let records = (chunk.len() / RECORD_LEN).div_ceil(every);
+        stats.record(HEADER_LEN + offset, chunk.len(), records as u64, start.elapsed());
+    }
+
+    if options.verbose {
+        eprintln!(
+            "Processed {} records in {:?}",
+            chunk.len() / RECORD_LEN,
+            start.elapsed()
+        );
+    }
+
+    Ok(measurements)
+}
@@ -0,0 +1,182 @@
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{checkpoint::read_bytes, chunk::shared_chunks, Options};
+
+const MAGIC: &[u8; 8] = b"1BRCCHNK";
+// Bumped whenever the layout changes, so that an old cache is found again rather than misread
+const VERSION: u32 = 2;
+
+/*
+ * Where a file was split into chunks, kept in a sidecar file next to it named after it with a
+ * ".chunks" suffix, so that a run against the same file can skip finding them again. Everything
+ * is little endian:
+ *
+ *   magic      8 bytes   "1BRCCHNK"
+ *   version    u32       bumped whenever the layout changes
+ *   length     u64       of the file the chunks were found in
+ *   modified   u64, u32  when that file was last modified, as seconds and nanoseconds since 1970
+ *   covered    u64       how many bytes of it were split, which is less with a limit
+ *   requested  u64       how many chunks were asked for
+ *   stripe     u64       the stripe size the chunks were aligned to, or 0 if they weren't
+ *   count      u64       how many chunks there are, which can be fewer than were asked for
+ *   ends       u64 each  where each chunk ends, which is where the next one starts
+ *
+ * If the file's length or time of modification have changed since, or the chunks were asked for
+ * differently or aligned to another stripe, the cache is ignored and written again. The chunks
+ * are checked to end just after a newline before they're used, so a cache that's been tampered
+ * with can't split a line.
+ */
+pub(crate) fn chunks(path: &Path, bytes: &[u8], options: &Options) -> Vec<Range<usize>> {
+    let requested = options
+        .parallelism
+        .saturating_mul(options.chunks_per_thread.max(1));
+
+    let stripe = options.stripe_size.unwrap_or(0);
+    let key = match key(path, bytes.len(), requested, stripe) {
+        Ok(key) => key,
+        // Without knowing when the file was modified, there's no telling if a cache is still valid
+        Err(_) => return shared_chunks(bytes, options),
+    };
+
+    let cache = cache_path(path);
+    if let Some(chunks) = load(&cache, &key).filter(|chunks| are_lines(chunks, bytes)) {
+        if options.verbose {
+            eprintln!("Reusing {} chunks from {}", chunks.len(), cache.display());
+        }
+
+        return chunks;
+    }
+
+    let chunks = shared_chunks(bytes, options);
+
+    // A single chunk is found without any searching, so it's not worth saving
+    if chunks.len() > 1 {
+        if let Err(e) = save(&cache, &key, &chunks) {
+            if options.verbose {
+                eprintln!("Couldn't cache the chunks to {}: {}", cache.display(), e);
+            }
+        }
+    }
+
+    chunks
+}
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut cache = OsString::from(path);
+    cache.push(".chunks");
+
+    PathBuf::from(cache)
+}
+
+// Everything a cache has to match to still be valid, in the order it's laid out
+#[derive(PartialEq, Eq)]
+struct Key {
+    length: u64,
+    seconds: u64,
+    nanoseconds: u32,
+    covered: u64,
+    requested: u64,
+    stripe: u64,
+}
+
+fn key(path: &Path, covered: usize, requested: usize, stripe: usize) -> io::Result<Key> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(io::Error::other)?;
+
+    Ok(Key {
+        length: metadata.len(),
+        seconds: modified.as_secs(),
+        nanoseconds: modified.subsec_nanos(),
+        covered: covered as u64,
+        requested: requested as u64,
+        stripe: stripe as u64,
+    })
+}
+
+// The chunks in the cache at `path`, if there is one and it matches `key`
+fn load(path: &Path, key: &Key) -> Option<Vec<Range<usize>>> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+
+    if read_bytes::<8>(&mut reader).ok()? != *MAGIC
+        || u32::from_le_bytes(read_bytes(&mut reader).ok()?) != VERSION
+    {
+        return None;
+    }
+
+    let found = Key {
+        length: read_u64(&mut reader)?,
+        seconds: read_u64(&mut reader)?,
+        nanoseconds: u32::from_le_bytes(read_bytes(&mut reader).ok()?),
+        covered: read_u64(&mut reader)?,
+        requested: read_u64(&mut reader)?,
+        stripe: read_u64(&mut reader)?,
+    };
+    if found != *key {
+        return None;
+    }
+
+    // There are never more chunks than were asked for, so a corrupt count can't have us allocate
+    let count = read_u64(&mut reader)?;
+    if count > key.requested {
+        return None;
+    }
+
+    let mut chunks = Vec::with_capacity(count as usize);
+    let mut start = 0;
+    for _ in 0..count {
+        let end = read_u64(&mut reader)? as usize;
+        chunks.push(start..end);
+        start = end;
+    }
+
+    Some(chunks)
+}
+
+fn read_u64(reader: &mut impl Read) -> Option<u64> {
+    read_bytes(reader).ok().map(u64::from_le_bytes)
+}
+
+// Whether the chunks tile `bytes` in order, each but the last ending just after a newline
+fn are_lines(chunks: &[Range<usize>], bytes: &[u8]) -> bool {
+    let ordered = chunks.iter().all(|chunk| chunk.start < chunk.end);
+    let whole = chunks.last().map_or(bytes.is_empty(), |last| last.end == bytes.len());
+
+    ordered
+        && whole
+        && chunks[..chunks.len().saturating_sub(1)]
+            .iter()
+            .all(|chunk| bytes[chunk.end - 1] == b'\n')
+}
+
+fn save(path: &Path, key: &Key, chunks: &[Range<usize>]) -> io::Result<()> {
+    // Like a checkpoint, it's written next to the cache and renamed over it, so that a run that's
+    // cut short can't leave half of one behind
+    let mut temporary = OsString::from(path);
+    temporary.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&key.length.to_le_bytes())?;
+    writer.write_all(&key.seconds.to_le_bytes())?;
+    writer.write_all(&key.nanoseconds.to_le_bytes())?;
+    for value in [key.covered, key.requested, key.stripe, chunks.len() as u64] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    for chunk in chunks {
+        writer.write_all(&(chunk.end as u64).to_le_bytes())?;
+    }
+    writer.into_inner().map_err(|e| e.into_error())?;
+
+    std::fs::rename(&temporary, path)
+}
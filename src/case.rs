@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use crate::{summarize, Measurement};
+
+/// Which spelling a station is written under once stations that only differ in case have been
+/// combined by [`fold_case`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Casing {
+    /// The name in lowercase, as given by [`str::to_lowercase`].
+    #[default]
+    Lower,
+    /// Whichever spelling has the most measurements, or the first in byte order of those with
+    /// the most.
+    Common,
+}
+
+/*
+ * Folding the case of every line as it's parsed would mean lowercasing, and so copying, every
+ * station name on the hot path. Instead the stations are aggregated under their names as they
+ * are, and only the few that differ in case are combined at the end. Aggregating is commutative,
+ * so the results are the same as if they'd been combined all along.
+ *
+ * "First seen" isn't offered as a casing, as the chunks of a file are aggregated in parallel and
+ * nothing records which spelling came first.
+ */
+
+/// Combines stations whose names only differ in case, such as `Tokyo` and `tokyo`, into one.
+pub fn fold_case(
+    results: BTreeMap<String, Measurement>,
+    casing: Casing,
+) -> BTreeMap<String, Measurement> {
+    let mut groups = BTreeMap::<String, BTreeMap<String, Measurement>>::new();
+    for (city, measurement) in results {
+        groups
+            .entry(city.to_lowercase())
+            .or_default()
+            .insert(city, measurement);
+    }
+
+    let mut folded = BTreeMap::new();
+    for (lowercase, spellings) in groups {
+        let name = match casing {
+            Casing::Lower => lowercase,
+            Casing::Common => {
+                let mut common = spellings.iter().next().unwrap();
+                for spelling in &spellings {
+                    // Strictly greater, so that ties go to the first in byte order
+                    if spelling.1.count() > common.1.count() {
+                        common = spelling;
+                    }
+                }
+
+                common.0.clone()
+            }
+        };
+
+        folded.insert(name, summarize(spellings.values()));
+    }
+
+    folded
+}
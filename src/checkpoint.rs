@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use crate::{chunk::aggregate_mapped, search::find, Measurement, Options};
+
+// Enough to keep every thread busy for a while, without losing much if a run is cut short
+const DEFAULT_SEGMENT_SIZE: usize = 1 << 30;
+
+const MAGIC: &[u8; 8] = b"1BRCCKPT";
+// Bumped whenever the layout changes, so that an old checkpoint is refused rather than misread
+const VERSION: u32 = 1;
+
+/// Where and how often to save the aggregation of a file so far, so that a run that's cut short
+/// can pick up where it left off.
+///
+/// The file is aggregated in segments of whole lines, one after another, each split across the
+/// threads as usual. A checkpoint is only ever saved between segments, once everything before it
+/// has been merged into the results, so it always covers an exact prefix of the file ending with
+/// a newline. Whatever was aggregated of the segment a run was cut short in is lost, and that
+/// segment is aggregated again from its start on resuming. No line is ever missed or counted
+/// twice.
+///
+/// A checkpoint is only checked against the length of the file it was taken of, so the file must
+/// not be modified in between.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The file to save checkpoints to. If it already exists the aggregation resumes from it, and
+    /// it's removed once the aggregation is finished.
+    pub path: PathBuf,
+    /// How long to wait between checkpoints.
+    pub interval: Duration,
+    /// Roughly how many bytes to aggregate between chances to save a checkpoint.
+    pub segment_size: usize,
+}
+
+impl Checkpoint {
+    /// Saves checkpoints to `path` every `interval`, with segments of 1GiB.
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+        }
+    }
+}
+
+// Aggregates the mapped file segment by segment, resuming from and saving checkpoints as it goes
+pub(crate) fn aggregate(
+    bytes: &[u8],
+    checkpoint: &Checkpoint,
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    // The skipped lines are shared with everything else using the options, so only the ones from
+    // this file are saved
+    let skipped_before = options.skipped.load(Ordering::Relaxed);
+
+    let (mut offset, mut results) = match load(checkpoint, bytes.len(), options)? {
+        Some(Saved {
+            offset,
+            skipped,
+            results,
+        }) => {
+            options.skipped.fetch_add(skipped, Ordering::Relaxed);
+            if let Some(progress) = &options.progress {
+                progress.advance(offset as u64);
+            }
+            if options.verbose {
+                eprintln!(
+                    "Resuming from byte {} with {}",
+                    offset,
+                    checkpoint.path.display()
+                );
+            }
+
+            (offset, results)
+        }
+        None => (0, BTreeMap::new()),
+    };
+    let mut saved = Instant::now();
+
+    while offset < bytes.len() {
+        // Segments end just after a newline like chunks do, so that a checkpoint never splits a line
+        let end = bytes
+            .get(offset.saturating_add(checkpoint.segment_size)..)
+            .and_then(|rest| find(rest, b'\n'))
+            .map_or(bytes.len(), |newline| {
+                offset + checkpoint.segment_size + newline + 1
+            });
+
+        let skipped_so_far = options.skipped.load(Ordering::Relaxed);
+        let segment = aggregate_mapped(&bytes[offset..end], offset, options)?;
+
+        /*
+         * A run that's stopped early is cut short partway through the segment, so it's saved as of
+         * the segment's start. The partial segment is still merged into the results that are
+         * returned, but is aggregated again on resuming, and the checkpoint is kept for that.
+         */
+        let stopped = options.stopped();
+        if stopped && offset > 0 {
+            let skipped = skipped_so_far - skipped_before;
+            save(checkpoint, bytes.len(), offset, skipped, options, &results)?;
+        }
+
+        for (city, measurement) in segment {
+            match results.get_mut(city) {
+                Some(item) => item.aggregate(&measurement),
+                None => {
+                    results.insert(city.to_owned(), measurement);
+                }
+            }
+        }
+        offset = end;
+
+        if stopped {
+            return Ok(results);
+        }
+
+        if offset < bytes.len() && saved.elapsed() >= checkpoint.interval {
+            let skipped = options.skipped.load(Ordering::Relaxed) - skipped_before;
+            save(checkpoint, bytes.len(), offset, skipped, options, &results)?;
+            saved = Instant::now();
+        }
+    }
+
+    // Everything has been aggregated, so there's nothing left to resume
+    match std::fs::remove_file(&checkpoint.path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(results),
+    }
+}
+
+/*
+ * A checkpoint is laid out as the magic bytes and version, followed by the length of the file it
+ * was taken of, the offset reached, how many lines were skipped, and how many stations there are,
+ * each a little endian u64. Then the delimiter, and whether there are histograms, as a byte each.
+ * Every station follows as the length of its name, the name, and then its measurement.
+ */
+fn save(
+    checkpoint: &Checkpoint,
+    len: usize,
+    offset: usize,
+    skipped: u64,
+    options: &Options,
+    results: &BTreeMap<String, Measurement>,
+) -> io::Result<()> {
+    // Writing next to the checkpoint and renaming over it means a run cut short partway through
+    // saving still leaves the previous checkpoint intact
+    let mut temporary = OsString::from(&checkpoint.path);
+    temporary.push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&temporary)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    for value in [len as u64, offset as u64, skipped, results.len() as u64] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.write_all(&[options.delimiter, options.histogram as u8])?;
+    write_stations(&mut writer, results)?;
+
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    std::fs::rename(&temporary, &checkpoint.path)
+}
+
+// What a checkpoint saves of an aggregation in progress
+struct Saved {
+    // Where to resume from, which is always just after a newline
+    offset: usize,
+    skipped: u64,
+    results: BTreeMap<String, Measurement>,
+}
+
+// The aggregation saved in the checkpoint, if there is one
+fn load(checkpoint: &Checkpoint, len: usize, options: &Options) -> io::Result<Option<Saved>> {
+    let file = match File::open(&checkpoint.path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let mut reader = BufReader::new(file);
+
+    let invalid = |message: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", checkpoint.path.display(), message),
+        )
+    };
+
+    if read_bytes(&mut reader)? != *MAGIC || u32::from_le_bytes(read_bytes(&mut reader)?) != VERSION
+    {
+        return Err(invalid(
+            "not a checkpoint, or one from another version".to_owned(),
+        ));
+    }
+
+    let taken_of = u64::from_le_bytes(read_bytes(&mut reader)?);
+    let offset = u64::from_le_bytes(read_bytes(&mut reader)?);
+    let skipped = u64::from_le_bytes(read_bytes(&mut reader)?);
+    let stations = u64::from_le_bytes(read_bytes(&mut reader)?);
+    let [delimiter, histogram] = read_bytes(&mut reader)?;
+
+    if taken_of != len as u64 || offset > taken_of {
+        return Err(invalid(format!(
+            "the checkpoint was taken of a file of {} bytes, but this one is {} bytes",
+            taken_of, len
+        )));
+    }
+
+    if delimiter != options.delimiter || (histogram == 1) != options.histogram {
+        return Err(invalid(
+            "the checkpoint was taken with a different delimiter or statistics".to_owned(),
+        ));
+    }
+
+    let results =
+        read_stations(&mut reader, stations, histogram == 1).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => invalid(e.to_string()),
+            _ => e,
+        })?;
+
+    Ok(Some(Saved {
+        offset: offset as usize,
+        skipped,
+        results,
+    }))
+}
+
+// Writes every station as the length of its name as a u64, the name, and then its measurement, as
+// both checkpoints and partials lay them out
+pub(crate) fn write_stations(
+    writer: &mut impl Write,
+    results: &BTreeMap<String, Measurement>,
+) -> io::Result<()> {
+    for (city, measurement) in results {
+        writer.write_all(&(city.len() as u64).to_le_bytes())?;
+        writer.write_all(city.as_bytes())?;
+        measurement.write_to(writer)?;
+    }
+
+    Ok(())
+}
+
+/*
+ * Reads back the given number of stations written by `write_stations`. Whether they should have
+ * histograms was saved alongside them, and a measurement that disagrees would have statistics
+ * counted over only some of its measurements, so it's refused rather than read.
+ */
+pub(crate) fn read_stations(
+    reader: &mut impl Read,
+    stations: u64,
+    histograms: bool,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let mut results = BTreeMap::new();
+    for _ in 0..stations {
+        let name_len = u64::from_le_bytes(read_bytes(reader)?);
+
+        // Reading through `take` means a corrupt length can't have us allocate more than is there
+        let mut name = Vec::new();
+        reader.take(name_len).read_to_end(&mut name)?;
+        if name.len() as u64 != name_len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let name =
+            String::from_utf8(name).map_err(|_| invalid("a station name isn't valid UTF-8"))?;
+        let measurement = Measurement::read_from(reader)?;
+        if measurement.has_histogram() != histograms {
+            return Err(invalid("a station's histogram doesn't match the rest"));
+        }
+        results.insert(name, measurement);
+    }
+
+    Ok(results)
+}
+
+#[inline(always)]
+pub(crate) fn read_bytes<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
@@ -0,0 +1,498 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read},
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    time::Instant,
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    binary,
+    intern::{interned, Interned},
+    parse::process_lines,
+    search::{find, rfind},
+    Accumulator, Measurement, Options,
+};
+
+#[inline(always)]
+pub(crate) fn memory_map(file: &File, options: &Options) -> io::Result<Mmap> {
+    /*
+     * Mapping the file lets every thread read its chunk straight out of the page cache, without
+     * copying it into a buffer of its own first or reopening the file.
+     *
+     * This is only sound as long as nothing modifies the file while it's mapped, which we have to
+     * take on trust.
+     */
+    let mmap = unsafe { Mmap::map(file) }?;
+
+    /*
+     * Scanning gigabytes 4KB at a time means a TLB miss every few thousand lines, which huge pages
+     * cut down on. Only Linux can be asked for them, and only some filesystems can back a file with
+     * them, so this is a hint the kernel is free to ignore. Failing to give it isn't an error.
+     */
+    #[cfg(target_os = "linux")]
+    if options.huge_pages {
+        if let Err(e) = mmap.advise(memmap2::Advice::HugePage) {
+            if options.verbose {
+                eprintln!("Couldn't ask for huge pages: {}", e);
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = options;
+
+    /*
+     * A single scan reads every page once, in order, which the kernel can only guess at by
+     * default. Every thread scans its own chunk from start to end, and as the chunks tile the
+     * whole map, advising the map once covers each of them.
+     */
+    #[cfg(unix)]
+    {
+        use crate::Readahead;
+
+        let advice: &[_] = match options.readahead {
+            Readahead::Default => &[],
+            Readahead::Sequential => &[memmap2::Advice::Sequential],
+            Readahead::WillNeed => &[memmap2::Advice::Sequential, memmap2::Advice::WillNeed],
+        };
+        for &advice in advice {
+            if let Err(e) = mmap.advise(advice) {
+                if options.verbose {
+                    eprintln!("Couldn't advise {:?} readahead: {}", advice, e);
+                }
+            }
+        }
+    }
+
+    Ok(mmap)
+}
+
+// Below this it costs more to hand a chunk to a thread and merge its results than to process it
+const MIN_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Splits `bytes` into at most `available_parallelism` chunks of whole lines, of roughly equal
+/// size. The chunks are half-open ranges that cover every byte exactly once and in order, each
+/// ending just after a newline, except for the last which runs to the end.
+#[inline(always)]
+pub fn chunks(bytes: &[u8], available_parallelism: usize) -> Vec<Range<usize>> {
+    striped_chunks(bytes, available_parallelism, 1)
+}
+
+/// Like [`chunks`], but with every chunk after the first starting at the first line to begin past
+/// a multiple of `stripe` bytes, as [`Options::stripe_size`] has the chunks of a file split. A
+/// `stripe` of 0 is taken as no stripe at all, which splits them just like [`chunks`] does.
+#[inline(always)]
+pub fn striped_chunks(
+    bytes: &[u8],
+    available_parallelism: usize,
+    stripe: usize,
+) -> Vec<Range<usize>> {
+    // Small files are split into fewer chunks than we have threads, down to a single chunk
+    let available_parallelism =
+        available_parallelism.clamp(1, (bytes.len() / MIN_CHUNK_SIZE).max(1));
+    let chunk_size = bytes.len() / available_parallelism;
+    // Every offset is a multiple of 1, which leaves the chunks unaligned
+    let stripe = stripe.max(1);
+
+    let mut beginning = 0;
+    let mut chunks = Vec::with_capacity(available_parallelism);
+
+    /*
+     * We're going to split the file into roughly equal chunks, one per thread.
+     * From the point where each chunk would end we look for the next newline, and end the chunk
+     * just after it. Every chunk but the last therefore ends with a newline, and as each one
+     * starts exactly where the one before it ended, every line, newline included, falls in
+     * exactly one chunk. A line is only ever split by a newline, and there are none inside it.
+     *
+     * The search isn't limited to a fixed window, so a line of any length can straddle the
+     * boundary. If there's no newline left before the end of the file, whatever remains becomes
+     * the last chunk.
+     *
+     * With a stripe, where we start looking is rounded up to the next stripe boundary, so that
+     * each chunk is read from just past one. The chunks come out longer for it, and so there can
+     * be fewer of them, with the last picking up whatever's left.
+     */
+    for _ in 1..available_parallelism {
+        let boundary = (beginning + chunk_size).next_multiple_of(stripe);
+        let Some(newline) = bytes
+            .get(boundary..)
+            .and_then(|rest| find(rest, b'\n'))
+        else {
+            break;
+        };
+        let end = boundary + newline + 1;
+
+        chunks.push(beginning..end);
+        beginning = end;
+    }
+
+    // The last chunk always runs to the end of the file. Otherwise the remainder of the
+    // integer division above would never be read. It's empty when the file ends in a newline
+    // right at a boundary, in which case there's nothing left to process.
+    if beginning < bytes.len() {
+        chunks.push(beginning..bytes.len());
+    }
+
+    /*
+     * Each chunk is turned into a str of its own, which when we're trusted to skip checking is
+     * only sound if it starts at the beginning of a character. Starting just after a newline
+     * guarantees that, as a newline can't be part of any other character. That's true of how the
+     * chunks are found above, but it's cheap enough to make sure of once per chunk.
+     */
+    for chunk in chunks.iter().skip(1) {
+        assert_eq!(
+            bytes[chunk.start - 1],
+            b'\n',
+            "chunk {:?} doesn't start after a newline",
+            chunk
+        );
+    }
+
+    chunks
+}
+
+// Cuts `bytes` down to the whole lines within the first `limit` bytes, if there's a limit. The
+// last line we keep has to end with its newline within the limit, or it would be cut in two.
+#[inline(always)]
+pub(crate) fn limit(bytes: &[u8], limit: Option<usize>) -> &[u8] {
+    match limit {
+        Some(limit) if limit < bytes.len() => {
+            let end = rfind(&bytes[..limit], b'\n').map_or(0, |newline| newline + 1);
+            &bytes[..end]
+        }
+        _ => bytes,
+    }
+}
+
+// Whether the chunk starts at the beginning of a character, and ends with a complete one
+#[inline(always)]
+fn boundaries_are_utf8(chunk: &[u8]) -> bool {
+    let is_continuation = |byte: u8| byte & 0b1100_0000 == 0b1000_0000;
+
+    if chunk.first().copied().is_some_and(is_continuation) {
+        return false;
+    }
+
+    // A character is at most 4 bytes long, so the last one starts somewhere in the last 4 bytes
+    let tail = &chunk[chunk.len().saturating_sub(4)..];
+    match tail.iter().rposition(|&byte| !is_continuation(byte)) {
+        Some(start) => std::str::from_utf8(&tail[start..]).is_ok(),
+        None => tail.is_empty(),
+    }
+}
+
+#[inline(always)]
+pub(crate) fn process_mapped_lines<'a, A: Accumulator>(
+    chunk: &'a [u8],
+    offset: usize,
+    options: &Options,
+) -> io::Result<impl Iterator<Item = (&'a str, A)>> {
+    let chunk = if options.trust_utf8 {
+        // Even when we've been told the input is valid we can cheaply make sure the chunk doesn't
+        // start or end partway through a character
+        if !boundaries_are_utf8(chunk) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "invalid UTF-8 at the edges of bytes {}..{}",
+                    offset,
+                    offset + chunk.len()
+                ),
+            ));
+        }
+
+        // Invalid input is undefined behaviour from here on, which a debug build would rather catch
+        debug_assert!(
+            std::str::from_utf8(chunk).is_ok(),
+            "bytes {}..{} are trusted to be UTF-8, but aren't",
+            offset,
+            offset + chunk.len()
+        );
+
+        // We've been told that the input is all valid utf8, so we can use unsafe to avoid the
+        // overhead of checking.
+        unsafe { std::str::from_utf8_unchecked(chunk) }
+    } else {
+        std::str::from_utf8(chunk).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid UTF-8 at byte {}", offset + e.valid_up_to()),
+            )
+        })?
+    };
+
+    process_lines(chunk, offset, options)
+}
+
+/*
+ * Left to itself the scheduler can move a thread between cores, and on a machine with several
+ * NUMA nodes between nodes, after it has allocated its map of stations. Pinning each thread to a
+ * core keeps it next to that memory. The pages of the file itself belong to the page cache, which
+ * places them wherever they were first read in, so those can still be remote.
+ *
+ * Where the cores can't be listed, such as on platforms core_affinity doesn't support, there's
+ * nothing to pin to and the threads are left alone.
+ */
+pub(crate) fn cores(options: &Options) -> Vec<core_affinity::CoreId> {
+    if !options.numa {
+        return Vec::new();
+    }
+
+    core_affinity::get_core_ids().unwrap_or_default()
+}
+
+// Pins the `i`th thread to a core, going round the cores in turn
+#[inline(always)]
+pub(crate) fn pin(cores: &[core_affinity::CoreId], i: usize) {
+    if !cores.is_empty() {
+        core_affinity::set_for_current(cores[i % cores.len()]);
+    }
+}
+
+/*
+ * Aggregates `bytes`, which start at `offset` in the file, by splitting them into chunks that the
+ * threads share out between themselves.
+ *
+ * Equal chunks of bytes aren't equal amounts of work when the lines in one part of the file are
+ * much longer or shorter than elsewhere, and a single slow chunk would hold up the whole run if
+ * every thread had just the one. So the file is split into `chunks_per_thread` chunks for every
+ * thread instead, and a thread that's been given quick chunks goes on to take more of them,
+ * rather than sitting idle while the others catch up.
+ */
+pub(crate) fn aggregate_mapped<'a, A: Accumulator>(
+    bytes: &'a [u8],
+    offset: usize,
+    options: &Options,
+) -> io::Result<Interned<'a, A>> {
+    aggregate_chunks(bytes, &shared_chunks(bytes, options), offset, options)
+}
+
+/*
+ * For a small file, starting threads, splitting it up and merging their results back together
+ * costs more than it saves, and so does mapping it. Instead it's read in one go and aggregated as
+ * a single chunk on the calling thread.
+ */
+pub(crate) fn aggregate_read(
+    mut file: File,
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+
+    if binary::is_binary(&contents) {
+        return Ok(binary::aggregate(&contents, options)?
+            .into_iter()
+            .map(|(city, measurement)| (city.to_owned(), measurement))
+            .collect());
+    }
+
+    let bytes = limit(&contents, options.limit_bytes);
+    if let Some(progress) = &options.progress {
+        progress.add_total(bytes.len() as u64);
+    }
+
+    let measurements = process_mapped_lines(bytes, 0, options)?
+        .map(|(city, measurement)| (city.to_owned(), measurement))
+        .collect();
+
+    Ok(measurements)
+}
+
+// Like `aggregate_mapped`, but with the chunks already found, which have to be whole lines
+pub(crate) fn aggregate_chunks<'a, A: Accumulator>(
+    bytes: &'a [u8],
+    chunks: &[Range<usize>],
+    offset: usize,
+    options: &Options,
+) -> io::Result<Interned<'a, A>> {
+    // Perform memory allocation while waiting for the threads to finish
+    let mut measurements = interned::<A>(options.stations_hint);
+
+    share(
+        chunks.len(),
+        options,
+        || interned::<A>(options.stations_hint),
+        |thread_measurements, i| {
+            let chunk = &bytes[chunks[i].clone()];
+            let range = offset + chunks[i].start..offset + chunks[i].end;
+
+            /*
+             * A chunk that couldn't be processed, such as one with a malformed line, fails the
+             * whole aggregation since its results would be missing. We say which chunk it was.
+             */
+            let result = process_mapped_lines(chunk, range.start, options).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "failed to process chunk {} (bytes {}..{}): {}",
+                        i, range.start, range.end, e
+                    ),
+                )
+            })?;
+            merge_into(thread_measurements, result);
+
+            Ok(())
+        },
+        // While we're waiting for the threads to finish, we can perform the aggregation
+        |thread_measurements| merge_into(&mut measurements, thread_measurements),
+    )?;
+
+    Ok(measurements)
+}
+
+// Splits `bytes` into `chunks_per_thread` chunks for every thread, for them to share out. The
+// split is timed here rather than by the public functions, which leave printing to their callers
+pub(crate) fn shared_chunks(bytes: &[u8], options: &Options) -> Vec<Range<usize>> {
+    let start = Instant::now();
+    let chunks = striped_chunks(
+        bytes,
+        options
+            .parallelism
+            .saturating_mul(options.chunks_per_thread.max(1)),
+        options.stripe_size.unwrap_or(1).max(1),
+    );
+
+    if options.verbose {
+        eprintln!(
+            "Split {} bytes into {} chunks in {:?}",
+            bytes.len(),
+            chunks.len(),
+            start.elapsed()
+        );
+    }
+
+    chunks
+}
+
+#[inline(always)]
+fn merge_into<'a, A: Accumulator>(
+    measurements: &mut Interned<'a, A>,
+    other: impl IntoIterator<Item = (&'a str, A)>,
+) {
+    #[cfg(feature = "prefetch")]
+    let other = prefetching(other);
+
+    for (city, measurement) in other {
+        let Some(item) = measurements.get_mut(city) else {
+            measurements.insert(city, measurement);
+            continue;
+        };
+
+        item.merge(&measurement);
+    }
+}
+
+/*
+ * Works through the jobs 0..jobs on up to `options.parallelism` threads. Rather than being handed
+ * a fixed share of the jobs up front, each thread takes the next job from a shared index whenever
+ * it's finished its last, until there are none left, which balances the work out however uneven
+ * the jobs are.
+ *
+ * Every thread folds the jobs it takes into a state of its own, started by `start`, and sends it
+ * back once it's out of jobs. `finish` is given each of them on the calling thread as soon as it
+ * arrives, so that merging them overlaps with the threads still working rather than waiting on
+ * them one by one. The first job to fail stops any job that hasn't been started yet, and its error
+ * is returned once every thread is done. Stopping the aggregation early does the same, but without
+ * an error, so the states cover the jobs that were done, and whatever of a job that stopped partway.
+ */
+pub(crate) fn share<S: Send>(
+    jobs: usize,
+    options: &Options,
+    start: impl Fn() -> S + Sync,
+    work: impl Fn(&mut S, usize) -> io::Result<()> + Sync,
+    mut finish: impl FnMut(S),
+) -> io::Result<()> {
+    let threads = options.parallelism.clamp(1, jobs.max(1));
+    let next = AtomicUsize::new(0);
+    let cores = cores(options);
+
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+
+        for thread in 0..threads {
+            let sender = sender.clone();
+            let (next, cores, start, work) = (&next, &cores, &start, &work);
+
+            scope.spawn(move || {
+                pin(cores, thread);
+                let mut state = start();
+
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= jobs || options.stopped() {
+                        break;
+                    }
+
+                    if let Err(e) = work(&mut state, i) {
+                        next.store(jobs, Ordering::Relaxed);
+
+                        // The receiver is only gone if another thread has already failed
+                        let _ = sender.send(Err(e));
+                        return;
+                    }
+                }
+
+                let _ = sender.send(Ok(state));
+            });
+        }
+
+        // Otherwise the receiver would wait on our own sender forever, after the threads finish
+        drop(sender);
+
+        for state in receiver {
+            finish(state?);
+        }
+
+        Ok(())
+    })
+}
+
+/*
+ * Every name being merged has to be hashed and then compared against whatever's in its bucket,
+ * both of which read it from wherever it is in the file. With thousands of stations those are
+ * scattered across all of it, so hinting at the next name's cache line while the current one is
+ * merged could hide some of the wait. Only the name itself can be prefetched, as the standard
+ * library's maps don't tell us which bucket the next lookup will land in.
+ *
+ * `_mm_prefetch` is stable, so unlike `std::intrinsics::prefetch_read_data` this doesn't need a
+ * nightly compiler, though it's just as much of a hint.
+ *
+ * It's off by default, as it didn't measurably help. Over 5 million rows of 10,000 stations on a
+ * single thread, it was within noise with the usual 4 chunks, and also with 1,024 chunks, where
+ * merging takes a good third of the run. The names a thread's map holds were only just read
+ * as it aggregated them, so they tend to be in the cache already.
+ */
+#[cfg(feature = "prefetch")]
+#[inline(always)]
+fn prefetching<'a, T>(
+    items: impl IntoIterator<Item = (&'a str, T)>,
+) -> impl Iterator<Item = (&'a str, T)> {
+    let mut items = items.into_iter().peekable();
+
+    std::iter::from_fn(move || {
+        let item = items.next()?;
+        if let Some((next, _)) = items.peek() {
+            prefetch(next.as_ptr());
+        }
+
+        Some(item)
+    })
+}
+
+#[cfg(feature = "prefetch")]
+#[inline(always)]
+fn prefetch(data: *const u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        std::arch::x86_64::_mm_prefetch::<{ std::arch::x86_64::_MM_HINT_T0 }>(data as *const i8)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = data;
+}
@@ -0,0 +1,684 @@
+use std::{ffi::OsString, path::PathBuf, sync::Arc, time::Duration};
+
+use one_brc::{
+    Casing, Checkpoint, ChunkStats, Format, OnError, Options, OutputOptions, Progress, Readahead,
+    Sort,
+};
+
+const DEFAULT_PATH: &str = "measurements.txt";
+
+// Often enough that a crash doesn't cost much, without spending much of the run saving
+const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+// Sets the number of threads when --threads isn't given, for environments such as CI where how
+// many cores there are is hard to tell or misleading
+const THREADS_VAR: &str = "ONEBRC_THREADS";
+
+pub(crate) enum Input {
+    // Every file is aggregated into the same results
+    Files(Vec<PathBuf>),
+    Stdin,
+}
+
+// What to do about a station passed to --only that doesn't appear in the results
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Missing {
+    // Write it anyway, with placeholders for its statistics, so the rows are always the same
+    Placeholder,
+    Error,
+}
+
+// Whether to aggregate the input and write the results, or only half of that
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Aggregate,
+    // Aggregate the input, but write the results as a partial for reducing later on
+    Map,
+    // Merge partials written by map, and write their results as if the input had been aggregated
+    Reduce,
+    // Aggregate the input, then serve the results over HTTP until stopped
+    Serve,
+}
+
+pub(crate) struct Args {
+    pub(crate) mode: Mode,
+    pub(crate) input: Input,
+    pub(crate) output_path: Option<PathBuf>,
+    pub(crate) format: Format,
+    pub(crate) sort: Sort,
+    pub(crate) top: Option<usize>,
+    pub(crate) only: Option<Vec<String>>,
+    pub(crate) exclude: Option<Vec<String>>,
+    pub(crate) missing: Missing,
+    pub(crate) options: Options,
+    pub(crate) output: OutputOptions,
+    pub(crate) threads: Option<usize>,
+    pub(crate) validate: bool,
+    pub(crate) bench: bool,
+    pub(crate) summary: bool,
+    // Print nothing but the results, and any error that stops them from being written
+    pub(crate) quiet: bool,
+    // Don't write the results either, for when only the exit status matters
+    pub(crate) no_result: bool,
+    // Combine stations whose names only differ in case
+    pub(crate) casing: Option<Casing>,
+    // The port to serve the results on, where 0 leaves it up to the system
+    #[cfg_attr(not(feature = "server"), allow(dead_code))]
+    pub(crate) port: u16,
+}
+
+// The port to serve on unless we're told otherwise
+const DEFAULT_PORT: u16 = 8080;
+
+impl Args {
+    pub(crate) fn parse() -> Result<Self, String> {
+        let mut argv = std::env::args_os().skip(1).peekable();
+        /*
+         * A file can be named after a mode as easily as anything else, so only the first argument
+         * picks one, and a `--` before it leaves it a file to aggregate. The `--` also ends the
+         * options, like it does everywhere else, for files whose names start with one.
+         */
+        let mode = match argv.peek().and_then(|arg| arg.to_str()) {
+            Some("map") => Mode::Map,
+            Some("reduce") => Mode::Reduce,
+            Some("serve") => Mode::Serve,
+            _ => Mode::Aggregate,
+        };
+        if mode != Mode::Aggregate {
+            argv.next();
+        }
+
+        let mut paths = Vec::new();
+        let mut output_path = None;
+        // Left unset until they're given, as serve refuses both
+        let mut format = None;
+        let mut sort = None;
+        let mut top = None;
+        let mut by = None;
+        let mut only = None;
+        let mut exclude = None;
+        let mut missing = Missing::Placeholder;
+        // Splitting the same file on every run is wasted work, so it's cached unless told not to
+        let mut options = Options {
+            cache_chunks: true,
+            ..Options::default()
+        };
+        let mut output = OutputOptions::default();
+        let mut threads = None;
+        let mut log_skipped = false;
+        let mut checkpoint = None;
+        let mut checkpoint_interval = None;
+        let mut validate = false;
+        let mut bench = false;
+        let mut summary = false;
+        let mut quiet = false;
+        let mut no_result = false;
+        let mut casing = None;
+        let mut port = None;
+
+        while let Some(arg) = argv.next() {
+            match arg.to_str() {
+                Some("--output") => {
+                    output_path = Some(PathBuf::from(value(&mut argv, "--output")?))
+                }
+                Some("--format") => format = Some(value(&mut argv, "--format")?.parse()?),
+                Some("--sort") => sort = Some(value(&mut argv, "--sort")?.parse()?),
+                Some("--sort-unicode") => sort = Some(Sort::Unicode),
+                Some("--top") => {
+                    let value = value(&mut argv, "--top")?;
+                    top = Some(value.parse::<usize>().map_err(|_| {
+                        format!(
+                            "invalid count for --top {:?}, expected a whole number",
+                            value
+                        )
+                    })?);
+                }
+                Some("--by") => match value(&mut argv, "--by")?.parse()? {
+                    Sort::Name | Sort::NameDesc | Sort::Unicode => {
+                        return Err("--by expects one of: mean, max, count".to_owned())
+                    }
+                    order => by = Some(order),
+                },
+                Some("--only") => {
+                    only = Some(
+                        value(&mut argv, "--only")?
+                            .split(',')
+                            .map(str::to_owned)
+                            .collect(),
+                    )
+                }
+                Some("--exclude") => {
+                    exclude = Some(
+                        value(&mut argv, "--exclude")?
+                            .split(',')
+                            .map(str::to_owned)
+                            .collect(),
+                    )
+                }
+                Some("--missing") => {
+                    missing = match value(&mut argv, "--missing")?.as_str() {
+                        "na" => Missing::Placeholder,
+                        "error" => Missing::Error,
+                        other => {
+                            return Err(format!(
+                                "unknown value {:?} for --missing, expected one of: na, error",
+                                other
+                            ))
+                        }
+                    }
+                }
+                Some("--threads") => {
+                    threads = Some(thread_count(&value(&mut argv, "--threads")?, "--threads")?)
+                }
+                Some("--chunks-per-thread") => {
+                    let value = value(&mut argv, "--chunks-per-thread")?;
+                    match value.parse::<usize>() {
+                        Ok(n) if n >= 1 => options.chunks_per_thread = n,
+                        _ => {
+                            return Err(format!(
+                                "invalid number of chunks per thread {:?}, expected a whole number of at least 1",
+                                value
+                            ))
+                        }
+                    }
+                }
+                Some("--stats") => {
+                    for stat in value(&mut argv, "--stats")?.split(',') {
+                        match stat {
+                            "median" => {
+                                options.histogram = true;
+                                output.median = true;
+                            }
+                            "mode" => {
+                                options.histogram = true;
+                                output.mode = true;
+                            }
+                            _ => {
+                                return Err(format!(
+                                    "unknown statistic {:?}, expected one of: median, mode",
+                                    stat
+                                ))
+                            }
+                        }
+                    }
+                }
+                Some("--delimiter") => {
+                    options.delimiter = delimiter(&value(&mut argv, "--delimiter")?)?
+                }
+                Some("--on-error") => {
+                    options.on_error = match value(&mut argv, "--on-error")?.as_str() {
+                        "fail" => OnError::Fail,
+                        "skip" => OnError::Skip,
+                        other => {
+                            return Err(format!(
+                                "unknown value {:?} for --on-error, expected one of: skip, fail",
+                                other
+                            ))
+                        }
+                    }
+                }
+                Some("--log-skipped") => log_skipped = true,
+                Some("--progress") => options.progress = Some(Arc::new(Progress::default())),
+                Some("--chunk-stats") => {
+                    options.chunk_stats = Some(Arc::new(ChunkStats::default()))
+                }
+                Some("--stations-hint") => {
+                    let value = value(&mut argv, "--stations-hint")?;
+                    options.stations_hint = value.parse().map_err(|_| {
+                        format!(
+                            "invalid station count {:?} for --stations-hint, expected a whole number",
+                            value
+                        )
+                    })?;
+                }
+                Some("--checkpoint") => {
+                    checkpoint = Some(PathBuf::from(value(&mut argv, "--checkpoint")?))
+                }
+                Some("--checkpoint-interval") => {
+                    checkpoint_interval = Some(duration(
+                        &value(&mut argv, "--checkpoint-interval")?,
+                        "--checkpoint-interval",
+                    )?)
+                }
+                Some("--limit-bytes") => {
+                    options.limit_bytes =
+                        Some(bytes(&value(&mut argv, "--limit-bytes")?, "--limit-bytes")?)
+                }
+                Some("--min-parallel-bytes") => {
+                    options.min_parallel_bytes = bytes(
+                        &value(&mut argv, "--min-parallel-bytes")?,
+                        "--min-parallel-bytes",
+                    )?
+                }
+                Some("--stripe-size") => {
+                    let value = value(&mut argv, "--stripe-size")?;
+                    match bytes(&value, "--stripe-size")? {
+                        0 => return Err("--stripe-size has to be at least 1 byte".to_owned()),
+                        stripe => options.stripe_size = Some(stripe),
+                    }
+                }
+                Some("--sample") => {
+                    let value = value(&mut argv, "--sample")?;
+                    match value.parse::<usize>() {
+                        Ok(every) if every >= 1 => options.sample = Some(every),
+                        _ => {
+                            return Err(format!(
+                                "invalid sample rate {:?}, expected a whole number of at least 1",
+                                value
+                            ))
+                        }
+                    }
+                }
+                Some("--debug-offsets") => {
+                    options.debug_offsets = true;
+                    output.offsets = true;
+                }
+                Some("--validate") => validate = true,
+                Some("--strict") => options.strict = true,
+                Some("--value-first") => options.value_first = true,
+                Some("--bench") => bench = true,
+                Some("--summary") => summary = true,
+                Some("--port") => {
+                    let value = value(&mut argv, "--port")?;
+                    port = Some(value.parse::<u16>().map_err(|_| {
+                        format!("invalid port {:?}, expected a number up to 65535", value)
+                    })?);
+                }
+                // --ci-casing implies --ci, so this can't override the casing it gave
+                Some("--ci") => casing = casing.or(Some(Casing::default())),
+                Some("--ci-casing") => {
+                    casing = match value(&mut argv, "--ci-casing")?.as_str() {
+                        "lower" => Some(Casing::Lower),
+                        "common" => Some(Casing::Common),
+                        other => {
+                            return Err(format!(
+                                "unknown value {:?} for --ci-casing, expected one of: lower, common",
+                                other
+                            ))
+                        }
+                    }
+                }
+                Some("-v" | "--verbose") => options.verbose = true,
+                Some("-q" | "--quiet") => quiet = true,
+                Some("--no-result") => no_result = true,
+                Some("--trust-utf8") => options.trust_utf8 = true,
+                Some("--hugepages") => options.huge_pages = true,
+                Some("--numa") => options.numa = true,
+                Some("--no-cache") => options.cache_chunks = false,
+                Some("--direct") => options.direct = true,
+                Some("--readahead") => {
+                    options.readahead = match value(&mut argv, "--readahead")?.as_str() {
+                        "default" => Readahead::Default,
+                        "sequential" => Readahead::Sequential,
+                        "willneed" => Readahead::WillNeed,
+                        other => {
+                            return Err(format!(
+                                "unknown value {:?} for --readahead, expected one of: default, sequential, willneed",
+                                other
+                            ))
+                        }
+                    }
+                }
+                Some("--rounding") => {
+                    output.precision.rounding = value(&mut argv, "--rounding")?.parse()?
+                }
+                Some("--decimals") => {
+                    let value = value(&mut argv, "--decimals")?;
+                    match value.parse::<u32>() {
+                        Ok(decimals) if decimals <= 3 => output.precision.decimals = decimals,
+                        _ => {
+                            return Err(format!(
+                                "invalid number of decimals {:?}, expected 0 to 3",
+                                value
+                            ))
+                        }
+                    }
+                }
+                Some("--with-count") => output.count = true,
+                Some("--with-stddev") => output.stddev = true,
+                Some("--percentiles") => {
+                    for percentile in value(&mut argv, "--percentiles")?.split(',') {
+                        match percentile.parse::<f64>() {
+                            Ok(p) if (0.0..=100.0).contains(&p) => output.percentiles.push(p),
+                            _ => {
+                                return Err(format!(
+                                    "invalid percentile {:?}, expected a number from 0 to 100",
+                                    percentile
+                                ))
+                            }
+                        }
+                    }
+                    options.histogram = true;
+                }
+                // Everything after it is a path, whatever it looks like
+                Some("--") => paths.extend(argv.by_ref().map(PathBuf::from)),
+                Some(flag) if flag.starts_with("--") => {
+                    return Err(format!("unknown option {}", flag))
+                }
+                _ => paths.push(PathBuf::from(arg)),
+            }
+        }
+
+        // The flag wins over the environment, which wins over however many cores there are
+        if threads.is_none() {
+            if let Some(value) = std::env::var_os(THREADS_VAR) {
+                threads = Some(thread_count(&value.to_string_lossy(), THREADS_VAR)?);
+            }
+        }
+
+        // Everything --quiet silences was asked for explicitly, so rather than pick one we refuse both
+        if quiet {
+            let conflicting = [
+                ("--verbose", options.verbose),
+                ("--progress", options.progress.is_some()),
+                ("--chunk-stats", options.chunk_stats.is_some()),
+                ("--log-skipped", log_skipped),
+                ("--bench", bench),
+            ];
+            if let Some((flag, _)) = conflicting.into_iter().find(|&(_, set)| set) {
+                return Err(format!("--quiet can't be combined with {}", flag));
+            }
+        }
+
+        if options.strict && options.value_first {
+            return Err("--strict can't be combined with --value-first".to_owned());
+        }
+
+        // Logging the lines we skip only makes sense if we're skipping them rather than failing
+        if log_skipped {
+            match options.on_error {
+                OnError::Skip => options.on_error = OnError::Log,
+                _ => return Err("--log-skipped requires --on-error skip".to_owned()),
+            }
+        }
+
+        match (checkpoint, checkpoint_interval) {
+            (Some(path), interval) => {
+                options.checkpoint = Some(Checkpoint::new(
+                    path,
+                    interval.unwrap_or(DEFAULT_CHECKPOINT_INTERVAL),
+                ))
+            }
+            (None, Some(_)) => return Err("--checkpoint-interval requires --checkpoint".to_owned()),
+            (None, None) => {}
+        }
+
+        // Picking the top stations only makes sense when they're ordered by a value, which is the
+        // max unless either --by or --sort say otherwise
+        let mut order = sort.unwrap_or_default();
+        if top.is_some() {
+            match by {
+                Some(by) => order = by,
+                None if matches!(order, Sort::Name | Sort::NameDesc | Sort::Unicode) => {
+                    order = Sort::Max
+                }
+                None => {}
+            }
+        }
+
+        // There's no conventional name for a partial, so there's nothing to default to
+        if mode == Mode::Reduce && paths.is_empty() {
+            return Err("reduce needs at least one partial to merge".to_owned());
+        }
+        // Partials are read whole, so there's nothing to resume partway through
+        if mode == Mode::Reduce && options.checkpoint.is_some() {
+            return Err("--checkpoint can't be used with reduce".to_owned());
+        }
+
+        if mode != Mode::Serve && port.is_some() {
+            return Err("--port only works with serve".to_owned());
+        }
+        if mode == Mode::Serve {
+            if !cfg!(feature = "server") {
+                return Err("serve requires building with the server feature".to_owned());
+            }
+
+            // Every station is served as JSON in order of name, so anything that shapes what's
+            // written other than its statistics and their decimals is moot, and stdin couldn't be
+            // read again to reload
+            let conflicting = [
+                ("--output", output_path.is_some()),
+                ("--format", format.is_some()),
+                ("--sort", sort.is_some()),
+                ("--by", by.is_some()),
+                ("--only", only.is_some()),
+                ("--exclude", exclude.is_some()),
+                ("--top", top.is_some()),
+                ("--summary", summary),
+                ("--validate", validate),
+                ("--bench", bench),
+                ("--progress", options.progress.is_some()),
+                ("--chunk-stats", options.chunk_stats.is_some()),
+                ("--checkpoint", options.checkpoint.is_some()),
+                ("stdin", paths.iter().any(|path| path.as_os_str() == "-")),
+            ];
+            if let Some((flag, _)) = conflicting.into_iter().find(|&(_, set)| set) {
+                return Err(format!("serve can't be combined with {}", flag));
+            }
+        }
+
+        Ok(Self {
+            mode,
+            // Default to the challenge's conventional file name in the working directory, with
+            // "-" standing in for stdin
+            input: match paths.as_slice() {
+                [] => Input::Files(vec![PathBuf::from(DEFAULT_PATH)]),
+                [path] if path.as_os_str() == "-" && options.checkpoint.is_some() => {
+                    return Err("--checkpoint only works with a file, not stdin".to_owned())
+                }
+                [path] if path.as_os_str() == "-" && options.direct => {
+                    return Err("--direct only works with files, not stdin".to_owned())
+                }
+                [path] if path.as_os_str() == "-" => Input::Stdin,
+                paths if paths.iter().any(|path| path.as_os_str() == "-") => {
+                    return Err("stdin can't be read along with other files".to_owned())
+                }
+                // Only the byte offset into a single file is saved
+                _ if options.checkpoint.is_some() && paths.len() > 1 => {
+                    return Err("--checkpoint only works with a single file".to_owned())
+                }
+                _ => Input::Files(paths),
+            },
+            output_path,
+            format: format.unwrap_or_default(),
+            sort: order,
+            top,
+            only,
+            exclude,
+            missing,
+            options,
+            output,
+            threads,
+            validate,
+            bench,
+            summary,
+            quiet,
+            no_result,
+            casing,
+            port: port.unwrap_or(DEFAULT_PORT),
+        })
+    }
+}
+
+// The challenge's file has a billion rows, so that's what we generate unless told otherwise
+const DEFAULT_ROWS: u64 = 1_000_000_000;
+
+pub(crate) struct GenerateArgs {
+    pub(crate) rows: u64,
+    // None means stdout
+    pub(crate) output: Option<PathBuf>,
+    pub(crate) seed: u64,
+}
+
+impl GenerateArgs {
+    // Parses the arguments that follow the generate subcommand
+    pub(crate) fn parse() -> Result<Self, String> {
+        let mut argv = std::env::args_os().skip(2);
+        let mut rows = DEFAULT_ROWS;
+        let mut output = Some(PathBuf::from(DEFAULT_PATH));
+        let mut seed = None;
+
+        while let Some(arg) = argv.next() {
+            match arg.to_str() {
+                Some("--rows") => {
+                    let value = value(&mut argv, "--rows")?;
+                    rows = value.parse().map_err(|_| {
+                        format!("invalid row count {:?}, expected a whole number", value)
+                    })?;
+                }
+                Some("--output") => {
+                    output = match value(&mut argv, "--output")?.as_str() {
+                        "-" => None,
+                        path => Some(PathBuf::from(path)),
+                    }
+                }
+                Some("--seed") => {
+                    let value = value(&mut argv, "--seed")?;
+                    seed = Some(value.parse().map_err(|_| {
+                        format!("invalid seed {:?}, expected a whole number", value)
+                    })?);
+                }
+                _ => return Err(format!("unexpected argument {}", arg.to_string_lossy())),
+            }
+        }
+
+        Ok(Self {
+            rows,
+            output,
+            // Without a seed every run should be different, and the time is different enough
+            seed: seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+            }),
+        })
+    }
+}
+
+// Binary files are tied to the text file they were converted from, so they're named after it
+const DEFAULT_BINARY_PATH: &str = "measurements.bin";
+
+pub(crate) struct ConvertArgs {
+    pub(crate) input: Input,
+    // None means stdout
+    pub(crate) output: Option<PathBuf>,
+    pub(crate) options: Options,
+}
+
+impl ConvertArgs {
+    // Parses the arguments that follow the convert subcommand
+    pub(crate) fn parse() -> Result<Self, String> {
+        let mut argv = std::env::args_os().skip(2);
+        let mut input = None;
+        let mut output = Some(PathBuf::from(DEFAULT_BINARY_PATH));
+        let mut options = Options::default();
+
+        while let Some(arg) = argv.next() {
+            match arg.to_str() {
+                Some("--output") => {
+                    output = match value(&mut argv, "--output")?.as_str() {
+                        "-" => None,
+                        path => Some(PathBuf::from(path)),
+                    }
+                }
+                Some("--delimiter") => {
+                    options.delimiter = delimiter(&value(&mut argv, "--delimiter")?)?
+                }
+                Some("--skip-malformed") => options.on_error = OnError::Skip,
+                Some("--value-first") => options.value_first = true,
+                Some(flag) if flag.starts_with("--") => {
+                    return Err(format!("unknown option {}", flag))
+                }
+                _ if input.is_some() => {
+                    return Err(format!("unexpected argument {}", arg.to_string_lossy()))
+                }
+                _ if arg == "-" => input = Some(Input::Stdin),
+                _ => input = Some(Input::Files(vec![PathBuf::from(arg)])),
+            }
+        }
+
+        Ok(Self {
+            input: input.unwrap_or_else(|| Input::Files(vec![PathBuf::from(DEFAULT_PATH)])),
+            output,
+            options,
+        })
+    }
+}
+
+// The delimiter is matched byte by byte, so it has to be a single byte. Tabs are awkward to pass
+// from a shell, so a literal \t is accepted too.
+fn delimiter(value: &str) -> Result<u8, String> {
+    match value.as_bytes() {
+        b"\\t" => Ok(b'\t'),
+        &[byte] => Ok(byte),
+        _ => Err(format!(
+            "invalid delimiter {:?}, expected a single byte character",
+            value
+        )),
+    }
+}
+
+// A number of threads, from `source`, which is either the flag or the environment variable
+fn thread_count(value: &str, source: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err(format!(
+            "invalid thread count {:?} for {}, expected a whole number of at least 1",
+            value, source
+        )),
+    }
+}
+
+// A whole number of bytes, optionally followed by K, M or G for thousands, millions or billions of
+// them, such as 100M. A trailing B is allowed too, as in 100MB.
+fn bytes(value: &str, flag: &str) -> Result<usize, String> {
+    let number = value.strip_suffix('B').unwrap_or(value);
+    let (number, multiplier) = match number.as_bytes().last() {
+        Some(b'K') => (&number[..number.len() - 1], 1_000),
+        Some(b'M') => (&number[..number.len() - 1], 1_000_000),
+        Some(b'G') => (&number[..number.len() - 1], 1_000_000_000),
+        _ => (number, 1),
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!(
+                "invalid size {:?} for {}, expected a whole number of bytes such as 1000, 100K, 100M or 1G",
+                value, flag
+            )
+        })
+}
+
+// A whole number of seconds, minutes or hours, such as 30s, 5m or 1h. A bare number is taken to
+// be in seconds.
+fn duration(value: &str, flag: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => 0,
+    };
+
+    // Too many hours to count in seconds is as invalid as a duration that isn't a number at all
+    match number.parse::<u64>().map(|number| number.checked_mul(seconds)) {
+        Ok(Some(total)) if seconds > 0 => Ok(Duration::from_secs(total)),
+        _ => Err(format!(
+            "invalid duration {:?} for {}, expected a whole number of seconds, minutes or hours such as 30s, 5m or 1h",
+            value, flag
+        )),
+    }
+}
+
+fn value(argv: &mut impl Iterator<Item = OsString>, flag: &str) -> Result<String, String> {
+    argv.next()
+        .ok_or_else(|| format!("{} requires a value", flag))?
+        .into_string()
+        .map_err(|_| format!("the value for {} must be valid UTF-8", flag))
+}
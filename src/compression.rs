@@ -0,0 +1,46 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+};
+
+use flate2::read::MultiGzDecoder;
+
+/*
+ * Compressed input can't be split into chunks, as we have no way of seeking to an arbitrary
+ * offset in the decompressed output. Instead we detect it up front and stream the decompressed
+ * bytes through the single-threaded reader.
+ *
+ * Supporting another codec means adding a variant, its magic bytes and its decoder below.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Compression {
+    Gzip,
+}
+
+impl Compression {
+    const MAGIC_LEN: usize = 2;
+
+    // Sniffs the file's header, rewinding it afterwards so the caller can read it from the start
+    pub(crate) fn detect(file: &mut File) -> io::Result<Option<Self>> {
+        let mut magic = [0u8; Self::MAGIC_LEN];
+        let read = file.read(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        Ok(Self::sniff(&magic[..read]))
+    }
+
+    // Like `detect`, but from the first bytes of a file that have already been read
+    pub(crate) fn sniff(header: &[u8]) -> Option<Self> {
+        match header.get(..Self::MAGIC_LEN)? {
+            [0x1f, 0x8b] => Some(Self::Gzip),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn decoder<'a>(self, reader: impl Read + 'a) -> Box<dyn Read + 'a> {
+        match self {
+            // Concatenated gzip members are valid gzip files, so make sure we read all of them
+            Self::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        }
+    }
+}
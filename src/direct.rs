@@ -0,0 +1,160 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    aggregate_stream_as, binary, compression::Compression, progress::Counted, Accumulator, Options,
+};
+
+/*
+ * With O_DIRECT, reads go straight from the device into our buffer, skipping the page cache, so a
+ * run measures the device rather than whatever the cache held from the last one. In exchange the
+ * kernel insists that the buffer's address, the offset in the file and the length of every read
+ * are all multiples of the device's logical block size. That's 512 bytes on older disks and 4096
+ * on most since, so aligning everything to 4096 satisfies either.
+ *
+ * Every read but the last is for a whole buffer, which is a whole number of blocks, so the offset
+ * stays aligned all the way through without ever having to seek. The last read comes up short at
+ * the end of the file, which the kernel allows.
+ *
+ * That only works for a single reader going from the start to the end, so rather than being split
+ * into chunks at aligned offsets, with the lines straddling each boundary stitched back together,
+ * the file is streamed through the same reader as stdin. That reads on one thread and aggregates
+ * on another, which keeps up with all but the fastest devices.
+ */
+const ALIGNMENT: usize = 4096;
+// Large enough that a read costs far more in transfer than in the system call
+const BUFFER_LEN: usize = 256 * ALIGNMENT;
+
+// A file opened for direct reads, buffered in a block aligned to what O_DIRECT needs
+struct DirectFile {
+    file: File,
+    // Over-allocated by an alignment, with the buffer itself starting at `start`
+    allocation: Vec<u8>,
+    start: usize,
+    position: usize,
+    filled: usize,
+}
+
+impl DirectFile {
+    #[cfg(target_os = "linux")]
+    fn open(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                // Filesystems without direct reads, such as tmpfs, refuse the flag outright
+                io::ErrorKind::InvalidInput => io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "the file's filesystem doesn't support direct reads",
+                ),
+                _ => e,
+            })?;
+
+        let allocation = vec![0; BUFFER_LEN + ALIGNMENT];
+        let start = allocation.as_ptr().align_offset(ALIGNMENT);
+
+        Ok(Self {
+            file,
+            allocation,
+            start,
+            position: 0,
+            filled: 0,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn open(_path: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "direct reads are only supported on Linux",
+        ))
+    }
+}
+
+impl Read for DirectFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let read = available.len().min(buf.len());
+        buf[..read].copy_from_slice(&available[..read]);
+        self.consume(read);
+
+        Ok(read)
+    }
+}
+
+impl BufRead for DirectFile {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buffer = &mut self.allocation[self.start..self.start + BUFFER_LEN];
+
+        if self.position == self.filled {
+            self.filled = self.file.read(buffer)?;
+            self.position = 0;
+        }
+
+        Ok(&buffer[self.position..self.filled])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.filled);
+    }
+}
+
+// Aggregates the file at `path` as a stream read with O_DIRECT, for `Options::direct`
+pub(crate) fn aggregate<A: Accumulator>(
+    path: &Path,
+    options: &Options,
+) -> io::Result<BTreeMap<String, A>> {
+    // A stream can only be read from the start, so there's no resuming partway through it
+    if options.checkpoint.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "direct reads can't be checkpointed",
+        ));
+    }
+
+    let mut file = DirectFile::open(path)?;
+
+    // The header is in the first buffer, which reading goes on to use rather than reading again
+    let header = file.fill_buf()?;
+    if binary::is_binary(header) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "binary files are mapped, so they can't be read directly",
+        ));
+    }
+    let compression = Compression::sniff(header);
+
+    let Some(progress) = options.progress.clone() else {
+        return match compression {
+            Some(compression) => {
+                aggregate_stream_as(BufReader::new(compression.decoder(file)), options)
+            }
+            None => aggregate_stream_as(file, options),
+        };
+    };
+
+    // The bytes are counted as they're read, which for a compressed file is before decompressing
+    progress.add_total(file.file.metadata()?.len());
+    let reader = Counted {
+        inner: file,
+        progress,
+    };
+    let options = Options {
+        progress: None,
+        ..options.clone()
+    };
+
+    match compression {
+        Some(compression) => {
+            aggregate_stream_as(BufReader::new(compression.decoder(reader)), &options)
+        }
+        None => aggregate_stream_as(BufReader::with_capacity(BUFFER_LEN, reader), &options),
+    }
+}
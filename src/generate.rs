@@ -0,0 +1,76 @@
+use std::io::{self, Write};
+
+use crate::{measurement::Fixed, stations::STATIONS};
+
+// The spread of every station's measurements around its mean, in tenths of a degree
+const STDDEV: f64 = 100.0;
+
+// The challenge's measurements never go beyond ±99.9
+const LIMIT: i64 = 999;
+
+/// Writes `rows` lines of random measurements to `writer`, in the challenge's `station;-12.3`
+/// format. Each line is for a station picked at random from the challenge's own list, with a
+/// measurement drawn from a normal distribution around that station's mean temperature.
+///
+/// The same `seed` always produces the same lines, so generated files can be compared between
+/// runs and machines.
+pub fn generate(writer: &mut impl Write, rows: u64, seed: u64) -> io::Result<()> {
+    let mut rng = SplitMix64(seed);
+
+    for _ in 0..rows {
+        let (station, mean) = STATIONS[rng.below(STATIONS.len() as u64) as usize];
+        let measurement = (mean as f64 + rng.gaussian() * STDDEV).round() as i64;
+
+        writeln!(
+            writer,
+            "{};{}",
+            station,
+            Fixed(measurement.clamp(-LIMIT, LIMIT), 1)
+        )?;
+    }
+
+    Ok(())
+}
+
+/*
+ * A small, fast generator that needs nothing but a 64 bit counter, see
+ * https://prng.di.unimi.it/splitmix64.c. It's nowhere near good enough for cryptography, but
+ * that doesn't matter for test data, and it keeps the output reproducible without pulling in a
+ * crate whose algorithm could change under us.
+ */
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    #[inline(always)]
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // A uniformly distributed float in [0, 1), made from the top 53 bits so that every value is
+    // exactly representable
+    #[inline(always)]
+    fn uniform(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Close enough to uniform in [0, n) for any n we'd use, as the bias is at most n / 2^64
+    #[inline(always)]
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+
+    // A standard normal sample, using the Box-Muller transform
+    #[inline(always)]
+    fn gaussian(&mut self) -> f64 {
+        // 1 - u is in (0, 1], which keeps the logarithm finite
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+
+        (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+}
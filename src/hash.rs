@@ -0,0 +1,62 @@
+/*
+ * The default SipHash hasher is designed to resist HashDoS attacks, which are irrelevant here
+ * as we are hashing station names from a file we trust. FxHash, as used by rustc, trades that
+ * resistance for a couple of instructions per word, which matters when we hash every line.
+ */
+#[cfg(feature = "fxhash")]
+pub(crate) type StationHasher = std::hash::BuildHasherDefault<FxHasher>;
+
+#[cfg(not(feature = "fxhash"))]
+pub(crate) type StationHasher = std::collections::hash_map::RandomState;
+
+pub(crate) type StationMap<K, V> = std::collections::HashMap<K, V, StationHasher>;
+
+#[inline(always)]
+pub(crate) fn station_map<K, V>(capacity: usize) -> StationMap<K, V> {
+    StationMap::with_capacity_and_hasher(capacity, StationHasher::default())
+}
+
+#[cfg(feature = "fxhash")]
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+#[cfg(feature = "fxhash")]
+#[derive(Default)]
+pub(crate) struct FxHasher {
+    hash: u64,
+}
+
+#[cfg(feature = "fxhash")]
+impl FxHasher {
+    #[inline(always)]
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+}
+
+#[cfg(feature = "fxhash")]
+impl std::hash::Hasher for FxHasher {
+    #[inline(always)]
+    fn write(&mut self, bytes: &[u8]) {
+        // Consume the input a word at a time, then mop up whatever is left over
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            self.add_to_hash(u64::from_le_bytes(unsafe {
+                chunk.try_into().unwrap_unchecked()
+            }));
+        }
+
+        for &byte in chunks.remainder() {
+            self.add_to_hash(byte as u64);
+        }
+    }
+
+    #[inline(always)]
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+
+    #[inline(always)]
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
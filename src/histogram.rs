@@ -0,0 +1,108 @@
+/*
+ * A fixed-resolution histogram of measurements, with one bucket per tenth of a degree.
+ *
+ * Storing every sample to find order statistics such as the median isn't an option with a
+ * billion rows, but temperatures realistically fall within -99.9..=99.9, so we can count how
+ * many times each possible value was seen instead. That keeps the memory per station fixed, at
+ * the cost of about 16KB each, and merging two histograms is a simple element-wise add.
+ *
+ * This limits the resolution of any statistic read from the histogram to a tenth of a degree,
+ * which is also the resolution of the input. Percentiles interpolate between neighbouring samples,
+ * but those samples are themselves only known to the nearest tenth.
+ *
+ * Values outside of the supported range are clamped into the lowest or highest bucket. They still
+ * count towards the ranks, but are reported as -99.9 or 99.9 should they be selected.
+ */
+const MIN_TENTHS: i32 = -999;
+const MAX_TENTHS: i32 = 999;
+const BUCKETS: usize = (MAX_TENTHS - MIN_TENTHS + 1) as usize;
+
+#[derive(Clone)]
+pub(crate) struct Histogram {
+    buckets: [u64; BUCKETS],
+}
+
+impl Histogram {
+    pub(crate) fn new() -> Box<Self> {
+        Box::new(Self {
+            buckets: [0; BUCKETS],
+        })
+    }
+
+    #[inline(always)]
+    pub(crate) fn record(&mut self, tenths: i32) {
+        self.buckets[(tenths.clamp(MIN_TENTHS, MAX_TENTHS) - MIN_TENTHS) as usize] += 1;
+    }
+
+    #[inline(always)]
+    pub(crate) fn merge(&mut self, other: &Histogram) {
+        for (bucket, other) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *bucket = bucket.saturating_add(*other);
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn scale(&mut self, factor: u64) {
+        // Saturates like the measurement's own counts do, rather than wrapping around
+        for bucket in &mut self.buckets {
+            *bucket = bucket.saturating_mul(factor);
+        }
+    }
+
+    // Every bucket in order, for checkpoints to save
+    pub(crate) fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    pub(crate) fn buckets_mut(&mut self) -> &mut [u64] {
+        &mut self.buckets
+    }
+
+    // The value of the sample at the given zero-based rank, in ascending order
+    fn value_at(&self, rank: u64) -> i64 {
+        let mut seen = 0;
+        for (i, &bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen > rank {
+                return (i as i32 + MIN_TENTHS) as i64;
+            }
+        }
+
+        MAX_TENTHS as i64
+    }
+
+    // The median of `count` samples in tenths of a degree, which there has to be at least one of.
+    // For an even count this is the mean of the two middle samples, rounded towards positive
+    // infinity like the mean is.
+    pub(crate) fn median(&self, count: u64) -> i64 {
+        let lower = self.value_at((count - 1) / 2);
+        let upper = self.value_at(count / 2);
+
+        (lower + upper + 1).div_euclid(2)
+    }
+
+    // The most frequently seen value in tenths of a degree. When several are seen equally often the
+    // lowest of them wins, so that the mode doesn't depend on the order anything was merged in.
+    pub(crate) fn mode(&self) -> i64 {
+        let mut mode = 0;
+        for (i, &bucket) in self.buckets.iter().enumerate() {
+            if bucket > self.buckets[mode] {
+                mode = i;
+            }
+        }
+
+        (mode as i32 + MIN_TENTHS) as i64
+    }
+
+    // The given percentile of `count` samples in tenths of a degree, which there has to be at least
+    // one of, linearly interpolating between the two samples either side of its rank as numpy's
+    // default method does.
+    pub(crate) fn percentile(&self, count: u64, percentile: f64) -> f64 {
+        let rank = (count - 1) as f64 * percentile / 100.0;
+        let lower_rank = rank.floor();
+        let lower = self.value_at(lower_rank as u64) as f64;
+        let upper = self.value_at(rank.ceil() as u64) as f64;
+
+        lower + (upper - lower) * (rank - lower_rank)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::hash::{station_map, StationMap};
+
+/*
+ * The statistics of each station a thread has seen, with the station names interned into ids.
+ * Each name is hashed once per line to find its id, which then indexes the statistics, kept apart
+ * from the names so that the table probed for every line holds nothing but names and ids, and
+ * stays small enough to sit in the cache however big a statistic is. Merging another table looks
+ * each of its names up once, and the names are only paired up with their statistics again as the
+ * results are handed out.
+ *
+ * Every thread interns into a table of its own, so there's no locking, and the ids only mean
+ * something within the table that gave them out. The names themselves are still borrowed from
+ * the input, which outlives every table made from it.
+ */
+pub(crate) struct Interned<'a, A> {
+    ids: StationMap<&'a str, u32>,
+    names: Vec<&'a str>,
+    values: Vec<A>,
+}
+
+#[inline(always)]
+pub(crate) fn interned<'a, A>(capacity: usize) -> Interned<'a, A> {
+    Interned {
+        ids: station_map(capacity),
+        names: Vec::with_capacity(capacity),
+        values: Vec::with_capacity(capacity),
+    }
+}
+
+impl<'a, A> Interned<'a, A> {
+    #[inline(always)]
+    pub(crate) fn get_mut(&mut self, name: &str) -> Option<&mut A> {
+        let &id = self.ids.get(name)?;
+
+        // Every id was given out for the value pushed alongside it
+        Some(unsafe { self.values.get_unchecked_mut(id as usize) })
+    }
+
+    // Interns a name that isn't in the table yet, with the first value seen for it
+    #[inline(always)]
+    pub(crate) fn insert(&mut self, name: &'a str, value: A) {
+        let id = u32::try_from(self.values.len()).expect("more stations than there are ids");
+
+        self.ids.insert(name, id);
+        self.names.push(name);
+        self.values.push(value);
+    }
+
+    pub(crate) fn values_mut(&mut self) -> impl Iterator<Item = &mut A> {
+        self.values.iter_mut()
+    }
+}
+
+// Resolves each id back to its name, in the order the stations were first seen
+impl<'a, A> IntoIterator for Interned<'a, A> {
+    type Item = (&'a str, A);
+    type IntoIter = std::iter::Zip<std::vec::IntoIter<&'a str>, std::vec::IntoIter<A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.names.into_iter().zip(self.values)
+    }
+}
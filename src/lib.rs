@@ -0,0 +1,270 @@
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+mod accumulator;
+#[cfg(feature = "parquet")]
+mod arrow;
+mod bgzf;
+mod binary;
+mod cache;
+mod case;
+mod checkpoint;
+mod chunk;
+mod compression;
+mod direct;
+mod generate;
+mod hash;
+mod histogram;
+mod intern;
+mod mapped;
+mod measurement;
+mod options;
+mod output;
+mod parse;
+mod partial;
+mod progress;
+mod record;
+mod search;
+mod stations;
+mod stats;
+mod validate;
+
+pub use accumulator::Accumulator;
+pub use binary::convert;
+pub use case::{fold_case, Casing};
+pub use checkpoint::Checkpoint;
+pub use chunk::{chunks, striped_chunks};
+pub use generate::generate;
+pub use mapped::MappedFile;
+pub use measurement::{Measurement, Precision, Rounding};
+pub use options::{OnError, Options, Readahead};
+pub use output::{unicode_cmp, Format, OutputOptions, Sort};
+pub use parse::{try_parse_line, try_parse_line_strict, LineError};
+pub use partial::Partial;
+pub use progress::Progress;
+pub use record::for_each_record;
+pub use stats::{ChunkStat, ChunkStats};
+pub use validate::{validate_file, validate_stream, Malformed};
+
+use compression::Compression;
+use parse::process_stream;
+use progress::Counted;
+
+/// Aggregates every station in the file at `path`, splitting the work across
+/// `parallelism` threads, and returns the results sorted by station name.
+///
+/// Gzip compressed files are detected by their header and decompressed on the fly. As they can't
+/// be split up, they are processed on the calling thread. Files in the binary format written by
+/// [`convert`] are detected the same way.
+pub fn aggregate_file(
+    path: &Path,
+    parallelism: usize,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    aggregate_file_with(
+        path,
+        &Options {
+            parallelism,
+            ..Options::default()
+        },
+    )
+}
+
+/// Like [`aggregate_file`], but with full control over how the file is aggregated.
+pub fn aggregate_file_with(
+    path: &Path,
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    if options.direct {
+        return direct::aggregate(path, options);
+    }
+
+    let mut file = File::open(path)?;
+    if let Some(compression) = Compression::detect(&mut file)? {
+        // A stream can only be read from the start, so there's no resuming partway through it
+        if options.checkpoint.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed files can't be checkpointed",
+            ));
+        }
+
+        // With an index of its blocks, a gzip file can be decompressed in parallel. A limit is on
+        // the decompressed text, which only reading it from the start can find the end of.
+        if compression == Compression::Gzip && options.limit_bytes.is_none() {
+            if let Some(index) = bgzf::Index::find(path)? {
+                return bgzf::aggregate(&file, &index, options);
+            }
+        }
+
+        return aggregate_compressed(file, compression, options);
+    }
+
+    // Checkpoints are taken between segments of a mapped file, however small it is
+    if options.checkpoint.is_none() && file.metadata()?.len() < options.min_parallel_bytes as u64 {
+        return chunk::aggregate_read(file, options);
+    }
+
+    let mapped = MappedFile::map(&file, path, options)?;
+
+    if let Some(checkpoint) = &options.checkpoint {
+        // The checkpoint doesn't record the sample rate, so resuming could combine different ones
+        if options.sample.is_some_and(|every| every > 1) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "sampled runs can't be checkpointed",
+            ));
+        }
+
+        // The checkpoint's results own their station names, so there's nothing to borrow
+        return mapped.aggregate_checkpointed(checkpoint, options);
+    }
+
+    Ok(mapped
+        .aggregate(options)?
+        .into_iter()
+        .map(|(city, measurement)| (city.to_owned(), measurement))
+        .collect())
+}
+
+/// Like [`aggregate_file_with`], but gathers whichever [`Accumulator`] `A` is for every station,
+/// rather than a [`Measurement`].
+///
+/// The input is split across the threads just the same, and the engine is compiled for each kind
+/// of accumulator on its own, so gathering measurements this way is no slower. Checkpoints and the binary
+/// format hold measurements themselves, so they're refused, and a gzip file is always decompressed
+/// on a single thread, even with an index of its blocks.
+pub fn aggregate_with<A: Accumulator>(
+    path: &Path,
+    options: &Options,
+) -> io::Result<BTreeMap<String, A>> {
+    if options.checkpoint.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only measurements can be checkpointed",
+        ));
+    }
+
+    if options.direct {
+        return direct::aggregate(path, options);
+    }
+
+    let mut file = File::open(path)?;
+    if let Some(compression) = Compression::detect(&mut file)? {
+        return aggregate_compressed(file, compression, options);
+    }
+
+    Ok(MappedFile::map(&file, path, options)?
+        .aggregate_text(options)?
+        .into_iter()
+        .map(|(city, accumulator)| (city.to_owned(), accumulator))
+        .collect())
+}
+
+// Decompresses the file on the fly as a stream, which has to be read from the start
+fn aggregate_compressed<A: Accumulator>(
+    file: File,
+    compression: Compression,
+    options: &Options,
+) -> io::Result<BTreeMap<String, A>> {
+    let Some(progress) = options.progress.clone() else {
+        return aggregate_stream_as(BufReader::new(compression.decoder(file)), options);
+    };
+
+    // We only know the compressed size up front, so that's what progress is measured in
+    progress.add_total(file.metadata()?.len());
+    let reader = Counted {
+        inner: file,
+        progress,
+    };
+
+    aggregate_stream_as(
+        BufReader::new(compression.decoder(reader)),
+        &Options {
+            progress: None,
+            ..options.clone()
+        },
+    )
+}
+
+/// Like [`aggregate_file_with`], but combines the stations of every file in `paths` into a single
+/// set of results. The files are aggregated one after another, each split across the threads.
+///
+/// An error names the file that caused it.
+pub fn aggregate_files_with(
+    paths: &[PathBuf],
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    // The checkpoint only records how far through a single file we are
+    if options.checkpoint.is_some() && paths.len() > 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "only a single file can be checkpointed",
+        ));
+    }
+
+    let mut results = BTreeMap::<String, Measurement>::new();
+
+    for path in paths {
+        let measurements = aggregate_file_with(path, options)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+        merge(&mut results, &measurements);
+    }
+
+    Ok(results)
+}
+
+/// Combines the results in `other` into `into`, as if every measurement behind them had been
+/// aggregated together. This is how the results of separate shards, such as those aggregated on
+/// different machines, can be brought together.
+pub fn merge(into: &mut BTreeMap<String, Measurement>, other: &BTreeMap<String, Measurement>) {
+    for (city, measurement) in other {
+        match into.get_mut(city) {
+            Some(item) => item.aggregate(measurement),
+            None => {
+                into.insert(city.clone(), measurement.clone());
+            }
+        }
+    }
+}
+
+/// Aggregates every station in `results` into one, with the lowest min, highest max and total
+/// count across all of them. The mean is weighted by each station's count, just as if every
+/// measurement had been recorded at the same station.
+pub fn summarize<'a>(results: impl IntoIterator<Item = &'a Measurement>) -> Measurement {
+    let mut summary = Measurement::default();
+    for measurement in results {
+        summary.aggregate(measurement);
+    }
+
+    summary
+}
+
+/// Aggregates every station read from `reader`, such as stdin, and returns the results sorted by
+/// station name. Streams can't be split up for parallel processing, so this runs on the calling
+/// thread.
+pub fn aggregate_stream(reader: impl BufRead) -> io::Result<BTreeMap<String, Measurement>> {
+    aggregate_stream_with(reader, &Options::default())
+}
+
+/// Like [`aggregate_stream`], but with full control over how the stream is aggregated.
+pub fn aggregate_stream_with(
+    reader: impl BufRead,
+    options: &Options,
+) -> io::Result<BTreeMap<String, Measurement>> {
+    aggregate_stream_as(reader, options)
+}
+
+// Like `aggregate_stream_with`, but into any accumulator
+pub(crate) fn aggregate_stream_as<A: Accumulator>(
+    reader: impl BufRead,
+    options: &Options,
+) -> io::Result<BTreeMap<String, A>> {
+    Ok(process_stream(reader, options)?.into_iter().collect())
+}
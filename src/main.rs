@@ -1,300 +1,516 @@
 use std::{
-    collections::HashMap,
-    fmt::{Display, Formatter},
+    collections::BTreeMap,
     fs::File,
-    io::{BufWriter, Read, Seek, SeekFrom, Write},
-    time::Instant,
+    io::{BufRead, BufReader, BufWriter, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-#[derive(Default)]
-struct Measurement {
-    min: f32,
-    max: f32,
-    sum: f32,
-    count: u32,
-}
+use one_brc::{
+    aggregate_files_with, aggregate_stream_with, validate_file, validate_stream, Measurement,
+    Partial, Progress, Sort,
+};
 
-impl Measurement {
-    #[inline(always)]
-    fn record(&mut self, measurement: f32) {
-        /*
-         * This may seem ridiculous, as we can just do something like
-         * ```
-         * self.min.min(other.min)
-         * ```
-         *
-         * However, this is a performance optimization. When we use the min/max functions,
-         * we are calling a function, which means that we are pushing a new frame onto the stack.
-         * We are also returning a value, which would be assigned to the existing value.
-         *
-         * The min and max values will only change infrequently, so we want to avoid performing
-         * a memcpy when the values haven't changed.
-         */
-        if measurement < self.min {
-            self.min = measurement;
-        }
+use cli::{Input, Missing, Mode};
 
-        if measurement > self.max {
-            self.max = measurement;
-        }
+mod cli;
+#[cfg(feature = "server")]
+mod serve;
+
+// The name the summary across every station is written under, which no real station should have
+const SUMMARY: &str = "__ALL__";
+
+// How many threads to use when there's no telling how many cores there are, which is enough to
+// make use of most machines without oversubscribing a small one by much
+const FALLBACK_PARALLELISM: usize = 4;
 
-        self.sum += measurement;
-        self.count += 1;
+// Set on Ctrl-C, which the aggregation checks for to stop early
+static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "generate")
+    {
+        return generate();
     }
 
-    #[inline(always)]
-    fn aggregate(&mut self, other: &Measurement) {
-        if other.min < self.min {
-            self.min = other.min;
-        }
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "convert")
+    {
+        return convert();
+    }
 
-        if other.max > self.max {
-            self.max = other.max;
+    let mut args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
         }
+    };
 
-        self.sum += other.sum;
-        self.count += other.count;
+    /*
+     * Get the number of available cores on the machine, unless we've been told how many threads
+     * to use. Detecting them can fail in a sandbox that hides the machine from us, which is no
+     * reason to give up on the run, so we go on with a handful of threads instead.
+     */
+    let available_parallelism = match args.threads {
+        Some(threads) => threads,
+        None => match std::thread::available_parallelism() {
+            Ok(n) => n.get(),
+            Err(e) => {
+                if !args.quiet {
+                    eprintln!(
+                        "Couldn't tell how many cores there are, so using {} threads ({}). Set ONEBRC_THREADS or pass --threads to choose.",
+                        FALLBACK_PARALLELISM, e
+                    );
+                }
+
+                FALLBACK_PARALLELISM
+            }
+        },
+    };
+
+    if args.options.verbose {
+        eprintln!("Parallelism: {}", available_parallelism);
     }
-}
 
-impl From<f32> for Measurement {
-    #[inline(always)]
-    fn from(measurement: f32) -> Self {
-        Self {
-            min: measurement,
-            max: measurement,
-            sum: measurement,
-            count: 1,
-        }
+    args.options.parallelism = available_parallelism;
+    args.options.stop = Some(handle_interrupt());
+
+    #[cfg(feature = "server")]
+    if args.mode == Mode::Serve {
+        return serve::serve(&args);
     }
-}
 
-impl Display for Measurement {
-    #[inline(always)]
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}/{}/{:.1}",
-            self.min,
-            self.max,
-            self.sum / self.count as f32
-        )
+    if args.validate {
+        validate(&args);
     }
-}
 
-#[inline(always)]
-fn round_towards_positive(mut n: f32) -> f32 {
-    n *= 10.0;
-    if n < 0.0 {
-        // For negative numbers we round up, for rounding towards positive
-        n = n.ceil();
-    } else {
-        n = n.round();
+    let reporter = args.options.progress.clone().map(report_progress);
+    let start = Instant::now();
+
+    let results = match &args.input {
+        _ if args.mode == Mode::Reduce => reduce(&args),
+        Input::Files(paths) => aggregate_files_with(paths, &args.options).inspect_err(|e| {
+            eprintln!("Failed to read {}", e);
+        }),
+        Input::Stdin => {
+            aggregate_stream_with(std::io::stdin().lock(), &args.options).inspect_err(|e| {
+                eprintln!("Failed to read stdin: {}", e);
+            })
+        }
+    };
+
+    // Dropping the sender tells the reporter that we're finished, so it can print the final total
+    if let Some((finished, reporter)) = reporter {
+        drop(finished);
+        let _ = reporter.join();
     }
 
-    n / 10.0
-}
+    let Ok(mut results) = results else {
+        std::process::exit(1);
+    };
 
-#[inline(always)]
-// We're manually implementing the search for our delimiter because we know that the measurements
-// are always to 1 decimal place. This means we can search from the end of the string and skip
-// the last 4 bytes, as they will be the minimum possible measurement eg (0.0).
-fn split_line<'a>(line: &'a str) -> (&'a str, &'a str) {
-    let bytes = line.as_bytes();
-    let mut i = bytes.len() - 4;
+    let interrupted = STOP.get().is_some_and(|stop| stop.load(Ordering::Relaxed));
+    if interrupted && !args.quiet {
+        eprintln!("Interrupted, so the results only cover part of the input");
+    }
 
-    while bytes[i] != b';' {
-        i -= 1;
+    if let Some(stats) = &args.options.chunk_stats {
+        let _ = stats.write_table(&mut std::io::stderr().lock());
     }
 
-    (&line[..i], &line[i + 1..])
-}
+    let skipped = args.options.skipped.load(Ordering::Relaxed);
+    if skipped > 0 && !args.quiet {
+        eprintln!("Skipped {} malformed lines", skipped);
+    }
 
-#[inline(always)]
-fn parse_line<'a>(line: &'a str) -> (&'a str, f32) {
-    // We know that the measurements are all to 1 decimal place. This means that
-    // if we search from the end of the string we will find the ; significantly faster.
-    let (city, measurement) = split_line(line);
+    // A partial has to have every station as it was aggregated, so that it can still be merged
+    // with the rest, so it's written before the results are narrowed down or folded in any way
+    if args.mode == Mode::Map {
+        let destination: Box<dyn Write> = match &args.output_path {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(file),
+                Err(e) => {
+                    eprintln!("Failed to create {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(std::io::stdout().lock()),
+        };
+        let mut writer = BufWriter::new(destination);
 
-    (
-        city,
-        round_towards_positive(unsafe { measurement.parse().unwrap_unchecked() }),
-    )
-}
+        Partial {
+            results,
+            skipped,
+            histograms: args.options.histogram,
+        }
+        .write_to(&mut writer)?;
+        writer.flush()?;
 
-#[inline(always)]
-fn process_lines<'a>(contents: &'a str) -> impl Iterator<Item = (&'a str, Measurement)> {
-    let mut measurements = HashMap::<&str, Measurement>::with_capacity(10000);
-    let mut line_count = 0u32;
-    let start = Instant::now();
+        if interrupted {
+            std::process::exit(130);
+        }
 
-    for line in contents.lines() {
-        let (city, measurement) = parse_line(line);
+        return Ok(());
+    }
 
-        let Some(item) = measurements.get_mut(city) else {
-            measurements.insert(city, Measurement::from(measurement));
-            continue;
-        };
+    if let Some(casing) = args.casing {
+        results = one_brc::fold_case(results, casing);
+    }
 
-        item.record(measurement);
+    // Every line read, before the results are narrowed down
+    let rows = results.values().map(Measurement::count).sum::<u64>() + skipped;
+
+    // Narrowing the results down to the stations we were asked for only happens once everything
+    // has been aggregated, so it has no bearing on how the file was split up
+    if let Some(only) = &args.only {
+        let mut filtered = BTreeMap::new();
+        for station in only {
+            // Once the stations have been folded, whichever way a station is asked for should match
+            // it, under whatever casing it was folded to
+            let entry = match args.casing {
+                Some(_) => results
+                    .keys()
+                    .find(|city| city.to_lowercase() == station.to_lowercase())
+                    .cloned()
+                    .and_then(|city| results.remove_entry(&city)),
+                None => results.remove_entry(station),
+            };
 
-        line_count += 1;
+            match entry {
+                Some((city, measurement)) => {
+                    filtered.insert(city, measurement);
+                }
+                None if args.missing == Missing::Error => {
+                    eprintln!("Station {} does not appear in the input", station);
+                    std::process::exit(1);
+                }
+                None => {
+                    filtered.insert(station.clone(), Measurement::default());
+                }
+            }
+        }
+        results = filtered;
     }
 
-    let end = start.elapsed();
+    // Excluded stations were aggregated like any other, they're just left out of what's written
+    if let Some(exclude) = &args.exclude {
+        results.retain(|city, _| match args.casing {
+            Some(_) => !exclude
+                .iter()
+                .any(|station| station.to_lowercase() == city.to_lowercase()),
+            None => !exclude.contains(city),
+        });
+    }
 
-    println!(
-        "Processed {} lines in {:?}, averaging {:?} per line",
-        line_count,
-        end,
-        end / line_count
-    );
+    // The summary covers every station left after --only, including any that --top leaves out, and
+    // always comes last whatever the order
+    let summary = args
+        .summary
+        .then(|| (SUMMARY.to_owned(), one_brc::summarize(results.values())));
+    let summary = summary.as_ref().map(|(name, summary)| (name, summary));
+
+    // Create a buffer to write to stdout, or the file we were given, this is faster than writing to
+    // either directly
+    let destination: Box<dyn Write> = match &args.output_path {
+        // The results are still formatted, so --bench times the same work either way
+        _ if args.no_result => Box::new(std::io::sink()),
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(destination);
+
+    // The results are already sorted by name, so they're taken straight from the map in either
+    // direction, and only any other order means sorting them again
+    let top = args.top.unwrap_or(usize::MAX);
+    let mut ordered = match args.sort {
+        Sort::Name => Vec::from_iter(results.iter().take(top)),
+        Sort::NameDesc => Vec::from_iter(results.iter().rev().take(top)),
+        sort => {
+            let mut results = Vec::from_iter(results.iter());
+            results.sort_by(sort.comparator());
+            results.truncate(top);
+
+            results
+        }
+    };
+    ordered.extend(summary);
+
+    // Formatting millions of stations takes long enough to be worth sharing out like aggregating
+    args.format.write_parallel(
+        &mut writer,
+        &ordered,
+        &args.output,
+        args.options.parallelism,
+    )?;
+    writer.flush()?;
+
+    if args.bench {
+        print_bench(&args.input, rows, start.elapsed())?;
+    }
 
-    measurements.into_iter()
+    // The partial results are still written, but the exit status tells that they're partial
+    if interrupted {
+        std::process::exit(130);
+    }
+
+    Ok(())
 }
 
-#[inline(always)]
-fn memory_map(available_parallelism: usize) -> Vec<(i64, i64)> {
-    let mut file = unsafe { File::open("measurements.txt").unwrap_unchecked() };
+/*
+ * Has Ctrl-C ask the aggregation to stop, rather than killing the process, so that whatever has
+ * been aggregated so far is still written. The default handler is put back as soon as ours runs,
+ * so pressing it a second time kills the process as usual, for when even stopping takes too long.
+ * Reads aren't restarted after it either, so one it interrupts gives up rather than waiting on
+ * more of stdin.
+ */
+fn handle_interrupt() -> Arc<AtomicBool> {
+    let stop = STOP.get_or_init(Arc::default).clone();
+
+    #[cfg(unix)]
+    {
+        extern "C" fn interrupt(_: libc::c_int) {
+            // Only async-signal-safe calls are allowed here, which an atomic store is
+            if let Some(stop) = STOP.get() {
+                stop.store(true, Ordering::Relaxed);
+            }
+        }
 
-    /*
-     * Tell the compiler to treat the output as a usize
-     * This allows us to avoid runtime type conversion.
-     */
-    let file_size = unsafe { file.metadata().unwrap_unchecked().len() } as usize;
-    let chunk_size = file_size / available_parallelism;
-    let start = Instant::now();
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = interrupt as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        }
+    }
 
-    let temp = &mut [0u8; 100];
-    let mut beginning = 0u64;
-    let mut mmap = Vec::with_capacity(available_parallelism);
+    stop
+}
 
-    /*
-     * We're going to seek the file chunk by chunk.
-     * At the end of each seek we read the next 100 bytes, find the first newline and
-     * treat it's index as the end of the chunk.
-     *
-     * This lets us produce a list of tuples that represent the start and end of each chunk
-     * without having to perform full file reads.
-     */
-    unsafe {
-        for _ in 0..available_parallelism {
-            file.seek(SeekFrom::Start(beginning + chunk_size as u64))
-                .unwrap_unchecked();
-            file.read(temp.as_mut_slice()).unwrap_unchecked();
-            let temp = std::str::from_utf8_unchecked(temp);
-            let newline = temp.find('\n').unwrap_unchecked() as u64;
-            let end = newline + beginning + chunk_size as u64;
-            file.seek(SeekFrom::Start(end)).unwrap_unchecked();
-
-            mmap.push((beginning as i64, end as i64));
-            beginning = end + 1;
+// Merges the partials that map wrote, counting the lines they skipped as if they'd been skipped here
+fn reduce(args: &cli::Args) -> std::io::Result<BTreeMap<String, Measurement>> {
+    let merged = match &args.input {
+        Input::Files(paths) => {
+            // The first partial is merged into as it is, so that it's kept with or without histograms
+            let mut merged: Option<Partial> = None;
+            for path in paths {
+                let partial = File::open(path)
+                    .and_then(|file| Partial::read_from(BufReader::new(file)))
+                    .inspect_err(|e| eprintln!("Failed to read {}: {}", path.display(), e))?;
+                match &mut merged {
+                    Some(merged) => merged
+                        .merge(&partial)
+                        .inspect_err(|e| eprintln!("Failed to merge {}: {}", path.display(), e))?,
+                    None => merged = Some(partial),
+                }
+            }
+
+            merged.unwrap_or_default()
         }
+        Input::Stdin => Partial::read_from(std::io::stdin().lock())
+            .inspect_err(|e| eprintln!("Failed to read stdin: {}", e))?,
     };
 
-    println!(
-        "Memory mapped {} chunks in {:?}",
-        mmap.len(),
-        start.elapsed()
+    args.options
+        .skipped
+        .fetch_add(merged.skipped, Ordering::Relaxed);
+
+    Ok(merged.results)
+}
+
+// Prints a summary of the run as a single line of key=value pairs, on stderr so that it stays
+// out of the results
+fn print_bench(input: &Input, rows: u64, elapsed: Duration) -> std::io::Result<()> {
+    // A stream's size can't be known up front, so there's nothing to measure against
+    let bytes = match input {
+        Input::Files(paths) => paths
+            .iter()
+            .map(|path| Ok(std::fs::metadata(path)?.len()))
+            .sum::<std::io::Result<u64>>()?,
+        Input::Stdin => 0,
+    };
+    let seconds = elapsed.as_secs_f64();
+
+    eprintln!(
+        "rows={} bytes={} seconds={:.6} rows_per_sec={:.0} gb_per_sec={:.3}",
+        rows,
+        bytes,
+        seconds,
+        rows as f64 / seconds,
+        bytes as f64 / seconds / 1e9
     );
 
-    mmap
+    Ok(())
 }
 
-#[inline(always)]
-fn process_mapped_lines<'a>(start: i64, end: i64) -> impl Iterator<Item = (&'a str, Measurement)> {
-    let chunk_size = (end - start) as usize;
-    let mut buf = Vec::with_capacity(chunk_size);
-    let beginning = Instant::now();
+// Checks every line of the input without aggregating it, exiting with a failure if any are
+// malformed
+fn validate(args: &cli::Args) -> ! {
+    let mut count = 0;
+
+    match &args.input {
+        Input::Files(paths) => {
+            for path in paths {
+                let Ok(malformed) = validate_file(path, &args.options).inspect_err(|e| {
+                    eprintln!("Failed to read {}: {}", path.display(), e);
+                }) else {
+                    std::process::exit(1);
+                };
+
+                for line in &malformed {
+                    eprintln!(
+                        "Malformed line at byte {} of {}: {:?}",
+                        line.offset,
+                        path.display(),
+                        line.line
+                    );
+                }
+                count += malformed.len();
+            }
+        }
+        Input::Stdin => {
+            let Ok(malformed) = validate_stream(std::io::stdin().lock(), &args.options)
+                .inspect_err(|e| {
+                    eprintln!("Failed to read stdin: {}", e);
+                })
+            else {
+                std::process::exit(1);
+            };
 
-    let buf = unsafe {
-        let mut file = File::options()
-            .read(true)
-            .open("measurements.txt")
-            .unwrap_unchecked();
-        file.seek(SeekFrom::Start(start as u64)).unwrap_unchecked();
-        let mut take = file.take(chunk_size as u64);
+            for line in &malformed {
+                eprintln!("Malformed line at byte {}: {:?}", line.offset, line.line);
+            }
+            count += malformed.len();
+        }
+    }
 
-        take.read_to_end(&mut buf).unwrap_unchecked();
+    if count == 0 {
+        println!("Every line is valid");
+        std::process::exit(0);
+    }
 
-        let buf = buf.leak();
+    println!("Found {} malformed lines", count);
+    std::process::exit(1);
+}
 
-        // We know that the input is all valid utf8, so we can use unsafe to avoid the overhead of checking.
-        std::str::from_utf8_unchecked(buf)
+// Writes a file of random measurements for testing and benchmarking against
+fn generate() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match cli::GenerateArgs::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let destination: Box<dyn Write> = match &args.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(std::io::stdout().lock()),
     };
+    let mut writer = BufWriter::new(destination);
 
-    println!("Read {} bytes in {:?}", buf.len(), beginning.elapsed());
+    one_brc::generate(&mut writer, args.rows, args.seed)?;
+    writer.flush()?;
 
-    process_lines(buf)
+    Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /*
-     * Get the number of available cores on the machine
-     */
-    let available_parallelism = unsafe {
-        std::thread::available_parallelism()
-            .unwrap_unchecked()
-            .get()
+// Converts a text file into the binary format, which is much faster to aggregate again
+fn convert() -> Result<(), Box<dyn std::error::Error>> {
+    let args = match cli::ConvertArgs::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
     };
 
-    println!("Parallelism: {}", available_parallelism);
+    let reader: Box<dyn BufRead> = match &args.input {
+        Input::Files(paths) => match File::open(&paths[0]) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", paths[0].display(), e);
+                std::process::exit(1);
+            }
+        },
+        Input::Stdin => Box::new(std::io::stdin().lock()),
+    };
 
-    // Commenting this out while testing the memory map. Using a parallelism constant of 1 is
-    // required for testing to ensure that we don't end up with pointer overlap.
-    // let available_parallelism = available_parallelism * PARALLELISM_CONSTANT;
+    let destination: Box<dyn Write> = match &args.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("Failed to create {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(destination);
 
-    let mmap = memory_map(available_parallelism);
+    if let Err(e) = one_brc::convert(reader, &mut writer, &args.options) {
+        eprintln!("Failed to convert: {}", e);
+        std::process::exit(1);
+    }
+    writer.flush()?;
 
-    let handles = (0..available_parallelism)
-        .map(|thread_count| {
-            /*
-             * Now that we have a map of the file, we can spawn threads to read the file
-             * chunk by chunk in parallel.
-             */
-            let (start, end) = mmap[thread_count];
+    let skipped = args.options.skipped.load(Ordering::Relaxed);
+    if skipped > 0 {
+        eprintln!("Skipped {} malformed lines", skipped);
+    }
 
-            std::thread::spawn(move || process_mapped_lines(start, end))
-        })
-        .collect::<Vec<_>>();
+    Ok(())
+}
 
-    // Perform memory allocation while waiting for the threads to finish
-    let mut measurements = HashMap::<&str, Measurement>::with_capacity(10000);
+// Prints how far through the input we are every second, on stderr so that it stays out of the
+// results, until the returned sender is dropped
+fn report_progress(progress: Arc<Progress>) -> (mpsc::Sender<()>, JoinHandle<()>) {
+    let (finished, done) = mpsc::channel();
 
-    for handle in handles {
-        let result = unsafe { handle.join().unwrap_unchecked() };
+    let reporter = std::thread::spawn(move || {
+        while done.recv_timeout(Duration::from_secs(1)) == Err(RecvTimeoutError::Timeout) {
+            print_progress(&progress);
+        }
 
-        // While we're waiting for the threads to finish, we can perform the aggregation
-        for (city, measurement) in result {
-            let Some(item) = measurements.get_mut(&city) else {
-                measurements.insert(city, measurement);
-                continue;
-            };
+        print_progress(&progress);
+        eprintln!();
+    });
 
-            item.aggregate(&measurement);
-        }
-    }
+    (finished, reporter)
+}
 
-    let mut results = Vec::from_iter(measurements.iter());
-    results.sort_by(|a, b| a.0.cmp(&b.0));
-
-    // Create a buffer to write to stdout, this is faster than writing to stdout directly
-    let stdout = std::io::stdout();
-    let mut handle = stdout.lock();
-    let mut writer = BufWriter::new(&mut handle);
-
-    unsafe {
-        // Write the buffer to stdout
-        writer.write(b"{").unwrap_unchecked();
-        for (city, measurement) in results {
-            writer
-                .write_fmt(format_args!("{}={},", city, measurement))
-                .unwrap_unchecked();
-        }
-        // Removing the trailing comma
-        writer.write(b"\x08}").unwrap_unchecked();
-        writer.flush().unwrap_unchecked();
+fn print_progress(progress: &Progress) {
+    let processed = progress.processed();
+
+    // Streams don't have a size we can measure against
+    match progress.total() {
+        0 => eprint!("\rProcessed {} bytes", processed),
+        total => eprint!(
+            "\rProcessed {} of {} bytes ({:.1}%)",
+            processed,
+            total,
+            processed as f64 / total as f64 * 100.0
+        ),
     }
-
-    Ok(())
 }
@@ -0,0 +1,120 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+
+use crate::{
+    binary, cache, checkpoint,
+    chunk::{aggregate_chunks, aggregate_mapped, limit, memory_map},
+    compression::Compression,
+    Accumulator, Checkpoint, Measurement, Options,
+};
+
+/// A file mapped into memory, which can be aggregated without copying any station names out of
+/// it.
+///
+/// The results of [`MappedFile::aggregate`] borrow their names straight from the map, so they
+/// can't outlive it: the map stays mapped for as long as the `MappedFile` does, and the compiler
+/// won't let it be dropped while any of the results are still around. Copy the names out, as
+/// [`aggregate_file_with`](crate::aggregate_file_with) does, to keep results beyond that.
+pub struct MappedFile {
+    mmap: Mmap,
+    // Where the file was opened from, for its cache of chunks
+    path: PathBuf,
+}
+
+impl MappedFile {
+    /// Maps the file at `path`, which has to be uncompressed text or in the binary format, as a
+    /// compressed file can't be read in place.
+    pub fn open(path: &Path, options: &Options) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        if Compression::detect(&mut file)?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed files can't be mapped",
+            ));
+        }
+
+        Self::map(&file, path, options)
+    }
+
+    pub(crate) fn map(file: &File, path: &Path, options: &Options) -> io::Result<Self> {
+        Ok(Self {
+            mmap: memory_map(file, options)?,
+            path: path.to_owned(),
+        })
+    }
+
+    /// Aggregates every station in the file, splitting the work across the threads like
+    /// [`aggregate_file_with`](crate::aggregate_file_with), and returns the results sorted by
+    /// station name with the names borrowed from the map. Checkpoints aren't supported here, as
+    /// the stations they resume with aren't in the map.
+    pub fn aggregate(&self, options: &Options) -> io::Result<BTreeMap<&str, Measurement>> {
+        if options.checkpoint.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "checkpointed results can't borrow from the map",
+            ));
+        }
+
+        if binary::is_binary(&self.mmap) {
+            return binary::aggregate(&self.mmap, options);
+        }
+
+        self.aggregate_text(options)
+    }
+
+    // Like `aggregate`, but into any accumulator, which only works for text as the binary format
+    // can only be aggregated into measurements
+    pub(crate) fn aggregate_text<A: Accumulator>(
+        &self,
+        options: &Options,
+    ) -> io::Result<BTreeMap<&str, A>> {
+        if binary::is_binary(&self.mmap) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "binary files can only be aggregated into measurements",
+            ));
+        }
+
+        let bytes = self.text(options);
+        if !options.cache_chunks {
+            return Ok(aggregate_mapped(bytes, 0, options)?.into_iter().collect());
+        }
+
+        let chunks = cache::chunks(&self.path, bytes, options);
+
+        Ok(aggregate_chunks(bytes, &chunks, 0, options)?
+            .into_iter()
+            .collect())
+    }
+
+    pub(crate) fn aggregate_checkpointed(
+        &self,
+        checkpoint: &Checkpoint,
+        options: &Options,
+    ) -> io::Result<BTreeMap<String, Measurement>> {
+        if binary::is_binary(&self.mmap) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "binary files can't be checkpointed",
+            ));
+        }
+
+        checkpoint::aggregate(self.text(options), checkpoint, options)
+    }
+
+    // The part of the file that's to be aggregated as text, counted towards the progress total
+    fn text(&self, options: &Options) -> &[u8] {
+        let bytes = limit(&self.mmap, options.limit_bytes);
+        if let Some(progress) = &options.progress {
+            progress.add_total(bytes.len() as u64);
+        }
+
+        bytes
+    }
+}
@@ -0,0 +1,707 @@
+use std::{
+    fmt::{Display, Formatter},
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use crate::{checkpoint::read_bytes, histogram::Histogram};
+
+/*
+ * Every measurement is stored as a fixed-point integer of thousandths of a degree, eg -12.3 is
+ * stored as -12300. This keeps the hot path free of floating-point arithmetic and makes the
+ * aggregated results exact and reproducible regardless of how the work was split between threads.
+ *
+ * The challenge's measurements only have one decimal place, but instruments can be more precise
+ * than that. Thousandths keep up to three decimal places exact, while the output is rounded to
+ * tenths unless it's asked for with more decimal places.
+ */
+pub(crate) const SCALE: i64 = 1000;
+
+/*
+ * Rounds a value in thousandths of a degree to tenths, with halves going towards positive
+ * infinity as the challenge specifies: 2.55 becomes 2.6, but -2.55 becomes -2.5 and -0.05 becomes
+ * 0.0. Adding half a tenth and flooring does exactly that, and since it's all in integers no
+ * value ends up a hair either side of a half the way it could as a float.
+ */
+#[inline(always)]
+pub(crate) fn to_tenths(thousandths: i64) -> i64 {
+    Rounding::HalfUp.divide(thousandths as i128, 100)
+}
+
+/// How the min, max and mean are rounded to the precision they're written with, shown here for
+/// one decimal place.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Rounding {
+    /// To the nearest, with halves going towards positive infinity as the challenge specifies:
+    /// 2.25 becomes 2.3 and -2.25 becomes -2.2.
+    #[default]
+    HalfUp,
+    /// To the nearest, with halves going to the even neighbour as IEEE 754 does by default: 2.25
+    /// becomes 2.2, 2.35 becomes 2.4 and -2.25 becomes -2.2.
+    Nearest,
+    /// Towards positive infinity: 2.21 becomes 2.3 and -2.29 becomes -2.2.
+    Up,
+    /// Towards negative infinity: 2.29 becomes 2.2 and -2.21 becomes -2.3.
+    Down,
+    /// Towards zero, dropping the extra digits: 2.29 becomes 2.2 and -2.29 becomes -2.2.
+    TowardZero,
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-up" => Ok(Self::HalfUp),
+            "nearest" => Ok(Self::Nearest),
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "toward-zero" => Ok(Self::TowardZero),
+            _ => Err(format!(
+                "unknown rounding {:?}, expected one of: half-up, nearest, up, down, toward-zero",
+                s
+            )),
+        }
+    }
+}
+
+impl Rounding {
+    // Divides `numerator` by a positive `denominator`, rounding the quotient to a whole number.
+    // Everything stays in integers, so the result is exact however close it is to a half.
+    #[inline(always)]
+    pub(crate) fn divide(self, numerator: i128, denominator: i128) -> i64 {
+        let floor = numerator.div_euclid(denominator);
+        let remainder = numerator.rem_euclid(denominator);
+
+        let rounded = match self {
+            Self::HalfUp => (2 * numerator + denominator).div_euclid(2 * denominator),
+            Self::Nearest => match (2 * remainder).cmp(&denominator) {
+                std::cmp::Ordering::Less => floor,
+                std::cmp::Ordering::Greater => floor + 1,
+                std::cmp::Ordering::Equal => floor + floor.rem_euclid(2),
+            },
+            Self::Up => floor + (remainder != 0) as i128,
+            Self::Down => floor,
+            Self::TowardZero => numerator / denominator,
+        };
+
+        rounded as i64
+    }
+}
+
+#[derive(Clone)]
+pub struct Measurement {
+    min: i64,
+    max: i64,
+    sum: i64,
+    // Accumulated in integers like the sum, so the standard deviation doesn't suffer from the
+    // catastrophic cancellation that summing squares of floats would over a billion samples
+    sum_sq: u128,
+    count: u64,
+    // Only tracked when order statistics such as the median have been asked for
+    histogram: Option<Box<Histogram>>,
+    // The byte offset of the first line seen for the station, which is only tracked for debugging,
+    // with u64::MAX standing in for unknown so that the struct stays within a cache line
+    first_offset: u64,
+}
+
+// An empty measurement, which any recorded value will be both lower and higher than
+impl Default for Measurement {
+    fn default() -> Self {
+        Self {
+            min: i64::MAX,
+            max: i64::MIN,
+            sum: 0,
+            sum_sq: 0,
+            count: 0,
+            histogram: None,
+            first_offset: u64::MAX,
+        }
+    }
+}
+
+impl Measurement {
+    /// Records a single measurement, given in thousandths of a degree.
+    #[inline(always)]
+    pub fn record(&mut self, measurement: i32) {
+        let measurement = measurement as i64;
+
+        /*
+         * These used to be `if`s, on the grounds that the min and max rarely change, so the
+         * branches are nearly always predicted and the stores skipped. `min` and `max` are inlined
+         * like anything else and compile down to conditional moves, which cost the same whatever
+         * the data. Timed over 5 million rows on a single thread, the two were within noise of each
+         * other on the usual shuffled input, while on input sorted by measurement, where the max
+         * changes on every line, the conditional moves were 3-10% faster. In isolation, a
+         * station whose min and max keep on growing in turn took the branches 3x as long, which
+         * the updating_ benchmarks in benches/aggregate.rs compare.
+         */
+        self.min = self.min.min(measurement);
+        self.max = self.max.max(measurement);
+
+        self.sum += measurement;
+        self.sum_sq += (measurement * measurement) as u128;
+        self.count += 1;
+
+        if let Some(histogram) = &mut self.histogram {
+            histogram.record(to_tenths(measurement) as i32);
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn aggregate(&mut self, other: &Measurement) {
+        if other.min < self.min {
+            self.min = other.min;
+        }
+
+        if other.max > self.max {
+            self.max = other.max;
+        }
+
+        self.sum = self.sum.saturating_add(other.sum);
+        self.sum_sq = self.sum_sq.saturating_add(other.sum_sq);
+        self.count = self.count.saturating_add(other.count);
+        self.first_offset = self.first_offset.min(other.first_offset);
+
+        match (&mut self.histogram, &other.histogram) {
+            (Some(histogram), Some(other)) => histogram.merge(other),
+            (None, Some(other)) => self.histogram = Some(other.clone()),
+            _ => {}
+        }
+    }
+
+    /*
+     * Counts every measurement recorded so far `factor` times over, as if each stood for that many
+     * measurements of the same value. The min and max aren't affected, nor is anything averaged.
+     *
+     * A sum or count too big to hold saturates at its limit rather than wrapping around, and so do
+     * the merges that follow. With measurements within ±99.9 that takes a factor in the tens of
+     * trillions, far more lines than any file has, and while the mean and count are meaningless by
+     * then, the min and max are still right and nothing panics.
+     */
+    #[inline(always)]
+    pub(crate) fn scale(&mut self, factor: u64) {
+        self.sum = self
+            .sum
+            .saturating_mul(i64::try_from(factor).unwrap_or(i64::MAX));
+        self.sum_sq = self.sum_sq.saturating_mul(factor as u128);
+        self.count = self.count.saturating_mul(factor);
+
+        if let Some(histogram) = &mut self.histogram {
+            histogram.scale(factor);
+        }
+    }
+
+    // Starts a measurement that also builds a histogram of every value recorded
+    #[inline(always)]
+    pub(crate) fn with_histogram(measurement: i32) -> Self {
+        let mut histogram = Histogram::new();
+        histogram.record(to_tenths(measurement as i64) as i32);
+
+        Self {
+            histogram: Some(histogram),
+            ..Self::from(measurement)
+        }
+    }
+
+    // Writes every field, in little endian, for `read_from` to read back out of a checkpoint
+    pub(crate) fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&self.min.to_le_bytes())?;
+        writer.write_all(&self.max.to_le_bytes())?;
+        writer.write_all(&self.sum.to_le_bytes())?;
+        writer.write_all(&self.sum_sq.to_le_bytes())?;
+        writer.write_all(&self.count.to_le_bytes())?;
+
+        let Some(histogram) = &self.histogram else {
+            return writer.write_all(&[0]);
+        };
+
+        writer.write_all(&[1])?;
+        for bucket in histogram.buckets() {
+            writer.write_all(&bucket.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    // Whether it's building a histogram, which every measurement of an aggregation is or none are
+    pub(crate) fn has_histogram(&self) -> bool {
+        self.histogram.is_some()
+    }
+
+    pub(crate) fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let mut measurement = Self {
+            min: i64::from_le_bytes(read_bytes(reader)?),
+            max: i64::from_le_bytes(read_bytes(reader)?),
+            sum: i64::from_le_bytes(read_bytes(reader)?),
+            sum_sq: u128::from_le_bytes(read_bytes(reader)?),
+            count: u64::from_le_bytes(read_bytes(reader)?),
+            histogram: None,
+            first_offset: u64::MAX,
+        };
+
+        if read_bytes::<1>(reader)? == [1] {
+            let mut histogram = Histogram::new();
+            for bucket in histogram.buckets_mut() {
+                *bucket = u64::from_le_bytes(read_bytes(reader)?);
+            }
+            measurement.histogram = Some(histogram);
+        }
+
+        Ok(measurement)
+    }
+
+    /// The lowest recorded measurement.
+    pub fn min(&self) -> f64 {
+        self.min as f64 / SCALE as f64
+    }
+
+    /// The highest recorded measurement.
+    pub fn max(&self) -> f64 {
+        self.max as f64 / SCALE as f64
+    }
+
+    /// The arithmetic mean of every recorded measurement.
+    pub fn mean(&self) -> f64 {
+        self.sum as f64 / self.count as f64 / SCALE as f64
+    }
+
+    /// The number of recorded measurements.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The population standard deviation of every recorded measurement.
+    pub fn stddev(&self) -> f64 {
+        self.stddev_thousandths() / SCALE as f64
+    }
+
+    // Var(X) = E[X²] - E[X]², scaled up by count² so that everything but the square root is exact
+    #[inline(always)]
+    fn stddev_thousandths(&self) -> f64 {
+        let count = self.count as i128;
+        let sum = self.sum as i128;
+        let variance = count * self.sum_sq as i128 - sum * sum;
+
+        (variance as f64).sqrt() / self.count as f64
+    }
+
+    #[inline(always)]
+    pub(crate) fn stddev_fixed(&self, precision: Precision) -> Option<Fixed> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // Standard deviations are never negative so rounding towards positive is just rounding
+        let unit = precision.unit() as f64;
+        Some(precision.fixed((self.stddev_thousandths() / unit).round() as i64))
+    }
+
+    /// The byte offset of the first line seen for this station, which is only known when it was
+    /// aggregated with [`Options::debug_offsets`](crate::Options). It isn't saved in checkpoints.
+    pub fn first_offset(&self) -> Option<u64> {
+        (self.first_offset != u64::MAX).then_some(self.first_offset)
+    }
+
+    #[inline(always)]
+    pub(crate) fn set_first_offset(&mut self, offset: u64) {
+        self.first_offset = offset;
+    }
+
+    /// The median of every recorded measurement, to the nearest tenth of a degree. This is only
+    /// available when the results were aggregated with [`Options::histogram`](crate::Options), and
+    /// is None when nothing was recorded.
+    pub fn median(&self) -> Option<f64> {
+        self.median_tenths().map(|median| median as f64 / 10.0)
+    }
+
+    #[inline(always)]
+    pub(crate) fn median_tenths(&self) -> Option<i64> {
+        let histogram = self.histogram.as_ref()?;
+
+        (self.count > 0).then(|| histogram.median(self.count))
+    }
+
+    #[inline(always)]
+    pub(crate) fn median_fixed(&self, precision: Precision) -> Option<Fixed> {
+        self.median_tenths().map(|median| precision.tenths(median))
+    }
+
+    /// The most frequent measurement, to the nearest tenth of a degree, with ties going to the
+    /// lowest. Like the median this needs a histogram, and is None when nothing was recorded.
+    pub fn mode(&self) -> Option<f64> {
+        self.mode_tenths().map(|mode| mode as f64 / 10.0)
+    }
+
+    #[inline(always)]
+    pub(crate) fn mode_tenths(&self) -> Option<i64> {
+        let histogram = self.histogram.as_ref()?;
+
+        (self.count > 0).then(|| histogram.mode())
+    }
+
+    #[inline(always)]
+    pub(crate) fn mode_fixed(&self, precision: Precision) -> Option<Fixed> {
+        self.mode_tenths().map(|mode| precision.tenths(mode))
+    }
+
+    /// The given percentile, from 0 to 100, of every recorded measurement. Like the median this
+    /// needs a histogram, and so is only known to the nearest tenth of a degree. It's None when
+    /// nothing was recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<f64> {
+        self.percentile_tenths(percentile)
+            .map(|tenths| tenths / 10.0)
+    }
+
+    #[inline(always)]
+    fn percentile_tenths(&self, percentile: f64) -> Option<f64> {
+        let histogram = self.histogram.as_ref()?;
+
+        (self.count > 0).then(|| histogram.percentile(self.count, percentile))
+    }
+
+    #[inline(always)]
+    pub(crate) fn percentile_fixed(&self, percentile: f64, precision: Precision) -> Option<Fixed> {
+        let tenths = self.percentile_tenths(percentile)?;
+        let units = tenths * 100.0 / precision.unit() as f64;
+
+        // Rounding half towards positive infinity, to match the mean
+        Some(precision.fixed((units + 0.5).floor() as i64))
+    }
+
+    /*
+     * A measurement with nothing recorded, such as a station that was asked for but never seen,
+     * has no statistics at all. These return None for it rather than a misleading zero.
+     */
+    #[inline(always)]
+    pub(crate) fn min_fixed(&self, precision: Precision) -> Option<Fixed> {
+        (self.count > 0).then(|| precision.round(self.min as i128, 1))
+    }
+
+    #[inline(always)]
+    pub(crate) fn max_fixed(&self, precision: Precision) -> Option<Fixed> {
+        (self.count > 0).then(|| precision.round(self.max as i128, 1))
+    }
+
+    // The mean, rounded straight from the sum so that it's exact
+    #[inline(always)]
+    pub(crate) fn mean_fixed(&self, precision: Precision) -> Option<Fixed> {
+        (self.count > 0).then(|| precision.round(self.sum as i128, self.count as i128))
+    }
+
+    /// The min, max and mean in the challenge's format, like the `Display` impl, but rounded to
+    /// tenths as `rounding` says rather than always half up.
+    pub fn rounded(&self, rounding: Rounding) -> impl Display + '_ {
+        self.display(Precision {
+            rounding,
+            ..Precision::default()
+        })
+    }
+
+    /// The min, max and mean in the challenge's format, like the `Display` impl, but written with
+    /// the given precision.
+    pub fn display(&self, precision: Precision) -> impl Display + '_ {
+        Displayed(self, precision)
+    }
+}
+
+impl From<i32> for Measurement {
+    #[inline(always)]
+    fn from(measurement: i32) -> Self {
+        let measurement = measurement as i64;
+
+        Self {
+            min: measurement,
+            max: measurement,
+            sum: measurement,
+            sum_sq: (measurement * measurement) as u128,
+            count: 1,
+            histogram: None,
+            first_offset: u64::MAX,
+        }
+    }
+}
+
+/// How many decimal places statistics are written with, and how they're rounded to them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Precision {
+    /// From 0 to 3, the most that measurements are stored with. Anything more is treated as 3.
+    /// The median, mode and percentiles are only known to a tenth of a degree, so any places
+    /// beyond the first are always zero for them.
+    pub decimals: u32,
+    pub rounding: Rounding,
+}
+
+// One decimal place rounded half up, as the challenge specifies
+impl Default for Precision {
+    fn default() -> Self {
+        Self {
+            decimals: 1,
+            rounding: Rounding::default(),
+        }
+    }
+}
+
+impl Precision {
+    // How many thousandths of a degree the last decimal place stands for
+    #[inline(always)]
+    fn unit(self) -> i64 {
+        10_i64.pow(3 - self.decimals.min(3))
+    }
+
+    #[inline(always)]
+    fn fixed(self, units: i64) -> Fixed {
+        Fixed(units, self.decimals.min(3))
+    }
+
+    // Rounds `thousandths / count` to this many decimal places
+    #[inline(always)]
+    fn round(self, thousandths: i128, count: i128) -> Fixed {
+        self.fixed(
+            self.rounding
+                .divide(thousandths, self.unit() as i128 * count),
+        )
+    }
+
+    // A statistic that's only known in tenths, such as the median, at this many decimal places.
+    // Like the other statistics read from the histogram it's always rounded half up.
+    #[inline(always)]
+    fn tenths(self, tenths: i64) -> Fixed {
+        let half_up = Self {
+            rounding: Rounding::HalfUp,
+            ..self
+        };
+
+        half_up.round(tenths as i128 * 100, 1)
+    }
+}
+
+// Formats a value in units of the given number of decimal places, to exactly that many places.
+pub(crate) struct Fixed(pub(crate) i64, pub(crate) u32);
+
+impl Display for Fixed {
+    #[inline(always)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Self(value, decimals) = *self;
+
+        // Zero is never negative, so unlike floats we can't end up printing -0.0
+        let sign = if value < 0 { "-" } else { "" };
+        let abs = value.unsigned_abs();
+        let scale = 10_u64.pow(decimals);
+
+        match decimals {
+            0 => write!(f, "{}{}", sign, abs),
+            _ => write!(
+                f,
+                "{}{}.{:0width$}",
+                sign,
+                abs / scale,
+                abs % scale,
+                width = decimals as usize
+            ),
+        }
+    }
+}
+
+// The nearest float to the value, for formats that store numbers rather than write them out
+#[cfg(feature = "parquet")]
+impl From<Fixed> for f64 {
+    fn from(Fixed(value, decimals): Fixed) -> Self {
+        value as f64 / 10_f64.powi(decimals as i32)
+    }
+}
+
+// Formats an optional statistic, falling back to the given placeholder when it's unavailable
+pub(crate) struct Optional<'a>(pub(crate) Option<Fixed>, pub(crate) &'a str);
+
+impl Display for Optional<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(fixed) => fixed.fmt(f),
+            None => f.write_str(self.1),
+        }
+    }
+}
+
+struct Displayed<'a>(&'a Measurement, Precision);
+
+impl Display for Displayed<'_> {
+    #[inline(always)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Self(measurement, precision) = *self;
+
+        write!(
+            f,
+            "{}/{}/{}",
+            Optional(measurement.min_fixed(precision), "NA"),
+            Optional(measurement.max_fixed(precision), "NA"),
+            Optional(measurement.mean_fixed(precision), "NA")
+        )
+    }
+}
+
+impl Display for Measurement {
+    #[inline(always)]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Displayed(self, Precision::default()).fmt(f)
+    }
+}
+
+/*
+ * An empty measurement's min and max are sentinels rather than values, so they're serialized as
+ * missing instead, in thousandths of a degree like everything else. Deserializing makes sure that
+ * what we get back is a measurement that could have been recorded: the min and max are there
+ * exactly when something was, and a histogram has a bucket for every tenth, with the same count.
+ *
+ * The impls are written out by hand, as the crate is built with a static relocation model that
+ * proc macros such as serde's derives can't be linked with.
+ */
+#[cfg(feature = "serde")]
+mod serialize {
+    use std::fmt::Formatter;
+
+    use serde::{
+        de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+        ser::SerializeStruct,
+        Deserialize, Deserializer, Serialize, Serializer,
+    };
+
+    use super::{Histogram, Measurement};
+
+    const FIELDS: &[&str] = &["min", "max", "sum", "sum_sq", "count", "histogram"];
+
+    impl Serialize for Measurement {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let recorded = self.count > 0;
+
+            let mut state = serializer.serialize_struct("Measurement", FIELDS.len())?;
+            state.serialize_field("min", &recorded.then_some(self.min))?;
+            state.serialize_field("max", &recorded.then_some(self.max))?;
+            state.serialize_field("sum", &self.sum)?;
+            state.serialize_field("sum_sq", &self.sum_sq)?;
+            state.serialize_field("count", &self.count)?;
+            state.serialize_field(
+                "histogram",
+                &self.histogram.as_ref().map(|histogram| histogram.buckets()),
+            )?;
+            state.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Measurement {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_struct("Measurement", FIELDS, MeasurementVisitor)
+        }
+    }
+
+    // Every field as it was serialized, before we know that they make a valid measurement
+    struct Fields {
+        min: Option<i64>,
+        max: Option<i64>,
+        sum: i64,
+        sum_sq: u128,
+        count: u64,
+        histogram: Option<Vec<u64>>,
+    }
+
+    impl TryFrom<Fields> for Measurement {
+        type Error = String;
+
+        fn try_from(fields: Fields) -> Result<Self, Self::Error> {
+            let (min, max) =
+                match (fields.min, fields.max, fields.count) {
+                    (None, None, 0) => (i64::MAX, i64::MIN),
+                    (Some(min), Some(max), 1..) if min <= max => (min, max),
+                    _ => return Err(
+                        "expected a min no higher than the max, exactly when the count isn't zero"
+                            .to_owned(),
+                    ),
+                };
+
+            let histogram = match fields.histogram {
+                Some(buckets) => {
+                    let mut histogram = Histogram::new();
+                    if buckets.len() != histogram.buckets().len()
+                        || buckets.iter().sum::<u64>() != fields.count
+                    {
+                        return Err(format!(
+                            "expected a histogram of {} buckets adding up to the count",
+                            histogram.buckets().len()
+                        ));
+                    }
+
+                    histogram.buckets_mut().copy_from_slice(&buckets);
+                    Some(histogram)
+                }
+                None => None,
+            };
+
+            Ok(Self {
+                min,
+                max,
+                sum: fields.sum,
+                sum_sq: fields.sum_sq,
+                count: fields.count,
+                histogram,
+                first_offset: u64::MAX,
+            })
+        }
+    }
+
+    struct MeasurementVisitor;
+
+    impl<'de> Visitor<'de> for MeasurementVisitor {
+        type Value = Measurement;
+
+        fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            f.write_str("a measurement")
+        }
+
+        // Formats without field names, such as bincode, serialize structs as a sequence
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Measurement, A::Error> {
+            let missing = |i| de::Error::invalid_length(i, &"a measurement of 6 fields");
+
+            Fields {
+                min: seq.next_element()?.ok_or_else(|| missing(0))?,
+                max: seq.next_element()?.ok_or_else(|| missing(1))?,
+                sum: seq.next_element()?.ok_or_else(|| missing(2))?,
+                sum_sq: seq.next_element()?.ok_or_else(|| missing(3))?,
+                count: seq.next_element()?.ok_or_else(|| missing(4))?,
+                histogram: seq.next_element()?.ok_or_else(|| missing(5))?,
+            }
+            .try_into()
+            .map_err(de::Error::custom)
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Measurement, A::Error> {
+            let (mut min, mut max, mut histogram) = (None, None, None);
+            let (mut sum, mut sum_sq, mut count) = (None, None, None);
+
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "min" => min = map.next_value()?,
+                    "max" => max = map.next_value()?,
+                    "sum" => sum = Some(map.next_value()?),
+                    "sum_sq" => sum_sq = Some(map.next_value()?),
+                    "count" => count = Some(map.next_value()?),
+                    "histogram" => histogram = map.next_value()?,
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+            }
+
+            // The optional fields can be left out altogether, but the rest have to be there
+            Fields {
+                min,
+                max,
+                sum: sum.ok_or_else(|| de::Error::missing_field("sum"))?,
+                sum_sq: sum_sq.ok_or_else(|| de::Error::missing_field("sum_sq"))?,
+                count: count.ok_or_else(|| de::Error::missing_field("count"))?,
+                histogram,
+            }
+            .try_into()
+            .map_err(de::Error::custom)
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::{Checkpoint, ChunkStats, Progress};
+
+/// What to do about a line that can't be parsed, such as one without a delimiter or with a
+/// measurement that isn't a number.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum OnError {
+    /// Stop at the first malformed line, with an error giving its byte offset.
+    #[default]
+    Fail,
+    /// Leave malformed lines out, counting them in [`Options::skipped`].
+    Skip,
+    /// Like [`OnError::Skip`], but also print each malformed line and its byte offset to stderr.
+    Log,
+}
+
+/// How much help to give the kernel's readahead when reading a mapped file.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Readahead {
+    /// Leave readahead to the kernel's defaults.
+    #[default]
+    Default,
+    /// Tell the kernel that the file is read sequentially, so it can read further ahead and drop
+    /// pages once they've been read.
+    Sequential,
+    /// Like [`Readahead::Sequential`], but also ask for the whole file to be read in straight
+    /// away.
+    WillNeed,
+}
+
+/// Controls how measurements are aggregated.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The number of threads to split a file between.
+    pub parallelism: usize,
+    /// How many chunks to split a file into for each thread, which the threads share out between
+    /// themselves as they go. More, smaller chunks balance the work better when some parts of a
+    /// file are slower to aggregate than others, at the cost of merging more results.
+    pub chunks_per_thread: usize,
+    /// Files smaller than this many bytes are read in one go and aggregated on the calling thread,
+    /// rather than mapped and split between the threads, which for a small file takes longer than
+    /// it saves. Zero splits every file. Checkpointed files are always mapped.
+    pub min_parallel_bytes: usize,
+    /// Whether to build a histogram for each station, which is needed for order statistics such
+    /// as the median. This costs about 16KB per station per thread.
+    pub histogram: bool,
+    /// The byte separating each station name from its measurement.
+    pub delimiter: u8,
+    /// Skip checking that a file is valid UTF-8, other than at the edges of each chunk. This
+    /// saves a pass over the file, but invalid input then leads to undefined behaviour.
+    pub trust_utf8: bool,
+    /// Roughly how many distinct stations to expect, which every map of stations is sized for up
+    /// front so that it doesn't have to grow while we're aggregating.
+    pub stations_hint: usize,
+    /// Print how long each step takes, and how the file was split, to stderr as the aggregation
+    /// goes.
+    pub verbose: bool,
+    /// Where to publish how much of the input has been aggregated, if anywhere.
+    pub progress: Option<Arc<Progress>>,
+    /// Where to collect how long each chunk took and how many lines it held, if anywhere. Streams
+    /// aren't split into chunks, so they aren't collected.
+    pub chunk_stats: Option<Arc<ChunkStats>>,
+    /// What to do about malformed lines.
+    pub on_error: OnError,
+    /// How many malformed lines have been skipped. It's shared between clones of the options.
+    pub skipped: Arc<AtomicU64>,
+    /// Set to stop the aggregation early, such as when the user presses Ctrl-C. Every thread stops
+    /// at a line boundary soon after, and the results cover whatever had been aggregated by then,
+    /// which is still every measurement of those lines. Binary files only stop between chunks.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Where and how often to save the aggregation so far, so that a run that's cut short can be
+    /// resumed. Only a single uncompressed file can be checkpointed.
+    pub checkpoint: Option<Checkpoint>,
+    /// Only aggregate the whole lines within the first this many bytes of each input, such as for
+    /// a quick run against part of a huge file. Binary files stop at the last whole record within
+    /// it instead.
+    pub limit_bytes: Option<usize>,
+    /// Only aggregate every this many lines, starting with the first of each chunk, for a quick
+    /// estimate across the whole of a huge file. Each line that's aggregated is counted this many
+    /// times over, so the counts estimate the whole file's and the means are unchanged in
+    /// expectation, but the statistics are only as accurate as the sample. The min and max are
+    /// only those of the lines sampled, and malformed lines are only noticed if they're sampled.
+    /// Which lines those are depends on where the chunks start, and so on the number of threads.
+    /// Binary files sample their records the same way. Sampled runs can't be checkpointed. A rate
+    /// so high that the scaled up sums and counts no longer fit, in the tens of trillions, leaves
+    /// them at their maximums rather than wrapping around.
+    pub sample: Option<usize>,
+    /// Track the byte offset at which each station is first seen, as
+    /// [`Measurement::first_offset`](crate::Measurement::first_offset), for checking how a file was
+    /// split up. Nothing is tracked on the path taken by every line unless this is set.
+    pub debug_offsets: bool,
+    /// Fail on the first line that breaks the challenge's rules, even though it could be
+    /// aggregated, with its byte offset and which rule it breaks. See
+    /// [`try_parse_line_strict`](crate::try_parse_line_strict) for what those are. Lines that
+    /// can't be aggregated at all are still dealt with according to [`Options::on_error`]. Binary
+    /// files aren't checked.
+    pub strict: bool,
+    /// Read lines with the measurement before the station's name, as in `12.3;Tokyo`, rather than
+    /// after it. This can't be combined with [`Options::strict`], as the challenge's rules have
+    /// the measurement last.
+    pub value_first: bool,
+    /// Ask for the file to be mapped with huge pages, which only does anything on Linux, and only
+    /// for files on a filesystem that supports them.
+    pub huge_pages: bool,
+    /// How to advise the kernel's readahead for a mapped file. Like huge pages this is only a
+    /// hint, and is ignored where it isn't supported.
+    pub readahead: Readahead,
+    /// Pin each thread to a core of its own, so that on a machine with several NUMA nodes a
+    /// thread and the memory it allocates stay on the same node.
+    pub numa: bool,
+    /// Start each chunk of a text file at the first line past a multiple of this many bytes, for
+    /// a file on a striped array such as RAID 0 with this stripe size, so that no thread's reads
+    /// start partway through a stripe and straddle two of the disks. As the
+    /// threads take the chunks in order, raising [`Options::chunks_per_thread`] has them read
+    /// interleaved stripes rather than long runs of their own. It only helps where the disks are
+    /// the bottleneck, and on a single disk or with the file already in the page cache it's
+    /// just slightly unequal chunks.
+    pub stripe_size: Option<usize>,
+    /// Read files with `O_DIRECT`, bypassing the page cache, such as to benchmark a cold run
+    /// against the device itself. Each file is streamed from start to end on a single thread
+    /// like stdin, rather than being mapped and split, as direct reads have to be aligned to the
+    /// device's blocks. Only supported on Linux, and only on filesystems that allow it. Binary
+    /// files and checkpoints can't be read this way.
+    pub direct: bool,
+    /// Save where a file was split into chunks to a sidecar file next to it, named after it with a
+    /// `.chunks` suffix, and reuse them while the file's length and time of modification are
+    /// unchanged. Only uncompressed text files opened by path are cached.
+    pub cache_chunks: bool,
+}
+
+// Enough for a thread that's been given slow chunks to be caught up on by the others
+const DEFAULT_CHUNKS_PER_THREAD: usize = 4;
+
+/*
+ * Starting the threads, mapping the file and merging what they found costs a millisecond or two
+ * whatever the size of the file, which is about how long a single thread takes over a megabyte.
+ * Much past that the threads win, however many of them there are.
+ */
+const DEFAULT_MIN_PARALLEL_BYTES: usize = 1 << 20;
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            parallelism: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            chunks_per_thread: DEFAULT_CHUNKS_PER_THREAD,
+            histogram: false,
+            delimiter: b';',
+            trust_utf8: false,
+            // The challenge allows for up to 10,000 stations
+            stations_hint: 10000,
+            verbose: false,
+            progress: None,
+            chunk_stats: None,
+            on_error: OnError::Fail,
+            skipped: Arc::default(),
+            stop: None,
+            checkpoint: None,
+            limit_bytes: None,
+            sample: None,
+            debug_offsets: false,
+            strict: false,
+            value_first: false,
+            huge_pages: false,
+            readahead: Readahead::Default,
+            numa: false,
+            stripe_size: None,
+            min_parallel_bytes: DEFAULT_MIN_PARALLEL_BYTES,
+            direct: false,
+            cache_chunks: false,
+        }
+    }
+}
+
+impl Options {
+    // Whether the aggregation has been asked to stop early
+    #[inline(always)]
+    pub(crate) fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,623 @@
+use std::{
+    cmp::Ordering,
+    io::{self, Write},
+    str::FromStr,
+};
+
+use crate::{
+    measurement::{Fixed, Optional},
+    Measurement, Precision,
+};
+
+/// The formats the aggregated results can be written in.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// The challenge's `{Abha=-23.0/18.0/59.2, Abidjan=-16.2/26.0/67.3, ...}` format.
+    #[default]
+    OneBrc,
+    /// A JSON object mapping each station to its min, max, mean and count.
+    Json,
+    /// A `city,min,max,mean,count` header followed by one row per station. Names are quoted as
+    /// RFC 4180 says, but lines end in `\n` rather than its `\r\n`, like the other formats and
+    /// most tools on Unix. Readers of CSV, spreadsheets included, accept either.
+    Csv,
+    /// A `city\tmin\tmax\tmean` row per station, with no header and no quoting, for `awk` and
+    /// `cut`. Tabs, line breaks and backslashes in station names are escaped as `\t`, `\n`, `\r`
+    /// and `\\`, so every row has the same number of columns.
+    Tsv,
+    /// A Parquet file of a single table, with `station`, `min`, `max`, `mean` and `count` columns
+    /// and nothing else, whatever other statistics were asked for. The count is a `u64` and the
+    /// rest of the statistics `f64`s, which are null for a station without any measurements.
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1brc" => Ok(Self::OneBrc),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            "tsv" => Ok(Self::Tsv),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(Self::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => {
+                Err("parquet output requires building with the parquet feature".to_owned())
+            }
+            #[cfg(feature = "parquet")]
+            _ => Err(format!(
+                "unknown format {:?}, expected one of: 1brc, json, csv, tsv, parquet",
+                s
+            )),
+            #[cfg(not(feature = "parquet"))]
+            _ => Err(format!(
+                "unknown format {:?}, expected one of: 1brc, json, csv, tsv",
+                s
+            )),
+        }
+    }
+}
+
+/// The orders the aggregated results can be written in.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Sort {
+    /// Ascending by station name, in UTF-8 byte order as the challenge expects. This is the same
+    /// order as comparing the names a Unicode scalar value at a time, so uppercase letters come
+    /// before lowercase ones, and accented letters after every unaccented one, with `Zürich`
+    /// sorting after `Zwolle`.
+    #[default]
+    Name,
+    /// Ascending by station name, in an order closer to what a reader expects than the
+    /// challenge's, though without any locale's rules. See [`unicode_cmp`].
+    Unicode,
+    /// Descending by station name.
+    NameDesc,
+    /// Highest mean first.
+    Mean,
+    /// Highest max first.
+    Max,
+    /// Most measurements first.
+    Count,
+}
+
+impl FromStr for Sort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Self::Name),
+            "name-desc" => Ok(Self::NameDesc),
+            "unicode" => Ok(Self::Unicode),
+            "mean" => Ok(Self::Mean),
+            "max" => Ok(Self::Max),
+            "count" => Ok(Self::Count),
+            _ => Err(format!(
+                "unknown sort order {:?}, expected one of: name, name-desc, unicode, mean, max, count",
+                s
+            )),
+        }
+    }
+}
+
+type Entry<'a> = (&'a String, &'a Measurement);
+
+impl Sort {
+    /// A comparator for sorting results into this order. Stations with equal values are ordered
+    /// by name so that the output is deterministic.
+    pub fn comparator(self) -> impl Fn(&Entry, &Entry) -> Ordering {
+        move |a, b| match self {
+            Self::Name => a.0.cmp(b.0),
+            Self::NameDesc => b.0.cmp(a.0),
+            Self::Unicode => unicode_cmp(a.0, b.0),
+            Self::Mean => b.1.mean().total_cmp(&a.1.mean()).then(a.0.cmp(b.0)),
+            Self::Max => b.1.max().total_cmp(&a.1.max()).then(a.0.cmp(b.0)),
+            Self::Count => b.1.count().cmp(&a.1.count()).then(a.0.cmp(b.0)),
+        }
+    }
+}
+
+/// Compares station names ignoring case and the accents of Latin letters, falling back to UTF-8
+/// byte order for names that only differ in those, so that `Zürich` sorts between `Zagreb` and
+/// `Zwolle`, and `zagreb` right after `Zagreb`.
+///
+/// This isn't the Unicode Collation Algorithm, and no locale's tailoring is applied. Letters in the
+/// Latin-1 Supplement and Latin Extended-A blocks are compared as their unaccented base letters,
+/// with `æ`, `œ`, `ĳ`, `ß` and `þ` spelled out as `ae`, `oe`, `ij`, `ss` and `th`. Every other
+/// character is compared as its lowercase Unicode scalar value, so names in scripts like CJK
+/// keep the same relative order as `Sort::Name` gives them.
+pub fn unicode_cmp(a: &str, b: &str) -> Ordering {
+    let primary = |name: &str| {
+        let mut key = String::with_capacity(name.len());
+        for c in name.chars().flat_map(char::to_lowercase) {
+            match base_letters(c) {
+                Some(base) => key.push_str(base),
+                None => key.push(c),
+            }
+        }
+
+        key
+    };
+
+    primary(a).cmp(&primary(b)).then(a.cmp(b))
+}
+
+// The unaccented spelling of a lowercase Latin letter with an accent
+fn base_letters(c: char) -> Option<&'static str> {
+    Some(match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ð' | 'ď' | 'đ' => "d",
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĳ' => "ij",
+        'ĵ' => "j",
+        'ķ' | 'ĸ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' | 'ŉ' | 'ŋ' => "n",
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ß' | 'ś' | 'ŝ' | 'ş' | 'š' | 'ſ' => "s",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    })
+}
+
+/// Controls which statistics are written beyond the min, max and mean.
+#[derive(Clone, Default, Debug)]
+pub struct OutputOptions {
+    /// Write the number of measurements of each station. The JSON and CSV formats always include
+    /// it, so this only affects the challenge's format.
+    pub count: bool,
+    /// Write the median of each station, which requires the results to have been aggregated with
+    /// [`Options::histogram`](crate::Options).
+    pub median: bool,
+    /// Write the most frequent measurement of each station, breaking ties towards the lowest. Like
+    /// the median this requires [`Options::histogram`](crate::Options), and so is to the nearest
+    /// tenth of a degree.
+    pub mode: bool,
+    /// Write the population standard deviation of each station.
+    pub stddev: bool,
+    /// Write each of these percentiles, from 0 to 100, for each station. Like the median these
+    /// require the results to have been aggregated with [`Options::histogram`](crate::Options).
+    pub percentiles: Vec<f64>,
+    /// How many decimal places every statistic is written with, and how the min, max and mean are
+    /// rounded to them. The other statistics are always rounded half up like the challenge's.
+    pub precision: Precision,
+    /// Write the byte offset at which each station was first seen, which requires the results to
+    /// have been aggregated with [`Options::debug_offsets`](crate::Options).
+    pub offsets: bool,
+}
+
+// Below this many stations for each worker, formatting them costs less than handing them out
+const MIN_ROWS_PER_WORKER: usize = 64 * 1024;
+
+impl Format {
+    /// Writes the results, which are expected to already be sorted, to `writer`.
+    pub fn write<'a>(
+        self,
+        writer: &mut impl Write,
+        results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        self.write_header(writer, options)?;
+        self.write_rows(writer, results, true, options)?;
+        self.write_footer(writer)
+    }
+
+    /// Like [`Format::write`], but with the results split into ranges that are each formatted on
+    /// one of up to `parallelism` threads, then written in order. This only pays off for results
+    /// with hundreds of thousands of stations, so fewer than that are just written as usual.
+    pub fn write_parallel(
+        self,
+        writer: &mut impl Write,
+        results: &[(&String, &Measurement)],
+        options: &OutputOptions,
+        parallelism: usize,
+    ) -> io::Result<()> {
+        let workers = match self {
+            // A Parquet file describes every row at its end, so it can't be put together in pieces
+            #[cfg(feature = "parquet")]
+            Self::Parquet => 1,
+            _ => parallelism.clamp(1, (results.len() / MIN_ROWS_PER_WORKER).max(1)),
+        };
+        if workers == 1 {
+            return self.write(writer, results.iter().copied(), options);
+        }
+
+        self.write_header(writer, options)?;
+        std::thread::scope(|scope| {
+            let workers = results
+                .chunks(results.len().div_ceil(workers))
+                .enumerate()
+                .map(|(i, range)| {
+                    scope.spawn(move || {
+                        let mut buffer = Vec::new();
+                        self.write_rows(&mut buffer, range.iter().copied(), i == 0, options)?;
+
+                        Ok::<_, io::Error>(buffer)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // Each range is written as soon as it's ready, while the ones after it are still going
+            for worker in workers {
+                writer.write_all(&worker.join().unwrap()?)?;
+            }
+
+            Ok::<_, io::Error>(())
+        })?;
+        self.write_footer(writer)
+    }
+
+    fn write_header(self, writer: &mut impl Write, options: &OutputOptions) -> io::Result<()> {
+        match self {
+            Self::OneBrc | Self::Json => writer.write_all(b"{"),
+            Self::Csv => write_csv_header(writer, options),
+            Self::Tsv => Ok(()),
+            #[cfg(feature = "parquet")]
+            Self::Parquet => Ok(()),
+        }
+    }
+
+    // Writes a run of rows, where `first` is whether they start the results, as the rows of some
+    // formats are separated from the ones before them
+    fn write_rows<'a>(
+        self,
+        writer: &mut impl Write,
+        results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+        first: bool,
+        options: &OutputOptions,
+    ) -> io::Result<()> {
+        match self {
+            Self::OneBrc => write_one_brc(writer, results, first, options),
+            Self::Json => write_json(writer, results, first, options),
+            Self::Csv => write_csv(writer, results, options),
+            Self::Tsv => write_tsv(writer, results, options),
+            // Only ever given every row at once, by `write`
+            #[cfg(feature = "parquet")]
+            Self::Parquet => crate::arrow::write_parquet(writer, results, options),
+        }
+    }
+
+    fn write_footer(self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            // Ending the line so that the results are a line of their own
+            Self::OneBrc | Self::Json => writer.write_all(b"}\n"),
+            Self::Csv | Self::Tsv => Ok(()),
+            #[cfg(feature = "parquet")]
+            Self::Parquet => Ok(()),
+        }
+    }
+}
+
+fn write_one_brc<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    first: bool,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    for (i, (city, measurement)) in results.into_iter().enumerate() {
+        if i > 0 || !first {
+            writer.write_all(b", ")?;
+        }
+
+        write!(
+            writer,
+            "{}={}",
+            city,
+            measurement.display(options.precision)
+        )?;
+        if options.count {
+            write!(writer, "/{}", measurement.count())?;
+        }
+        if options.median {
+            write!(
+                writer,
+                "/{}",
+                Optional(measurement.median_fixed(options.precision), "NA")
+            )?;
+        }
+        if options.mode {
+            write!(
+                writer,
+                "/{}",
+                Optional(measurement.mode_fixed(options.precision), "NA")
+            )?;
+        }
+        if options.stddev {
+            write!(
+                writer,
+                "/{}",
+                Optional(measurement.stddev_fixed(options.precision), "NA")
+            )?;
+        }
+        for &percentile in &options.percentiles {
+            write!(
+                writer,
+                "/{}",
+                Optional(
+                    measurement.percentile_fixed(percentile, options.precision),
+                    "NA"
+                )
+            )?;
+        }
+        if options.offsets {
+            write!(writer, "/{}", Optional(offset(measurement), "NA"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_json<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    first: bool,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    for (i, (city, measurement)) in results.into_iter().enumerate() {
+        if i > 0 || !first {
+            writer.write_all(b",")?;
+        }
+
+        write_json_string(writer, city)?;
+        write!(
+            writer,
+            r#":{{"min":{},"max":{},"mean":{},"count":{}"#,
+            Optional(measurement.min_fixed(options.precision), "null"),
+            Optional(measurement.max_fixed(options.precision), "null"),
+            Optional(measurement.mean_fixed(options.precision), "null"),
+            measurement.count()
+        )?;
+        if options.median {
+            write!(
+                writer,
+                r#","median":{}"#,
+                Optional(measurement.median_fixed(options.precision), "null")
+            )?;
+        }
+        if options.mode {
+            write!(
+                writer,
+                r#","mode":{}"#,
+                Optional(measurement.mode_fixed(options.precision), "null")
+            )?;
+        }
+        if options.stddev {
+            write!(
+                writer,
+                r#","stddev":{}"#,
+                Optional(measurement.stddev_fixed(options.precision), "null")
+            )?;
+        }
+        for &percentile in &options.percentiles {
+            write!(
+                writer,
+                r#","p{}":{}"#,
+                percentile,
+                Optional(
+                    measurement.percentile_fixed(percentile, options.precision),
+                    "null"
+                )
+            )?;
+        }
+        if options.offsets {
+            write!(
+                writer,
+                r#","offset":{}"#,
+                Optional(offset(measurement), "null")
+            )?;
+        }
+        writer.write_all(b"}")?;
+    }
+
+    Ok(())
+}
+
+// The offset a station was first seen at, as a statistic with no decimal places
+fn offset(measurement: &Measurement) -> Option<Fixed> {
+    Some(Fixed(measurement.first_offset()? as i64, 0))
+}
+
+fn write_json_string(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    writer.write_all(b"\"")?;
+
+    // Write the string in runs, only breaking them up for the characters that need escaping
+    let mut run_start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if (c as u32) < 0x20 => "",
+            _ => continue,
+        };
+
+        writer.write_all(&s.as_bytes()[run_start..i])?;
+        if escaped.is_empty() {
+            write!(writer, "\\u{:04x}", c as u32)?;
+        } else {
+            writer.write_all(escaped.as_bytes())?;
+        }
+        run_start = i + c.len_utf8();
+    }
+
+    writer.write_all(&s.as_bytes()[run_start..])?;
+    writer.write_all(b"\"")
+}
+
+fn write_csv_header(writer: &mut impl Write, options: &OutputOptions) -> io::Result<()> {
+    writer.write_all(b"city,min,max,mean,count")?;
+    if options.median {
+        writer.write_all(b",median")?;
+    }
+    if options.mode {
+        writer.write_all(b",mode")?;
+    }
+    if options.stddev {
+        writer.write_all(b",stddev")?;
+    }
+    for percentile in &options.percentiles {
+        write!(writer, ",p{}", percentile)?;
+    }
+    if options.offsets {
+        writer.write_all(b",offset")?;
+    }
+    writer.write_all(b"\n")
+}
+
+fn write_csv<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    for (city, measurement) in results {
+        write_csv_field(writer, city)?;
+        write!(
+            writer,
+            ",{},{},{},{}",
+            Optional(measurement.min_fixed(options.precision), ""),
+            Optional(measurement.max_fixed(options.precision), ""),
+            Optional(measurement.mean_fixed(options.precision), ""),
+            measurement.count()
+        )?;
+        if options.median {
+            write!(
+                writer,
+                ",{}",
+                Optional(measurement.median_fixed(options.precision), "")
+            )?;
+        }
+        if options.mode {
+            write!(
+                writer,
+                ",{}",
+                Optional(measurement.mode_fixed(options.precision), "")
+            )?;
+        }
+        if options.stddev {
+            write!(
+                writer,
+                ",{}",
+                Optional(measurement.stddev_fixed(options.precision), "")
+            )?;
+        }
+        for &percentile in &options.percentiles {
+            write!(
+                writer,
+                ",{}",
+                Optional(
+                    measurement.percentile_fixed(percentile, options.precision),
+                    ""
+                )
+            )?;
+        }
+        if options.offsets {
+            write!(writer, ",{}", Optional(offset(measurement), ""))?;
+        }
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+// Any of the other statistics that were asked for follow the mean, in the same order as in CSV
+fn write_tsv<'a>(
+    writer: &mut impl Write,
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    options: &OutputOptions,
+) -> io::Result<()> {
+    for (city, measurement) in results {
+        write_tsv_field(writer, city)?;
+        write!(
+            writer,
+            "\t{}\t{}\t{}",
+            Optional(measurement.min_fixed(options.precision), ""),
+            Optional(measurement.max_fixed(options.precision), ""),
+            Optional(measurement.mean_fixed(options.precision), "")
+        )?;
+        if options.median {
+            write!(
+                writer,
+                "\t{}",
+                Optional(measurement.median_fixed(options.precision), "")
+            )?;
+        }
+        if options.mode {
+            write!(
+                writer,
+                "\t{}",
+                Optional(measurement.mode_fixed(options.precision), "")
+            )?;
+        }
+        if options.stddev {
+            write!(
+                writer,
+                "\t{}",
+                Optional(measurement.stddev_fixed(options.precision), "")
+            )?;
+        }
+        for &percentile in &options.percentiles {
+            write!(
+                writer,
+                "\t{}",
+                Optional(
+                    measurement.percentile_fixed(percentile, options.precision),
+                    ""
+                )
+            )?;
+        }
+        if options.offsets {
+            write!(writer, "\t{}", Optional(offset(measurement), ""))?;
+        }
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+// Per RFC 4180, fields containing a separator, quote or line break are wrapped in quotes,
+// with any quotes inside them doubled up.
+fn write_csv_field(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    if !s.contains([',', '"', '\r', '\n']) {
+        return writer.write_all(s.as_bytes());
+    }
+
+    writer.write_all(b"\"")?;
+    writer.write_all(s.replace('"', "\"\"").as_bytes())?;
+    writer.write_all(b"\"")
+}
+
+// TSV has no quoting, so the characters that would end a field or a row are escaped with a
+// backslash instead, as most tools that read it expect, and so are backslashes themselves
+fn write_tsv_field(writer: &mut impl Write, s: &str) -> io::Result<()> {
+    if !s.contains(['\t', '\n', '\r', '\\']) {
+        return writer.write_all(s.as_bytes());
+    }
+
+    for c in s.chars() {
+        match c {
+            '\t' => writer.write_all(b"\\t")?,
+            '\n' => writer.write_all(b"\\n")?,
+            '\r' => writer.write_all(b"\\r")?,
+            '\\' => writer.write_all(b"\\\\")?,
+            c => write!(writer, "{}", c)?,
+        }
+    }
+
+    Ok(())
+}
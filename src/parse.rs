@@ -0,0 +1,600 @@
+use std::{
+    io::{self, BufRead},
+    sync::{atomic::Ordering, mpsc},
+    time::Instant,
+};
+
+use crate::{
+    hash::{station_map, StationMap},
+    intern::{interned, Interned},
+    search::{find, lines, rfind},
+    stats::per_line,
+    Accumulator, Measurement, OnError, Options,
+};
+
+#[inline(always)]
+// We're manually implementing the search for our delimiter because we know that the measurement
+// is always at the end of the line, and is far shorter than the station name. This means we can
+// find the delimiter much sooner by searching from the end of the line.
+//
+// It also means a station name can contain the delimiter itself, as in "Foo;Bar;12.3", since the
+// measurement never does. A line without any delimiter at all is reported rather than indexed into,
+// and so is one where a delimiter outside of ASCII matches partway through a character.
+fn split_line(line: &str, delimiter: u8) -> Option<(&str, &str)> {
+    let i = rfind(line.as_bytes(), delimiter)?;
+
+    Some((line.get(..i)?, line.get(i + 1..)?))
+}
+
+// What to multiply the parsed digits by to get thousandths, indexed by the number of decimals
+const DECIMAL_SCALE: [i32; 4] = [1000, 100, 10, 1];
+
+// More digits than this before the decimal point could overflow once scaled up to thousandths
+const MAX_INTEGER_DIGITS: u32 = 6;
+
+#[inline(always)]
+// Parses a measurement such as "-12.3" into thousandths of a degree (-12300) by walking the bytes.
+// We skip the '.' and treat the digits as a single integer, scaling it up by however many decimal
+// places there were, which avoids the floating-point parser entirely. Any digits past the third
+// decimal place are beyond our precision, and are truncated.
+//
+// Anything that isn't a plain decimal number, such as "abc", "1e5" or "", is rejected.
+fn parse_thousandths(measurement: &str) -> Option<i32> {
+    let (negative, digits) = match measurement.as_bytes() {
+        [b'-', digits @ ..] => (true, digits),
+        digits => (false, digits),
+    };
+
+    // Nearly every measurement has one or two digits and a single decimal place, which we can
+    // match on directly rather than walking the bytes
+    let value = match *digits {
+        [a @ b'0'..=b'9', b'.', b @ b'0'..=b'9'] => Some(digit(a) * 10 + digit(b)),
+        [a @ b'0'..=b'9', b @ b'0'..=b'9', b'.', c @ b'0'..=b'9'] => {
+            Some(digit(a) * 100 + digit(b) * 10 + digit(c))
+        }
+        _ => None,
+    };
+    if let Some(value) = value {
+        let thousandths = value * DECIMAL_SCALE[1];
+
+        return Some(if negative { -thousandths } else { thousandths });
+    }
+
+    let mut value = 0i32;
+    let mut integer_digits = 0;
+    let mut decimals = None;
+    for &byte in digits {
+        match (byte, &mut decimals) {
+            (b'.', None) => decimals = Some(0),
+            (b'0'..=b'9', Some(3)) => continue,
+            (b'0'..=b'9', Some(decimals)) => *decimals += 1,
+            (b'0'..=b'9', None) if integer_digits < MAX_INTEGER_DIGITS => integer_digits += 1,
+            _ => return None,
+        }
+
+        if byte != b'.' {
+            value = value * 10 + digit(byte);
+        }
+    }
+
+    // There has to be at least one digit, on one side of the decimal point or the other
+    if integer_digits == 0 && decimals.unwrap_or(0) == 0 {
+        return None;
+    }
+
+    let thousandths = value * DECIMAL_SCALE[decimals.unwrap_or(0)];
+
+    if negative {
+        Some(-thousandths)
+    } else {
+        Some(thousandths)
+    }
+}
+
+#[inline(always)]
+fn digit(byte: u8) -> i32 {
+    (byte - b'0') as i32
+}
+
+#[inline(always)]
+pub(crate) fn parse_line(line: &str, delimiter: u8) -> Option<(&str, i32)> {
+    // Files written on Windows end their lines with \r\n, which would otherwise leave the \r
+    // at the end of the measurement
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    #[cfg(feature = "swar")]
+    if let Some(parsed) = parse_tenths_line(line, delimiter) {
+        return Some(parsed);
+    }
+
+    let (city, measurement) = split_line(line, delimiter)?;
+
+    Some((city, parse_thousandths(measurement)?))
+}
+
+// Like `parse_line`, but for lines with the measurement first, as in "12.3;Tokyo". The measurement
+// is just as short at the start of the line, so the delimiter is searched for from there instead,
+// and a station name can still contain it.
+#[inline(always)]
+fn parse_value_first(line: &str, delimiter: u8) -> Option<(&str, i32)> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let i = find(line.as_bytes(), delimiter)?;
+    let (measurement, city) = (line.get(..i)?, line.get(i + 1..)?);
+
+    Some((city, parse_thousandths(measurement)?))
+}
+
+// Parses a line laid out however the options say
+#[inline(always)]
+pub(crate) fn parse<'a>(line: &'a str, options: &Options) -> Option<(&'a str, i32)> {
+    if options.value_first {
+        parse_value_first(line, options.delimiter)
+    } else {
+        parse_line(line, options.delimiter)
+    }
+}
+
+/*
+ * Nearly every line ends in a measurement of one or two digits and a single decimal place, which
+ * this parses without searching for the delimiter or branching on the digits, by treating the last
+ * 4 bytes of the line as a word with a lane per byte. Those are "d.d" in the last three lanes,
+ * with either another digit or whatever precedes the measurement in the first. Masking the digits
+ * down to their values leaves a single multiply to shift each one into the same lane, scaled by
+ * its place: the hundreds of tenths, tens and ones.
+ *
+ * The delimiter has to come just before the measurement and its sign, so anything else, such as
+ * more decimal places or a line that's too short, is left to `split_line` and `parse_thousandths`.
+ * So is every line when the delimiter could be mistaken for part of a measurement.
+ */
+#[cfg(feature = "swar")]
+#[inline(always)]
+fn parse_tenths_line(line: &str, delimiter: u8) -> Option<(&str, i32)> {
+    let bytes = line.as_bytes();
+    if bytes.len() < 5 || matches!(delimiter, b'0'..=b'9' | b'-' | b'.') {
+        return None;
+    }
+
+    let [.., a, b, c, d] = *bytes else {
+        return None;
+    };
+    let word = u32::from_le_bytes([a, b, c, d]);
+
+    // The last three lanes have to hold "d.d", where adding 6 to a digit carries out of its low
+    // nibble only if it's above 9
+    if word & 0xF0FF_F000 != 0x302E_3000 || ((word & 0x0F00_0F00) + 0x0600_0600) & 0x1000_1000 != 0
+    {
+        return None;
+    }
+    let two_digits = a.is_ascii_digit();
+
+    let values = word & if two_digits { 0x0F00_0F0F } else { 0x0F00_0F00 };
+    let tenths = ((values as u64 * 0x640A_0001) >> 24 & 0x3FF) as i32;
+
+    let start = bytes.len() - 3 - two_digits as usize;
+    let negative = bytes[start - 1] == b'-';
+    // A line that's nothing but a negative measurement of two digits has no room for a delimiter
+    let split = (start - 1).checked_sub(negative as usize)?;
+    if bytes[split] != delimiter {
+        return None;
+    }
+
+    let thousandths = tenths * DECIMAL_SCALE[1];
+
+    Some((
+        line.get(..split)?,
+        if negative { -thousandths } else { thousandths },
+    ))
+}
+
+/// Why [`try_parse_line`] or [`try_parse_line_strict`] couldn't parse a line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineError {
+    /// The line isn't valid UTF-8.
+    InvalidUtf8,
+    /// There's no delimiter to split the station from its measurement.
+    NoDelimiter,
+    /// What follows the last delimiter isn't a plain decimal number.
+    InvalidMeasurement,
+    /// The station's name is empty or longer than 100 bytes, which only strict parsing rejects.
+    NameLength,
+    /// The station's name contains the delimiter, which only strict parsing rejects.
+    NameContainsDelimiter,
+    /// The measurement doesn't have exactly one decimal place, which only strict parsing rejects.
+    NotOneDecimal,
+    /// The measurement is outside of -99.9..=99.9, which only strict parsing rejects.
+    OutOfRange,
+}
+
+impl std::fmt::Display for LineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::InvalidUtf8 => "the line isn't valid UTF-8",
+            Self::NoDelimiter => "the line has no delimiter",
+            Self::InvalidMeasurement => "the measurement isn't a number",
+            Self::NameLength => "the station name isn't 1 to 100 bytes long",
+            Self::NameContainsDelimiter => "the station name contains the delimiter",
+            Self::NotOneDecimal => "the measurement doesn't have exactly one decimal place",
+            Self::OutOfRange => "the measurement isn't within -99.9 to 99.9",
+        })
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Parses a single line, without its newline, into its station and measurement in thousandths of
+/// a degree, exactly as the line would be when aggregated. Unlike aggregating, the line is always
+/// checked to be valid UTF-8 first, so any bytes at all can be given.
+pub fn try_parse_line(line: &[u8], delimiter: u8) -> Result<(&str, i32), LineError> {
+    let line = std::str::from_utf8(line).map_err(|_| LineError::InvalidUtf8)?;
+
+    // Only once it's known to be malformed does it matter which part is to blame
+    parse_line(line, delimiter).ok_or_else(|| {
+        match split_line(line.strip_suffix('\r').unwrap_or(line), delimiter) {
+            Some(_) => LineError::InvalidMeasurement,
+            None => LineError::NoDelimiter,
+        }
+    })
+}
+
+/// Like [`try_parse_line`], but only accepts lines that keep to the challenge's rules: a station
+/// name of 1 to 100 bytes without the delimiter in it, and a measurement within -99.9..=99.9 with
+/// exactly one decimal place. Lines ending in `\r` are rejected too.
+pub fn try_parse_line_strict(line: &[u8], delimiter: u8) -> Result<(&str, i32), LineError> {
+    parse_line_strict(
+        std::str::from_utf8(line).map_err(|_| LineError::InvalidUtf8)?,
+        delimiter,
+    )
+}
+
+// The longest station name the challenge allows, in bytes
+const MAX_STRICT_NAME_LEN: usize = 100;
+
+// The challenge's rules, checked one after another rather than with any of the tricks of
+// `parse_line`, as they're what those tricks are checked against
+pub(crate) fn parse_line_strict(line: &str, delimiter: u8) -> Result<(&str, i32), LineError> {
+    let (city, measurement) = split_line(line, delimiter).ok_or(LineError::NoDelimiter)?;
+
+    if city.is_empty() || city.len() > MAX_STRICT_NAME_LEN {
+        return Err(LineError::NameLength);
+    }
+    if city.as_bytes().contains(&delimiter) {
+        return Err(LineError::NameContainsDelimiter);
+    }
+
+    let value = parse_thousandths(measurement).ok_or(LineError::InvalidMeasurement)?;
+
+    let integer = measurement.strip_prefix('-').unwrap_or(measurement);
+    match integer.split_once('.') {
+        Some((integer, decimals)) if !integer.is_empty() && decimals.len() == 1 => {}
+        _ => return Err(LineError::NotOneDecimal),
+    }
+    if value.abs() > 99_900 {
+        return Err(LineError::OutOfRange);
+    }
+
+    Ok((city, value))
+}
+
+// Fails the aggregation on a line that parsed, but breaks the challenge's rules
+#[cold]
+#[inline(never)]
+fn violation(line: &str, offset: usize, error: LineError) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "line at byte {} breaks the challenge's rules, as {}: {:?}",
+            offset, error, line
+        ),
+    )
+}
+
+// Deals with a line that couldn't be parsed, which fails the aggregation unless we've been told
+// to skip over them
+#[cold]
+#[inline(never)]
+pub(crate) fn malformed(line: &str, offset: usize, options: &Options) -> io::Result<()> {
+    match options.on_error {
+        OnError::Fail => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("malformed line at byte {}: {:?}", offset, line),
+        )),
+        OnError::Skip | OnError::Log => {
+            if options.on_error == OnError::Log {
+                eprintln!("Skipping malformed line at byte {}: {:?}", offset, line);
+            }
+            options.skipped.fetch_add(1, Ordering::Relaxed);
+
+            Ok(())
+        }
+    }
+}
+
+// Starts the measurement of a station that's first seen at byte `offset`
+#[inline(always)]
+pub(crate) fn first_measurement(measurement: i32, offset: usize, options: &Options) -> Measurement {
+    let mut first = if options.histogram {
+        Measurement::with_histogram(measurement)
+    } else {
+        Measurement::from(measurement)
+    };
+
+    if options.debug_offsets {
+        first.set_first_offset(offset as u64);
+    }
+
+    first
+}
+
+// How many lines each thread processes between publishing its progress
+const PROGRESS_INTERVAL: u64 = 1 << 16;
+
+#[inline(always)]
+pub(crate) fn process_lines<'a, A: Accumulator>(
+    contents: &'a str,
+    // Where the contents start in the file, so that malformed lines can be pointed out
+    offset: usize,
+    options: &Options,
+) -> io::Result<impl Iterator<Item = (&'a str, A)>> {
+    let start = Instant::now();
+
+    // Whether to sample is decided once for the whole chunk, leaving the loop over its lines alone
+    let (mut measurements, line_count, reported) = match options.sample {
+        Some(every) if every > 1 => {
+            aggregate_lines::<A>(lines(contents).step_by(every), contents, offset, options)?
+        }
+        _ => aggregate_lines::<A>(lines(contents), contents, offset, options)?,
+    };
+
+    let end = start.elapsed();
+
+    if let Some(every) = options.sample.filter(|&every| every > 1) {
+        for measurement in measurements.values_mut() {
+            measurement.scale(every as u64);
+        }
+    }
+
+    if let Some(progress) = &options.progress {
+        progress.advance((contents.len() - reported) as u64);
+    }
+
+    if let Some(stats) = &options.chunk_stats {
+        stats.record(offset, contents.len(), line_count, end);
+    }
+
+    if options.verbose {
+        eprintln!(
+            "Processed {} lines in {:?}, averaging {:?} per line",
+            line_count,
+            end,
+            per_line(end, line_count)
+        );
+    }
+
+    Ok(measurements.into_iter())
+}
+
+// Aggregates the given lines of `contents`, returning how many there were and how many bytes of
+// progress have been published so far
+#[inline(always)]
+fn aggregate_lines<'a, A: Accumulator>(
+    lines: impl Iterator<Item = &'a str>,
+    contents: &'a str,
+    offset: usize,
+    options: &Options,
+) -> io::Result<(Interned<'a, A>, u64, usize)> {
+    if options.strict && options.value_first {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the challenge's rules have the measurement last, so value first lines can't be strict",
+        ));
+    }
+
+    let mut measurements = interned::<A>(options.stations_hint);
+    // A chunk of a big enough file has more lines than a u32 can count
+    let mut line_count = 0u64;
+    let mut reported = 0;
+
+    for line in lines {
+        line_count += 1;
+
+        /*
+         * Publishing progress on every line would have every thread contending for the same
+         * counter, so it's only done every so often. How far through the chunk we are comes from
+         * where the line starts, which saves keeping a running total.
+         */
+        if line_count.is_multiple_of(PROGRESS_INTERVAL) {
+            if let Some(progress) = &options.progress {
+                let offset = line.as_ptr() as usize - contents.as_ptr() as usize;
+                progress.advance((offset - reported) as u64);
+                reported = offset;
+            }
+
+            // Checking for a stop is kept off the path taken by every line in the same way
+            if options.stopped() {
+                break;
+            }
+        }
+
+        // Only needed for the rare lines that are malformed or the first of their station
+        let line_offset = || offset + (line.as_ptr() as usize - contents.as_ptr() as usize);
+
+        let parsed = match options.strict {
+            false => parse(line, options),
+            true => match parse_line_strict(line, options.delimiter) {
+                Ok(parsed) => Some(parsed),
+                // Lines that couldn't be aggregated at all are still up to `on_error`
+                Err(LineError::NoDelimiter | LineError::InvalidMeasurement) => None,
+                Err(e) => return Err(violation(line, line_offset(), e)),
+            },
+        };
+        let Some((city, measurement)) = parsed else {
+            malformed(line, line_offset(), options)?;
+            continue;
+        };
+
+        let Some(item) = measurements.get_mut(city) else {
+            measurements.insert(city, A::first(measurement, line_offset(), options));
+            continue;
+        };
+
+        item.record(measurement);
+    }
+
+    Ok((measurements, line_count, reported))
+}
+
+// How much of a stream is read at a time before it's handed over to be aggregated
+const BLOCK_SIZE: usize = 1 << 20;
+
+// How many blocks can be waiting to be aggregated before reading has to wait for them
+const BLOCKS_IN_FLIGHT: usize = 2;
+
+/*
+ * Aggregates a stream. Unlike a file we can't seek around a stream to split the work, but we can
+ * at least overlap reading it, which for a compressed file includes decompressing it, with
+ * aggregating what's been read so far.
+ *
+ * The calling thread reads the stream in blocks of whole lines, and hands each one over to
+ * another thread to aggregate while it reads the next. Whatever follows the last newline in a
+ * block is carried over to the start of the next one, so no line is ever split between them. A
+ * line longer than a block just makes for a longer block. Once it's done with a block, the other
+ * thread sends it back to be read into again.
+ */
+pub(crate) fn process_stream<A: Accumulator>(
+    mut reader: impl BufRead,
+    options: &Options,
+) -> io::Result<StationMap<String, A>> {
+    let (blocks, received) = mpsc::sync_channel::<(usize, Vec<u8>)>(BLOCKS_IN_FLIGHT);
+    let (recycle, recycled) = mpsc::channel::<Vec<u8>>();
+
+    std::thread::scope(|scope| {
+        let aggregator = scope.spawn(move || aggregate_blocks(received, recycle, options));
+
+        let mut carry = Vec::new();
+        let mut offset = 0;
+        let read = loop {
+            let mut block = recycled
+                .try_recv()
+                .unwrap_or_else(|_| Vec::with_capacity(BLOCK_SIZE));
+            if options.stopped() {
+                break Ok(());
+            }
+            block.clear();
+            block.append(&mut carry);
+
+            // A line longer than a block has to keep growing it, doubling it so that it's only
+            // copied so many times
+            let target = BLOCK_SIZE.max(block.len() * 2);
+            let end = match fill(&mut reader, &mut block, target, options) {
+                Ok(end) => end,
+                Err(e) => break Err(e),
+            };
+
+            // A line that runs past the limit isn't whole within it, so it's left out with the rest
+            let limit = options
+                .limit_bytes
+                .map(|limit| limit.saturating_sub(offset));
+            if let Some(limit) = limit.filter(|&limit| limit < block.len()) {
+                block.truncate(rfind(&block[..limit], b'\n').map_or(0, |newline| newline + 1));
+                let _ = blocks.send((offset, block));
+                break Ok(());
+            }
+
+            if end {
+                if !block.is_empty() {
+                    let _ = blocks.send((offset, block));
+                }
+                break Ok(());
+            }
+
+            // Without a newline there isn't a whole line to hand over yet, so keep reading
+            let Some(newline) = rfind(&block, b'\n') else {
+                carry = block;
+                continue;
+            };
+            carry.extend_from_slice(&block[newline + 1..]);
+            block.truncate(newline + 1);
+
+            let len = block.len();
+            // The aggregator only hangs up early if it failed, which it'll tell us about below
+            if blocks.send((offset, block)).is_err() {
+                break Ok(());
+            }
+            offset += len;
+        };
+
+        // Hanging up tells the aggregator that there are no more blocks to come
+        drop(blocks);
+        let measurements = aggregator.join().unwrap();
+
+        read.and(measurements)
+    })
+}
+
+// Reads from `reader` until `block` holds at least `target` bytes, returning whether the stream
+// has ended. Being stopped early cuts the block short, though not in the middle of a line, as only
+// its whole lines are handed over.
+fn fill(
+    reader: &mut impl BufRead,
+    block: &mut Vec<u8>,
+    target: usize,
+    options: &Options,
+) -> io::Result<bool> {
+    while block.len() < target {
+        let available = match reader.fill_buf() {
+            Ok([]) => return Ok(true),
+            Ok(available) => available,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted && options.stopped() => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        let read = available.len().min(target - block.len());
+        block.extend_from_slice(&available[..read]);
+        reader.consume(read);
+    }
+
+    Ok(false)
+}
+
+// Aggregates each block of whole lines as it arrives, along with where it starts in the stream
+fn aggregate_blocks<A: Accumulator>(
+    blocks: mpsc::Receiver<(usize, Vec<u8>)>,
+    recycle: mpsc::Sender<Vec<u8>>,
+    options: &Options,
+) -> io::Result<StationMap<String, A>> {
+    let mut measurements = station_map::<String, A>(options.stations_hint);
+
+    // Each block is only a sliver of the stream, so its timings aren't worth printing
+    let options = Options {
+        verbose: false,
+        chunk_stats: None,
+        ..options.clone()
+    };
+
+    for (offset, block) in blocks {
+        let contents = std::str::from_utf8(&block).map_err(|e| {
+            let line = rfind(&block[..e.valid_up_to()], b'\n').map_or(0, |newline| newline + 1);
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid UTF-8 in the line at byte {}", offset + line),
+            )
+        })?;
+
+        // Station names borrow from the block until they're copied the first time they're seen
+        for (city, measurement) in process_lines::<A>(contents, offset, &options)? {
+            match measurements.get_mut(city) {
+                Some(item) => item.merge(&measurement),
+                None => {
+                    measurements.insert(city.to_owned(), measurement);
+                }
+            }
+        }
+
+        // Reading has already finished if nobody is there to take it back
+        let _ = recycle.send(block);
+    }
+
+    Ok(measurements)
+}
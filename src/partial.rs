@@ -0,0 +1,90 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    checkpoint::{read_bytes, read_stations, write_stations},
+    merge, Measurement,
+};
+
+const MAGIC: &[u8; 8] = b"1BRCPART";
+// Bumped whenever the layout changes, so that an old partial is refused rather than misread
+const VERSION: u32 = 2;
+
+/// The results of aggregating part of the input, such as one node's share of it, for merging with
+/// the rest later on.
+#[derive(Clone, Default)]
+pub struct Partial {
+    /// Every station seen in that part, by name.
+    pub results: BTreeMap<String, Measurement>,
+    /// How many malformed lines were skipped in it.
+    pub skipped: u64,
+    /// Whether it was aggregated with [`Options::histogram`](crate::Options), and so has a
+    /// histogram for every station.
+    pub histograms: bool,
+}
+
+/*
+ * A partial is laid out as the magic bytes and version, followed by how many lines were skipped
+ * and how many stations there are, each a little endian u64, then whether there are histograms
+ * as a byte. Every station follows as the length of its name as a u64, the name, and then its
+ * measurement, laid out just like in a checkpoint: the min, max and sum as i64s, the sum of
+ * squares as a u128, the count as a u64, then a byte saying whether a histogram of u64 buckets
+ * follows.
+ */
+impl Partial {
+    /// Writes the partial out for [`Partial::read_from`] to read back in, perhaps on another
+    /// machine. The layout is versioned, so a partial from another version is refused rather than
+    /// misread.
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.skipped.to_le_bytes())?;
+        writer.write_all(&(self.results.len() as u64).to_le_bytes())?;
+        writer.write_all(&[self.histograms as u8])?;
+
+        write_stations(writer, &self.results)
+    }
+
+    /// Reads a partial written by [`Partial::write_to`].
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        if read_bytes(&mut reader)? != *MAGIC
+            || u32::from_le_bytes(read_bytes(&mut reader)?) != VERSION
+        {
+            return Err(invalid("not a partial, or one from another version"));
+        }
+
+        let skipped = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let stations = u64::from_le_bytes(read_bytes(&mut reader)?);
+        let histograms = read_bytes::<1>(&mut reader)? == [1];
+        let results = read_stations(&mut reader, stations, histograms)?;
+
+        Ok(Self {
+            results,
+            skipped,
+            histograms,
+        })
+    }
+
+    /// Combines `other` into this partial, as if both parts had been aggregated together.
+    ///
+    /// Partials with histograms can't be combined with ones without, as the histograms would only
+    /// cover some of each station's measurements, and so an error is returned instead.
+    pub fn merge(&mut self, other: &Partial) -> io::Result<()> {
+        if self.histograms != other.histograms {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "partials with histograms can't be merged with ones without, so map every part \
+                 with the same statistics",
+            ));
+        }
+
+        merge(&mut self.results, &other.results);
+        self.skipped += other.skipped;
+
+        Ok(())
+    }
+}
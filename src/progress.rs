@@ -0,0 +1,55 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Counts how many bytes of the input have been aggregated so far, so that another thread can
+/// report on it. Threads only publish their progress every so often, so reading it never waits
+/// on them.
+#[derive(Debug, Default)]
+pub struct Progress {
+    processed: AtomicU64,
+    total: AtomicU64,
+}
+
+impl Progress {
+    /// The number of bytes aggregated so far.
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// The size of the input in bytes, or zero if it isn't known up front, such as for a stream.
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    // Adds to the total rather than setting it, as several files can make up one aggregation
+    #[inline(always)]
+    pub(crate) fn add_total(&self, bytes: u64) {
+        self.total.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn advance(&self, bytes: u64) {
+        self.processed.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+// Counts the bytes read through it as progress, for input whose size is only known before it's
+// decompressed
+pub(crate) struct Counted<R> {
+    pub(crate) inner: R,
+    pub(crate) progress: Arc<Progress>,
+}
+
+impl<R: Read> Read for Counted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.progress.advance(read as u64);
+
+        Ok(read)
+    }
+}
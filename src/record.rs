@@ -0,0 +1,141 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    binary,
+    chunk::{memory_map, share, shared_chunks},
+    compression::Compression,
+    parse::{malformed, parse},
+    search::lines,
+    Options,
+};
+
+/// Calls `f` with the station and measurement of every line in the file at `path`. This is the
+/// same scan that [`aggregate_file`](crate::aggregate_file) makes, for statistics of your own.
+///
+/// # Units
+///
+/// Measurements are given in **thousandths** of a degree, not tenths, just as
+/// [`Accumulator`](crate::Accumulator)s and [`Measurement::record`](crate::Measurement::record)
+/// are given them, as the input can have up to three decimal places. `12.3` comes through as
+/// `12_300` and `-0.05` as `-50`.
+///
+/// ```no_run
+/// use std::{path::Path, sync::atomic::{AtomicU64, Ordering}};
+///
+/// // Counts the readings above 30.0 degrees
+/// let hot = AtomicU64::new(0);
+/// one_brc::for_each_record(Path::new("measurements.txt"), 8, |_station, thousandths| {
+///     if thousandths > 30_000 {
+///         hot.fetch_add(1, Ordering::Relaxed);
+///     }
+/// })?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// The file is split into chunks of whole lines across `parallelism` threads, so `f` is called
+/// from several threads at once, and in no particular order between chunks. Any state it keeps
+/// has to synchronize itself. Compressed files and files in the binary format are read just as
+/// they are when aggregated. A malformed line stops the scan with an error, though `f` may have
+/// been called for lines after it in other chunks by then.
+pub fn for_each_record(
+    path: &Path,
+    parallelism: usize,
+    f: impl Fn(&str, i32) + Sync,
+) -> io::Result<()> {
+    let options = Options {
+        parallelism,
+        ..Options::default()
+    };
+
+    let mut file = File::open(path)?;
+    if let Some(compression) = Compression::detect(&mut file)? {
+        return for_each_streamed(BufReader::new(compression.decoder(file)), &options, &f);
+    }
+
+    let mmap = memory_map(&file, &options)?;
+    if binary::is_binary(&mmap) {
+        return binary::for_each_record(&mmap, &options, &f);
+    }
+
+    let chunks = shared_chunks(&mmap, &options);
+
+    share(
+        chunks.len(),
+        &options,
+        || (),
+        |(), i| {
+            let range = chunks[i].clone();
+
+            for_each_line(&mmap[range.clone()], range.start, &options, &f).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "failed to process chunk {} (bytes {}..{}): {}",
+                        i, range.start, range.end, e
+                    ),
+                )
+            })
+        },
+        |()| {},
+    )
+}
+
+// Calls `f` for every line of a chunk, which starts at `offset` in the file
+fn for_each_line(
+    chunk: &[u8],
+    offset: usize,
+    options: &Options,
+    f: &impl Fn(&str, i32),
+) -> io::Result<()> {
+    let contents = std::str::from_utf8(chunk).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid UTF-8 at byte {}", offset + e.valid_up_to()),
+        )
+    })?;
+
+    for line in lines(contents) {
+        match parse(line, options) {
+            Some((city, measurement)) => f(city, measurement),
+            None => malformed(
+                line,
+                offset + (line.as_ptr() as usize - contents.as_ptr() as usize),
+                options,
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+// A stream can't be split up, so `f` is only ever called from the calling thread
+fn for_each_streamed(
+    mut reader: impl BufRead,
+    options: &Options,
+    f: &impl Fn(&str, i32),
+) -> io::Result<()> {
+    let mut line = String::new();
+    let mut offset = 0;
+
+    loop {
+        let read = reader.read_line(&mut line).map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid UTF-8 in the line at byte {}", offset),
+            ),
+            _ => e,
+        })?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        for_each_line(line.as_bytes(), offset, options, f)?;
+
+        offset += read;
+        line.clear();
+    }
+}
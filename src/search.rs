@@ -0,0 +1,93 @@
+/*
+ * Byte searches for the delimiter and newlines, which between them touch every byte of the input.
+ *
+ * With the simd feature enabled these compare 16 bytes at a time using portable SIMD, and only
+ * fall back to comparing byte by byte for whatever is left over at the end. Searching backwards
+ * for the delimiter rarely has a full 16 bytes to work with, as the measurement at the end of each
+ * line is so short, but newlines are searched for across whole station names.
+ */
+#[cfg(feature = "simd")]
+use std::simd::{cmp::SimdPartialEq, u8x16};
+
+#[cfg(feature = "simd")]
+const LANES: usize = 16;
+
+// The index of the first occurrence of `needle` in `haystack`
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn find(haystack: &[u8], needle: u8) -> Option<usize> {
+    let needles = u8x16::splat(needle);
+    let mut chunks = haystack.chunks_exact(LANES);
+
+    for (i, chunk) in (&mut chunks).enumerate() {
+        let matches = u8x16::from_slice(chunk).simd_eq(needles).to_bitmask();
+        if matches != 0 {
+            return Some(i * LANES + matches.trailing_zeros() as usize);
+        }
+    }
+
+    let offset = haystack.len() - chunks.remainder().len();
+    chunks
+        .remainder()
+        .iter()
+        .position(|&byte| byte == needle)
+        .map(|i| offset + i)
+}
+
+// The index of the last occurrence of `needle` in `haystack`
+#[cfg(feature = "simd")]
+#[inline(always)]
+pub(crate) fn rfind(haystack: &[u8], needle: u8) -> Option<usize> {
+    let needles = u8x16::splat(needle);
+    let mut chunks = haystack.rchunks_exact(LANES);
+
+    for (i, chunk) in (&mut chunks).enumerate() {
+        let matches = u8x16::from_slice(chunk).simd_eq(needles).to_bitmask();
+        if matches != 0 {
+            let start = haystack.len() - (i + 1) * LANES;
+            return Some(start + (u64::BITS - 1 - matches.leading_zeros()) as usize);
+        }
+    }
+
+    chunks.remainder().iter().rposition(|&byte| byte == needle)
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn find(haystack: &[u8], needle: u8) -> Option<usize> {
+    haystack.iter().position(|&byte| byte == needle)
+}
+
+#[cfg(not(feature = "simd"))]
+#[inline(always)]
+pub(crate) fn rfind(haystack: &[u8], needle: u8) -> Option<usize> {
+    haystack.iter().rposition(|&byte| byte == needle)
+}
+
+// Splits `contents` into lines the same way str::lines does, but using our own newline search
+#[inline(always)]
+pub(crate) fn lines(contents: &str) -> impl Iterator<Item = &str> {
+    // A newline is a single byte that never appears inside another character, so splitting on it
+    // can't leave a line that isn't valid UTF-8
+    byte_lines(contents.as_bytes()).map(|line| unsafe { std::str::from_utf8_unchecked(line) })
+}
+
+// Like lines, for bytes that aren't known to be UTF-8
+#[inline(always)]
+pub(crate) fn byte_lines(contents: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = contents;
+
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+
+        let (line, remainder) = match find(rest, b'\n') {
+            Some(newline) => (&rest[..newline], &rest[newline + 1..]),
+            None => (rest, &[][..]),
+        };
+        rest = remainder;
+
+        Some(line)
+    })
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use one_brc::{aggregate_files_with, fold_case, Format, Measurement};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::cli::{Args, Input};
+
+// Set on SIGHUP, which has the results aggregated again before the next request
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+// How long to wait for a request before checking whether we've been asked to reload or stop
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/*
+ * Aggregates the input once and then serves the results as JSON, in the same layout as
+ * `--format json`, until Ctrl-C:
+ *
+ *   GET  /stations         every station
+ *   GET  /stations/{name}  just that station, with its name percent-encoded, or a 404
+ *   POST /reload           aggregates the input again, as SIGHUP also does
+ *
+ * Requests are handled one at a time on the calling thread, so a reload holds the ones after it
+ * up until it's done, and they never see the results half replaced. If the input can't be
+ * aggregated again the results from before are kept. Only connections from this machine are
+ * accepted, as there's nothing here to keep anyone else out.
+ *
+ * The stations are always served in order of name and with all of them, so --format, --sort,
+ * --by, --top and anything else that shapes what's written are refused along with serve. The
+ * statistics asked for with --stats, --with-count, --with-stddev and --percentiles are served,
+ * to as many --decimals and with the --rounding asked for.
+ */
+pub(crate) fn serve(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let Ok(mut results) = aggregate(args) else {
+        std::process::exit(1);
+    };
+
+    handle_hangup();
+    let server = match Server::http(("127.0.0.1", args.port)) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Failed to listen on port {}: {}", args.port, e);
+            std::process::exit(1);
+        }
+    };
+    if !args.quiet {
+        eprintln!(
+            "Serving {} stations on http://{}",
+            results.len(),
+            server.server_addr()
+        );
+    }
+
+    // Ctrl-C asks the aggregation to stop, which is all the more reason to stop serving
+    while !args
+        .options
+        .stop
+        .as_ref()
+        .is_some_and(|stop| stop.load(Ordering::Relaxed))
+    {
+        if RELOAD.swap(false, Ordering::Relaxed) {
+            reload(args, &mut results);
+        }
+
+        let Some(request) = server.recv_timeout(POLL_INTERVAL)? else {
+            continue;
+        };
+        respond(request, args, &mut results)?;
+    }
+
+    Ok(())
+}
+
+fn aggregate(args: &Args) -> std::io::Result<BTreeMap<String, Measurement>> {
+    let Input::Files(paths) = &args.input else {
+        unreachable!("stdin can't be served, as it can't be read again");
+    };
+
+    let results = aggregate_files_with(paths, &args.options)
+        .inspect_err(|e| eprintln!("Failed to read {}", e))?;
+
+    Ok(match args.casing {
+        Some(casing) => fold_case(results, casing),
+        None => results,
+    })
+}
+
+// Aggregates the input again, keeping the results from before if it fails
+fn reload(args: &Args, results: &mut BTreeMap<String, Measurement>) -> bool {
+    match aggregate(args) {
+        Ok(reloaded) => {
+            *results = reloaded;
+            if !args.quiet {
+                eprintln!("Reloaded {} stations", results.len());
+            }
+
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+fn respond(
+    request: Request,
+    args: &Args,
+    results: &mut BTreeMap<String, Measurement>,
+) -> std::io::Result<()> {
+    // A query string doesn't change anything we serve
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+
+    let response = match (request.method(), path.as_str()) {
+        (Method::Get, "/stations") => json(results.iter(), args)?,
+        (Method::Get, path) if path.starts_with("/stations/") => {
+            match percent_decode(&path["/stations/".len()..]) {
+                Some(name) => match results.get_key_value(&name) {
+                    Some(station) => json([station], args)?,
+                    None => text(404, &format!("There's no station named {:?}", name)),
+                },
+                None => text(400, "The station's name isn't valid percent-encoded UTF-8"),
+            }
+        }
+        (Method::Post, "/reload") => match reload(args, results) {
+            true => text(200, &format!("Reloaded {} stations", results.len())),
+            false => text(
+                500,
+                "Failed to aggregate the input again, so nothing changed",
+            ),
+        },
+        (_, "/stations" | "/reload") => text(405, "Method not allowed"),
+        (_, path) if path.starts_with("/stations/") => text(405, "Method not allowed"),
+        _ => text(404, "Not found"),
+    };
+
+    // The client hanging up before it's read the response is its own problem
+    let _ = request.respond(response);
+
+    Ok(())
+}
+
+fn json<'a>(
+    results: impl IntoIterator<Item = (&'a String, &'a Measurement)>,
+    args: &Args,
+) -> std::io::Result<Response<std::io::Cursor<Vec<u8>>>> {
+    let mut body = Vec::new();
+    Format::Json.write(&mut body, results, &args.output)?;
+
+    Ok(Response::from_data(body).with_header(header("application/json")))
+}
+
+fn text(status: u16, message: &str) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_data(format!("{}\n", message))
+        .with_status_code(status)
+        .with_header(header("text/plain; charset=utf-8"))
+}
+
+fn header(content_type: &str) -> Header {
+    Header::from_bytes("Content-Type", content_type).unwrap()
+}
+
+// Decodes the %XX escapes in a path segment, which has to come out as UTF-8
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = after;
+            continue;
+        }
+
+        let hex = after
+            .get(..2)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))?;
+        bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+        rest = &after[2..];
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+// Has SIGHUP ask for the results to be aggregated again, as daemons conventionally reload on it
+fn handle_hangup() {
+    #[cfg(unix)]
+    {
+        extern "C" fn hangup(_: libc::c_int) {
+            // Only async-signal-safe calls are allowed here, which an atomic store is
+            RELOAD.store(true, Ordering::Relaxed);
+        }
+
+        unsafe {
+            let mut action = std::mem::zeroed::<libc::sigaction>();
+            action.sa_sigaction = hangup as *const () as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut());
+        }
+    }
+}
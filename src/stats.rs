@@ -0,0 +1,142 @@
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+    thread::ThreadId,
+    time::Duration,
+};
+
+/// Collects how long each chunk took to aggregate and how many lines it held, for spotting when
+/// the work isn't split evenly between the threads. Every thread adds its chunks as it finishes
+/// them, which only takes a lock once per chunk.
+#[derive(Debug, Default)]
+pub struct ChunkStats {
+    chunks: Mutex<Vec<(ThreadId, ChunkStat)>>,
+}
+
+/// How a single chunk went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkStat {
+    /// Which thread aggregated it, numbered in the order the threads first finished a chunk.
+    pub thread: usize,
+    /// Where it starts in its file.
+    pub offset: usize,
+    /// How long it is.
+    pub bytes: usize,
+    /// How many lines it held, or records for a file in the binary format. Only the lines that
+    /// were sampled are counted when sampling.
+    pub lines: u64,
+    /// How long its lines took to parse and aggregate.
+    pub duration: Duration,
+}
+
+impl ChunkStats {
+    #[inline(always)]
+    pub(crate) fn record(&self, offset: usize, bytes: usize, lines: u64, duration: Duration) {
+        let chunk = ChunkStat {
+            thread: 0,
+            offset,
+            bytes,
+            lines,
+            duration,
+        };
+
+        self.chunks
+            .lock()
+            .unwrap()
+            .push((std::thread::current().id(), chunk));
+    }
+
+    /// Every chunk recorded so far, in order of where they start, so the position of each is its
+    /// index within the file. Several files' chunks are interleaved by their offsets.
+    pub fn chunks(&self) -> Vec<ChunkStat> {
+        let recorded = self.chunks.lock().unwrap();
+
+        let mut threads = Vec::<ThreadId>::new();
+        let mut chunks = Vec::with_capacity(recorded.len());
+        for (id, chunk) in recorded.iter() {
+            let thread = match threads.iter().position(|thread| thread == id) {
+                Some(thread) => thread,
+                None => {
+                    threads.push(*id);
+                    threads.len() - 1
+                }
+            };
+
+            chunks.push(ChunkStat {
+                thread,
+                ..chunk.clone()
+            });
+        }
+        chunks.sort_by_key(|chunk| chunk.offset);
+
+        chunks
+    }
+
+    /// Writes a table of every chunk, then the totals for each thread, and how much longer the
+    /// slowest chunk took than the average one.
+    pub fn write_table(&self, writer: &mut impl Write) -> io::Result<()> {
+        let chunks = self.chunks();
+
+        writeln!(
+            writer,
+            "{:>6} {:>6} {:>14} {:>12} {:>10} {:>12} {:>9}",
+            "chunk", "thread", "offset", "bytes", "lines", "time", "per line"
+        )?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            writeln!(
+                writer,
+                "{:>6} {:>6} {:>14} {:>12} {:>10} {:>12} {:>9}",
+                i,
+                chunk.thread,
+                chunk.offset,
+                chunk.bytes,
+                chunk.lines,
+                format!("{:.2?}", chunk.duration),
+                format!("{:.1?}", per_line(chunk.duration, chunk.lines)),
+            )?;
+        }
+
+        writeln!(
+            writer,
+            "\n{:>6} {:>6} {:>12} {:>10} {:>12}",
+            "thread", "chunks", "bytes", "lines", "time"
+        )?;
+        let threads = chunks.iter().map(|chunk| chunk.thread + 1).max().unwrap_or(0);
+        for thread in 0..threads {
+            let mine = || chunks.iter().filter(|chunk| chunk.thread == thread);
+            writeln!(
+                writer,
+                "{:>6} {:>6} {:>12} {:>10} {:>12}",
+                thread,
+                mine().count(),
+                mine().map(|chunk| chunk.bytes).sum::<usize>(),
+                mine().map(|chunk| chunk.lines).sum::<u64>(),
+                format!("{:.2?}", mine().map(|chunk| chunk.duration).sum::<Duration>()),
+            )?;
+        }
+
+        // An even split has every chunk taking about as long as the average
+        let total = chunks.iter().map(|chunk| chunk.duration).sum::<Duration>();
+        if let Some(slowest) = chunks.iter().map(|chunk| chunk.duration).max() {
+            let mean = total.as_secs_f64() / chunks.len() as f64;
+            if mean > 0.0 {
+                writeln!(
+                    writer,
+                    "\nThe slowest chunk took {:.2}x as long as the average",
+                    slowest.as_secs_f64() / mean
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// An empty chunk has no lines to average over. Durations only divide by a u32, which a chunk of
+// a big enough file has more lines than, so the average is worked out in nanoseconds instead
+pub(crate) fn per_line(duration: Duration, lines: u64) -> Duration {
+    duration
+        .as_nanos()
+        .checked_div(lines as u128)
+        .map_or(Duration::ZERO, |nanos| Duration::from_nanos(nanos as u64))
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use crate::{
+    binary::{self, is_binary},
+    chunk::{limit, memory_map, share, shared_chunks},
+    compression::Compression,
+    parse::parse,
+    search::byte_lines,
+    Options,
+};
+
+/// A line that couldn't be aggregated, because it isn't valid UTF-8, has no delimiter, or its
+/// measurement isn't a number.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Malformed {
+    /// Where the line starts in the input.
+    pub offset: usize,
+    /// The line itself, with any invalid UTF-8 replaced.
+    pub line: String,
+}
+
+#[inline(always)]
+fn is_valid(line: &[u8], options: &Options) -> bool {
+    std::str::from_utf8(line).is_ok_and(|line| parse(line, options).is_some())
+}
+
+// Finds every malformed line in a chunk that starts at `offset` in the file
+fn validate_chunk(chunk: &[u8], offset: usize, options: &Options) -> Vec<Malformed> {
+    byte_lines(chunk)
+        .filter(|line| !is_valid(line, options))
+        .map(|line| Malformed {
+            offset: offset + (line.as_ptr() as usize - chunk.as_ptr() as usize),
+            line: String::from_utf8_lossy(line).into_owned(),
+        })
+        .collect()
+}
+
+/// Checks that every line of the file at `path` could be aggregated, without aggregating any of
+/// them, and returns the lines that couldn't be in the order they appear. The file is split
+/// between threads the same way as for [`aggregate_file_with`](crate::aggregate_file_with).
+pub fn validate_file(path: &Path, options: &Options) -> io::Result<Vec<Malformed>> {
+    let mut file = File::open(path)?;
+    if let Some(compression) = Compression::detect(&mut file)? {
+        return validate_stream(BufReader::new(compression.decoder(file)), options);
+    }
+
+    let mmap = memory_map(&file, options)?;
+
+    // Binary files don't have lines, so the most we can do is check that every record is readable
+    if is_binary(&mmap) {
+        return binary::aggregate(&mmap, options).map(|_| Vec::new());
+    }
+
+    let mmap = limit(&mmap, options.limit_bytes);
+
+    let chunks = shared_chunks(mmap, options);
+
+    let mut malformed = Vec::new();
+    share(
+        chunks.len(),
+        options,
+        Vec::new,
+        |thread_malformed, i| {
+            let range = chunks[i].clone();
+            thread_malformed.extend(validate_chunk(&mmap[range.clone()], range.start, options));
+
+            Ok(())
+        },
+        |thread_malformed| malformed.extend(thread_malformed),
+    )?;
+
+    // The chunks finish in any order, but the lines should be listed in the order they appear
+    malformed.sort_unstable_by_key(|malformed| malformed.offset);
+
+    Ok(malformed)
+}
+
+/// Like [`validate_file`], for a stream such as stdin.
+pub fn validate_stream(mut reader: impl BufRead, options: &Options) -> io::Result<Vec<Malformed>> {
+    let mut malformed = Vec::new();
+    let mut line = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        let read = reader.read_until(b'\n', &mut line)?;
+        if read == 0
+            || options
+                .limit_bytes
+                .is_some_and(|limit| offset + read > limit)
+        {
+            break;
+        }
+
+        let trimmed = line.strip_suffix(b"\n").unwrap_or(&line);
+        if !is_valid(trimmed, options) {
+            malformed.push(Malformed {
+                offset,
+                line: String::from_utf8_lossy(trimmed).into_owned(),
+            });
+        }
+
+        offset += read;
+        line.clear();
+    }
+
+    Ok(malformed)
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::io::Write;
+
+use common::{format, generated, TempFile};
+use flate2::{write::GzEncoder, Compression};
+use one_brc::{aggregate_file_with, aggregate_with, Accumulator, Measurement, Options};
+
+const ROWS: u64 = 200_000;
+
+// Only counts the measurements, and the extremes in thousandths, which is all some uses need
+#[derive(Debug, PartialEq)]
+struct Extremes {
+    count: u64,
+    min: i32,
+    max: i32,
+}
+
+impl Accumulator for Extremes {
+    fn first(thousandths: i32, _offset: usize, _options: &Options) -> Self {
+        Self {
+            count: 1,
+            min: thousandths,
+            max: thousandths,
+        }
+    }
+
+    fn record(&mut self, thousandths: i32) {
+        self.count += 1;
+        self.min = self.min.min(thousandths);
+        self.max = self.max.max(thousandths);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+fn options(parallelism: usize) -> Options {
+    Options {
+        parallelism,
+        ..Options::default()
+    }
+}
+
+#[test]
+fn a_custom_accumulator_agrees_with_the_measurements() {
+    let contents = generated(ROWS, 1);
+    let file = TempFile::new("accumulator", &contents);
+
+    let measurements = aggregate_file_with(&file.0, &options(1)).unwrap();
+
+    for parallelism in [1, 3, 8] {
+        let extremes = aggregate_with::<Extremes>(&file.0, &options(parallelism)).unwrap();
+
+        assert!(extremes.keys().eq(measurements.keys()), "{}", parallelism);
+        for (city, measurement) in &measurements {
+            let extremes = &extremes[city];
+            assert_eq!(extremes.count, measurement.count());
+            assert_eq!(extremes.min as f64 / 1000.0, measurement.min());
+            assert_eq!(extremes.max as f64 / 1000.0, measurement.max());
+        }
+    }
+}
+
+#[test]
+fn measurements_are_an_accumulator_like_any_other() {
+    let contents = generated(ROWS, 2);
+    let file = TempFile::new("accumulator-measurements", &contents);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(&contents).unwrap();
+    let compressed = TempFile::new("accumulator-gzip", &encoder.finish().unwrap());
+
+    let expected = format(&aggregate_file_with(&file.0, &options(4)).unwrap());
+
+    for path in [&file.0, &compressed.0] {
+        let results = aggregate_with::<Measurement>(path, &options(4)).unwrap();
+
+        assert_eq!(format(&results), expected);
+    }
+}
+
+#[test]
+fn binary_files_only_hold_measurements() {
+    let mut converted = Vec::new();
+    one_brc::convert(
+        generated(1_000, 3).as_slice(),
+        &mut converted,
+        &Options::default(),
+    )
+    .unwrap();
+    let binary = TempFile::new("accumulator-binary", &converted);
+
+    let error = aggregate_with::<Extremes>(&binary.0, &options(1)).unwrap_err();
+
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+// Measurements arrive in thousandths, so that up to three decimal places survive
+#[test]
+fn accumulators_are_given_thousandths() {
+    let file = TempFile::new("accumulator-units", b"Foo;12.3\nFoo;-0.05\n");
+
+    let extremes = aggregate_with::<Extremes>(&file.0, &options(1)).unwrap();
+
+    assert_eq!(
+        extremes["Foo"],
+        Extremes {
+            count: 2,
+            min: -50,
+            max: 12_300
+        }
+    );
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use std::io;
+
+use common::{format, generated, reference, TempFile};
+use one_brc::{aggregate_file, convert, Options};
+
+fn converted(contents: &[u8]) -> io::Result<Vec<u8>> {
+    let mut binary = Vec::new();
+    convert(contents, &mut binary, &Options::default())?;
+
+    Ok(binary)
+}
+
+#[test]
+fn converted_files_match_the_reference() {
+    let contents = generated(200_000, 40);
+    let file = TempFile::new("converted", &converted(&contents).unwrap());
+
+    for parallelism in [1, 3, 4] {
+        let results = aggregate_file(&file.0, parallelism).unwrap();
+
+        assert_eq!(
+            format(&results),
+            reference(&contents),
+            "{} threads",
+            parallelism
+        );
+    }
+}
+
+#[test]
+fn measurements_beyond_tenths_cant_be_converted() {
+    for contents in ["Foo;1.25\n", "Foo;4000.0\n"] {
+        let error = converted(contents.as_bytes()).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", contents);
+    }
+}
+
+#[test]
+fn truncated_binary_files_are_rejected() {
+    let binary = converted(&generated(1_000, 41)).unwrap();
+    let file = TempFile::new("truncated", &binary[..binary.len() - 5]);
+
+    let error = aggregate_file(&file.0, 1).err().unwrap();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::{path::Path, time::Duration};
+
+use common::{format, generated, reference, TempFile};
+use one_brc::{aggregate_file_with, Checkpoint, Options};
+
+// Checkpoints after every segment, with segments small enough that a test file has plenty of them
+fn options(checkpoint: &Path) -> Options {
+    Options {
+        parallelism: 2,
+        checkpoint: Some(Checkpoint {
+            segment_size: 64 * 1024,
+            ..Checkpoint::new(checkpoint, Duration::ZERO)
+        }),
+        ..Options::default()
+    }
+}
+
+// Generated contents, with the last line replaced by one of the same length that can't be parsed
+fn broken(contents: &[u8]) -> Vec<u8> {
+    let mut broken = contents.to_vec();
+    let end = broken.len() - 1;
+    let last = broken[..end]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .unwrap()
+        + 1;
+    broken[last..end].fill(b'x');
+
+    broken
+}
+
+fn checkpoint_path(name: &str) -> TempFile {
+    TempFile(std::env::temp_dir().join(format!("one-brc-{}-{}.ckpt", std::process::id(), name)))
+}
+
+/*
+ * A malformed last line fails the run after every segment before it has been checkpointed. Once
+ * it's fixed, resuming has to aggregate exactly the rest of the file. To tell that the run really
+ * resumed, the first line is changed as well, which only a run starting over would see.
+ */
+#[test]
+fn resumes_where_a_failed_run_left_off() {
+    let contents = generated(100_000, 20);
+    let file = TempFile::new("checkpoint-resume", &broken(&contents));
+    let checkpoint = checkpoint_path("resume");
+
+    assert!(aggregate_file_with(&file.0, &options(&checkpoint.0)).is_err());
+    assert!(checkpoint.0.exists());
+
+    let mut changed = contents.clone();
+    let first = changed.iter().position(|&byte| byte == b'\n').unwrap() - 1;
+    changed[first] = if changed[first] == b'9' { b'0' } else { b'9' };
+    std::fs::write(&file.0, &changed).unwrap();
+
+    let results = aggregate_file_with(&file.0, &options(&checkpoint.0)).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+    assert!(!checkpoint.0.exists());
+}
+
+#[test]
+fn refuses_a_checkpoint_of_another_file() {
+    let contents = generated(100_000, 21);
+    let file = TempFile::new("checkpoint-other", &broken(&contents));
+    let checkpoint = checkpoint_path("other");
+
+    assert!(aggregate_file_with(&file.0, &options(&checkpoint.0)).is_err());
+
+    std::fs::write(&file.0, &contents[..contents.len() / 2]).unwrap();
+    let error = aggregate_file_with(&file.0, &options(&checkpoint.0))
+        .err()
+        .unwrap();
+
+    assert!(error.to_string().contains("checkpoint was taken of a file"));
+    assert!(checkpoint.0.exists());
+}
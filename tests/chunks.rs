@@ -0,0 +1,283 @@
+mod common;
+
+use std::io::Write;
+
+use common::{format, generated, reference, TempFile};
+use one_brc::{aggregate_file_with, Options};
+
+#[test]
+fn chunks_cover_every_line_exactly_once() {
+    let contents = generated(300_000, 20);
+    let total = contents.split(|&byte| byte == b'\n').count() - 1;
+
+    for parallelism in [1, 2, 3, 4, 7, 16] {
+        let chunks = one_brc::chunks(&contents, parallelism);
+
+        // The chunks tile the file, each picking up exactly where the last left off
+        assert_eq!(chunks.first().unwrap().start, 0);
+        assert_eq!(chunks.last().unwrap().end, contents.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_eq!(contents[pair[0].end - 1], b'\n');
+        }
+
+        let lines = chunks
+            .iter()
+            .map(|chunk| {
+                contents[chunk.clone()]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count()
+            })
+            .sum::<usize>();
+        assert_eq!(lines, total, "split between {} threads", parallelism);
+    }
+}
+
+#[test]
+fn chunks_keep_a_line_without_a_trailing_newline() {
+    let mut contents = generated(300_000, 21);
+    contents.extend_from_slice(b"Last;1.0");
+
+    for parallelism in [1, 2, 5] {
+        let chunks = one_brc::chunks(&contents, parallelism);
+
+        assert_eq!(chunks.last().unwrap().end, contents.len());
+        assert!(contents[chunks.last().unwrap().clone()].ends_with(b"Last;1.0"));
+    }
+}
+
+// Wherever the chunk boundaries fall, each station's first offset is where it's first seen in the
+// whole file
+#[test]
+fn first_offsets_are_where_each_station_first_appears() {
+    let contents = generated(300_000, 22);
+    let file = common::TempFile::new("first-offsets", &contents);
+
+    let mut expected = std::collections::BTreeMap::new();
+    let mut offset = 0;
+    for line in contents.split_inclusive(|&byte| byte == b'\n') {
+        let station = std::str::from_utf8(line)
+            .unwrap()
+            .rsplit_once(';')
+            .unwrap()
+            .0;
+        expected.entry(station.to_owned()).or_insert(offset as u64);
+        offset += line.len();
+    }
+
+    for parallelism in [1, 4] {
+        let options = one_brc::Options {
+            parallelism,
+            debug_offsets: true,
+            ..one_brc::Options::default()
+        };
+        let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+        for (station, measurement) in &results {
+            assert_eq!(
+                measurement.first_offset(),
+                Some(expected[station]),
+                "{}",
+                station
+            );
+        }
+    }
+    assert!(one_brc::aggregate_file(&file.0, 4)
+        .unwrap()
+        .values()
+        .all(|measurement| measurement.first_offset().is_none()));
+}
+
+// Where the file would be cut in equal parts falls partway through a character of these names,
+// which the chunks mustn't split whether or not the input is trusted to be UTF-8
+#[test]
+fn chunks_never_split_a_multibyte_name() {
+    let names = ["東京", "Zürich", "Ōsaka", "Αθήνα", "😀 Smile"];
+    let mut contents = Vec::new();
+    for i in 0..100_000 {
+        writeln!(contents, "{};{}.{}", names[i % names.len()], i % 50, i % 10).unwrap();
+    }
+    let expected = reference(&contents);
+    let file = TempFile::new("chunks-multibyte", &contents);
+
+    let is_continuation = |byte: u8| byte & 0b1100_0000 == 0b1000_0000;
+    let splitting = (2..32)
+        .filter(|&parallelism| {
+            let size = contents.len() / parallelism;
+            (1..parallelism).any(|i| is_continuation(contents[i * size]))
+        })
+        .collect::<Vec<_>>();
+    assert!(!splitting.is_empty());
+
+    for parallelism in splitting {
+        for chunk in one_brc::chunks(&contents, parallelism) {
+            assert!(std::str::from_utf8(&contents[chunk]).is_ok());
+        }
+
+        for trust_utf8 in [false, true] {
+            let options = Options {
+                parallelism,
+                chunks_per_thread: 1,
+                trust_utf8,
+                ..Options::default()
+            };
+            let results = aggregate_file_with(&file.0, &options).unwrap();
+
+            assert_eq!(format(&results), expected, "{} threads", parallelism);
+        }
+    }
+}
+
+#[test]
+fn cached_chunks_are_reused_only_while_the_file_is_unchanged() {
+    let contents = generated(200_000, 24);
+    let file = TempFile::new("chunks-cached", &contents);
+    let mut cache = file.0.clone().into_os_string();
+    cache.push(".chunks");
+
+    let options = Options {
+        parallelism: 4,
+        cache_chunks: true,
+        ..Options::default()
+    };
+
+    // The first run writes the cache, and the second splits the file by it
+    for _ in 0..2 {
+        let results = aggregate_file_with(&file.0, &options).unwrap();
+
+        assert_eq!(format(&results), reference(&contents));
+        assert!(std::path::Path::new(&cache).exists());
+    }
+
+    // A file of another length can't be split where the old one was
+    let changed = generated(150_000, 25);
+    std::fs::write(&file.0, &changed).unwrap();
+    let results = aggregate_file_with(&file.0, &options).unwrap();
+    assert_eq!(format(&results), reference(&changed));
+
+    // Nor can a cache that isn't one be trusted
+    std::fs::write(&cache, b"not a cache").unwrap();
+    let results = aggregate_file_with(&file.0, &options).unwrap();
+    assert_eq!(format(&results), reference(&changed));
+}
+
+#[test]
+fn striped_chunks_start_at_the_first_line_past_a_stripe() {
+    let contents = generated(300_000, 26);
+
+    for stripe in [1, 4096, 100_000, 1 << 20] {
+        let chunks = one_brc::striped_chunks(&contents, 12, stripe);
+
+        assert_eq!(chunks.first().unwrap().start, 0);
+        assert_eq!(chunks.last().unwrap().end, contents.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+
+            // Just past a newline, with no other newline between it and the stripe before it
+            let start = pair[1].start;
+            let boundary = (start - 1) / stripe * stripe;
+            assert_eq!(contents[start - 1], b'\n');
+            assert!(!contents[boundary..start - 1].contains(&b'\n'));
+        }
+    }
+
+    // A stripe of 0 is no stripe at all, rather than a division by zero
+    assert_eq!(
+        one_brc::striped_chunks(&contents, 12, 0),
+        one_brc::chunks(&contents, 12)
+    );
+
+    // Only the chunks' boundaries move, so the results are the same
+    let file = TempFile::new("chunks-striped", &contents);
+    let options = Options {
+        parallelism: 3,
+        stripe_size: Some(100_000),
+        ..Options::default()
+    };
+    let results = aggregate_file_with(&file.0, &options).unwrap();
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn chunk_stats_account_for_every_line() {
+    let contents = generated(300_000, 28);
+    let file = TempFile::new("chunk-stats", &contents);
+
+    let stats = std::sync::Arc::new(one_brc::ChunkStats::default());
+    let options = Options {
+        parallelism: 3,
+        chunk_stats: Some(stats.clone()),
+        ..Options::default()
+    };
+    aggregate_file_with(&file.0, &options).unwrap();
+
+    let chunks = stats.chunks();
+    assert_eq!(chunks.len(), one_brc::chunks(&contents, 3 * 4).len());
+    assert_eq!(chunks.iter().map(|chunk| chunk.lines).sum::<u64>(), 300_000);
+
+    let mut offset = 0;
+    for chunk in &chunks {
+        assert_eq!(chunk.offset, offset);
+        assert!(chunk.thread < 3);
+        offset += chunk.bytes;
+    }
+    assert_eq!(offset, contents.len());
+
+    let mut table = Vec::new();
+    stats.write_table(&mut table).unwrap();
+    assert!(String::from_utf8(table).unwrap().contains("slowest chunk"));
+}
+
+// Where the file would be cut falls partway through these names, hundreds of bytes from the
+// newline the chunk has to end at instead, and one line is longer than a whole chunk
+#[test]
+fn chunks_never_split_a_long_name() {
+    let names = (0..10)
+        .map(|i| {
+            format!(
+                "{}{}",
+                char::from(b'A' + i),
+                "long name ".repeat(40 + 7 * i as usize)
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut contents = Vec::new();
+    for i in 0..2_000 {
+        writeln!(contents, "{};{}.{}", names[i % names.len()], i % 50, i % 10).unwrap();
+    }
+    writeln!(contents, "{};1.0", "x".repeat(500_000)).unwrap();
+    contents.extend_from_slice(&generated(10_000, 22));
+    let expected = reference(&contents);
+    let file = TempFile::new("chunks-long", &contents);
+
+    // At least some of the cuts are much further from a newline than the usual line's length
+    let to_newline = |at: usize| {
+        contents[at..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .unwrap()
+    };
+    assert!((2..16).any(|parallelism: usize| {
+        let size = contents.len() / parallelism;
+        (1..parallelism).any(|i| to_newline(i * size) > 100)
+    }));
+
+    for parallelism in [2, 3, 4, 7, 16] {
+        let chunks = one_brc::chunks(&contents, parallelism);
+        assert_eq!(chunks.last().unwrap().end, contents.len());
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start);
+            assert_eq!(contents[pair[0].end - 1], b'\n');
+        }
+
+        let options = Options {
+            parallelism,
+            chunks_per_thread: 1,
+            ..Options::default()
+        };
+        let results = aggregate_file_with(&file.0, &options).unwrap();
+
+        assert_eq!(format(&results), expected, "{} threads", parallelism);
+    }
+}
@@ -0,0 +1,635 @@
+mod common;
+
+use std::process::Command;
+
+use common::TempFile;
+
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_one-brc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Scripts compare stdout against the expected results, so nothing else can end up there
+#[test]
+fn stdout_only_contains_the_results() {
+    let file = TempFile::new("stdout", b"Foo;1.0\nBar;2.0\nFoo;3.0\n");
+    let path = file.0.to_str().unwrap();
+
+    for args in [vec![path], vec![path, "--verbose"], vec![path, "--bench"]] {
+        let output = run(&args);
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        // The separators used to be "removed" with a backspace, which only looked right on a
+        // terminal
+        assert!(!stdout.contains('\x08') && !stdout.contains(", }"));
+        assert_eq!(stdout, "{Bar=2.0/2.0/2.0, Foo=1.0/3.0/2.0}\n", "{:?}", args);
+    }
+}
+
+#[test]
+fn diagnostics_only_go_to_stderr_when_verbose() {
+    let file = TempFile::new("stderr", b"Foo;1.0\n");
+    let path = file.0.to_str().unwrap();
+
+    assert!(run(&[path]).stderr.is_empty());
+    assert!(String::from_utf8(run(&[path, "-v"]).stderr)
+        .unwrap()
+        .contains("Processed 1 lines"));
+}
+
+// The mean across every station is weighted by how many measurements each has, rather than being
+// the mean of their means, which would be 5.5 here
+#[test]
+fn the_summary_is_weighted_by_count() {
+    let file = TempFile::new("summary", b"Foo;1.0\nFoo;1.0\nFoo;1.0\nBar;10.0\n");
+    let path = file.0.to_str().unwrap();
+
+    let output = run(&[path, "--summary", "--with-count"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Bar=10.0/10.0/10.0/1, Foo=1.0/1.0/1.0/3, __ALL__=1.0/10.0/3.3/4}\n"
+    );
+}
+
+// Malformed lines are normally counted on stderr, which a pipeline wrapping us doesn't want
+#[test]
+fn quiet_only_writes_the_results() {
+    let file = TempFile::new("quiet", b"Foo;1.0\nnot a line\nBar;2.0\n");
+    let path = file.0.to_str().unwrap();
+
+    let output = run(&[path, "--quiet", "--on-error", "skip", "--format", "json"]);
+
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty(), "{:?}", output.stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with('{') && stdout.contains("\"Foo\""));
+
+    let output = run(&[path, "--quiet", "--on-error", "skip", "--no-result"]);
+
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty() && output.stderr.is_empty());
+}
+
+#[test]
+fn an_empty_file_writes_empty_results() {
+    let file = TempFile::new("cli-empty", b"");
+    let path = file.0.to_str().unwrap();
+
+    for args in [
+        vec![path],
+        vec![path, "--threads", "4"],
+        vec![path, "--bench"],
+    ] {
+        let output = run(&args);
+
+        assert!(output.status.success(), "{:?}", args);
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "{}\n");
+    }
+}
+
+#[test]
+fn excluded_stations_are_left_out() {
+    let file = TempFile::new(
+        "exclude",
+        b"Foo;1.0\nSensor_Debug;99.0\nBar;2.0\nTest_Station;-99.0\nFoo;3.0\n",
+    );
+    let path = file.0.to_str().unwrap();
+
+    let output = run(&[path, "--exclude", "Sensor_Debug,Test_Station", "--summary"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Bar=2.0/2.0/2.0, Foo=1.0/3.0/2.0, __ALL__=1.0/3.0/2.0}\n"
+    );
+}
+
+// A container limited to a single core is told there's only the one, which ONEBRC_THREADS can
+// override, and --threads overrides in turn
+#[cfg(target_os = "linux")]
+#[test]
+fn threads_come_from_the_flag_then_the_environment_then_the_cores() {
+    use std::os::unix::process::CommandExt;
+
+    let file = TempFile::new("threads", b"Foo;1.0\nBar;2.0\nFoo;3.0\n");
+    let path = file.0.to_str().unwrap();
+
+    let parallelism = |env: Option<&str>, args: &[&str]| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_one-brc"));
+        command.args(args).arg(path).arg("--verbose");
+        match env {
+            Some(threads) => command.env("ONEBRC_THREADS", threads),
+            None => command.env_remove("ONEBRC_THREADS"),
+        };
+
+        // Pinned to the first core, the cores available to it are counted as one
+        unsafe {
+            command.pre_exec(|| {
+                let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+                libc::CPU_SET(0, &mut set);
+                match libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) {
+                    0 => Ok(()),
+                    _ => Err(std::io::Error::last_os_error()),
+                }
+            });
+        }
+
+        let output = command.output().unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        if !output.status.success() {
+            return Err(stderr);
+        }
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "{Bar=2.0/2.0/2.0, Foo=1.0/3.0/2.0}\n"
+        );
+        Ok(stderr
+            .lines()
+            .find_map(|line| line.strip_prefix("Parallelism: "))
+            .unwrap()
+            .to_owned())
+    };
+
+    assert_eq!(parallelism(None, &[]).unwrap(), "1");
+    assert_eq!(parallelism(Some("3"), &[]).unwrap(), "3");
+    assert_eq!(parallelism(Some("3"), &["--threads", "2"]).unwrap(), "2");
+    assert!(parallelism(Some("none"), &[])
+        .unwrap_err()
+        .contains("for ONEBRC_THREADS"));
+}
+
+#[test]
+fn reducing_the_partials_of_each_half_matches_a_single_run() {
+    let mut input = Vec::new();
+    for i in 0..2_000 {
+        input.extend_from_slice(
+            format!("Station{};{}.{}\n", i % 37, i % 90 - 40, i % 10).as_bytes(),
+        );
+    }
+    input.extend_from_slice(b"malformed\n");
+
+    // Splitting just after a newline near the middle keeps every line whole in one half or the other
+    let middle = input[..input.len() / 2]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .unwrap()
+        + 1;
+    let whole = TempFile::new("map-whole", &input);
+    let halves = [
+        TempFile::new("map-first", &input[..middle]),
+        TempFile::new("map-second", &input[middle..]),
+    ];
+    let partials = [
+        TempFile::new("map-first-partial", b""),
+        TempFile::new("map-second-partial", b""),
+    ];
+
+    for (half, partial) in halves.iter().zip(&partials) {
+        let output = run(&[
+            "map",
+            half.0.to_str().unwrap(),
+            "--on-error",
+            "skip",
+            "--output",
+            partial.0.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+
+    let single = run(&[
+        whole.0.to_str().unwrap(),
+        "--on-error",
+        "skip",
+        "--summary",
+    ]);
+    let reduced = run(&[
+        "reduce",
+        partials[0].0.to_str().unwrap(),
+        partials[1].0.to_str().unwrap(),
+        "--summary",
+    ]);
+
+    assert!(reduced.status.success());
+    assert_eq!(
+        String::from_utf8(reduced.stdout).unwrap(),
+        String::from_utf8(single.stdout).unwrap()
+    );
+    assert_eq!(
+        String::from_utf8(reduced.stderr).unwrap(),
+        String::from_utf8(single.stderr).unwrap()
+    );
+}
+
+#[test]
+fn reduce_refuses_anything_but_a_partial() {
+    let file = TempFile::new("not-a-partial", b"Foo;1.0\n");
+
+    let output = run(&["reduce", file.0.to_str().unwrap()]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("not a partial"));
+}
+
+// A station's median would otherwise come from a histogram of only some of its measurements
+#[test]
+fn reduce_refuses_to_mix_partials_with_and_without_histograms() {
+    let file = TempFile::new("mixed-input", b"Foo;1.0\nFoo;2.0\nFoo;9.0\n");
+    let with = TempFile::new("mixed-with-histograms", b"");
+    let without = TempFile::new("mixed-without-histograms", b"");
+
+    for (partial, stats) in [(&with, &["--stats", "median"][..]), (&without, &[])] {
+        let mut args = vec![
+            "map",
+            file.0.to_str().unwrap(),
+            "--output",
+            partial.0.to_str().unwrap(),
+        ];
+        args.extend_from_slice(stats);
+        assert!(run(&args).status.success());
+    }
+
+    for partials in [[&with, &without], [&without, &with]] {
+        let output = run(&[
+            "reduce",
+            partials[0].0.to_str().unwrap(),
+            partials[1].0.to_str().unwrap(),
+            "--stats",
+            "median",
+        ]);
+
+        assert_eq!(output.status.code(), Some(1));
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("partials with histograms can't be merged with ones without"));
+    }
+
+    let output = run(&[
+        "reduce",
+        with.0.to_str().unwrap(),
+        with.0.to_str().unwrap(),
+        "--stats",
+        "median",
+    ]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn percentiles_outside_0_to_100_are_rejected() {
+    let file = TempFile::new("percentiles", b"Foo;1.0\nFoo;2.0\n");
+    let path = file.0.to_str().unwrap();
+
+    for percentiles in ["101", "-1", "50,100.5", "fifty"] {
+        let output = run(&[path, "--percentiles", percentiles]);
+
+        assert_eq!(output.status.code(), Some(2), "{}", percentiles);
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("expected a number from 0 to 100"));
+    }
+
+    let output = run(&[path, "--percentiles", "0,100"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Foo=1.0/2.0/1.5/1.0/2.0}\n"
+    );
+}
+
+#[test]
+fn the_stddev_is_written_after_the_mean() {
+    let file = TempFile::new("stddev", b"Foo;1.0\nFoo;2.0\nFoo;3.0\nFoo;4.0\n");
+    let path = file.0.to_str().unwrap();
+
+    let output = run(&[path, "--with-stddev"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Foo=1.0/4.0/2.5/1.1}\n"
+    );
+}
+
+// A tab is hard to type into a shell, so the two characters \t stand for one
+#[test]
+fn tabs_can_be_given_as_the_delimiter() {
+    let file = TempFile::new("tab-delimited", b"Foo\t1.0\nBar; Baz\t2.0\nFoo\t3.0\n");
+    let path = file.0.to_str().unwrap();
+
+    for delimiter in ["\\t", "\t"] {
+        let output = run(&[path, "--delimiter", delimiter]);
+
+        assert!(output.status.success(), "{:?}", delimiter);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "{Bar; Baz=2.0/2.0/2.0, Foo=1.0/3.0/2.0}\n"
+        );
+    }
+}
+
+// The delimiter is searched for as a single byte, so a character that takes several can't be one
+#[test]
+fn multi_byte_delimiters_are_rejected() {
+    let file = TempFile::new("multi-byte-delimited", "Foo→1.0\n".as_bytes());
+    let path = file.0.to_str().unwrap();
+
+    for delimiter in ["→", ";;", ""] {
+        let output = run(&[path, "--delimiter", delimiter]);
+
+        assert_eq!(output.status.code(), Some(2), "{:?}", delimiter);
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("expected a single byte character"));
+    }
+}
+
+#[test]
+fn a_stripe_of_0_bytes_is_rejected() {
+    let file = TempFile::new("stripe-size", b"Foo;1.0\n");
+    let path = file.0.to_str().unwrap();
+
+    let output = run(&[path, "--stripe-size", "0"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("--stripe-size has to be at least 1 byte"));
+
+    let output = run(&[path, "--stripe-size", "4096"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{Foo=1.0/1.0/1.0}\n"
+    );
+}
+
+#[test]
+fn checkpoint_intervals_too_long_to_count_are_rejected() {
+    let file = TempFile::new("checkpoint-interval", b"Foo;1.0\n");
+    let checkpoint = TempFile::new("checkpoint-interval-checkpoint", b"");
+    std::fs::remove_file(&checkpoint.0).unwrap();
+
+    for interval in ["99999999999999999h", "18446744073709551616s", "5x"] {
+        let output = run(&[
+            file.0.to_str().unwrap(),
+            "--checkpoint",
+            checkpoint.0.to_str().unwrap(),
+            "--checkpoint-interval",
+            interval,
+        ]);
+
+        assert_eq!(output.status.code(), Some(2), "{}", interval);
+        assert!(String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("invalid duration"));
+    }
+}
+
+// The station orders of each --sort, with ties in the count broken by name
+#[test]
+fn each_sort_order_orders_the_stations() {
+    let file = TempFile::new(
+        "sort",
+        b"Foo;1.0\nBar;5.0\nBaz;-3.0\nBaz;-1.0\nQux;2.0\nQux;2.0\nQux;2.0\nFoo;7.0\n",
+    );
+    let path = file.0.to_str().unwrap();
+
+    for (sort, expected) in [
+        ("name", ["Bar", "Baz", "Foo", "Qux"]),
+        ("name-desc", ["Qux", "Foo", "Baz", "Bar"]),
+        ("mean", ["Bar", "Foo", "Qux", "Baz"]),
+        ("max", ["Foo", "Bar", "Qux", "Baz"]),
+        ("count", ["Qux", "Baz", "Foo", "Bar"]),
+    ] {
+        let output = run(&[path, "--sort", sort, "--format", "tsv"]);
+
+        assert!(output.status.success(), "{}", sort);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stations = stdout
+            .lines()
+            .map(|line| line.split('\t').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(stations, expected, "--sort {}", sort);
+    }
+
+    let output = run(&[path, "--sort", "min"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("unknown sort order"));
+}
+
+// --top keeps the highest stations by --by, or by their max when it isn't given
+#[test]
+fn top_writes_only_the_highest_stations() {
+    let file = TempFile::new(
+        "top",
+        b"Foo;1.0\nBar;5.0\nBaz;-3.0\nBaz;-1.0\nQux;2.0\nQux;2.0\nQux;2.0\nFoo;7.0\n",
+    );
+    let path = file.0.to_str().unwrap();
+
+    for (args, expected) in [
+        (vec!["--top", "2"], "{Foo=1.0/7.0/4.0, Bar=5.0/5.0/5.0}\n"),
+        (
+            vec!["--top", "2", "--by", "mean"],
+            "{Bar=5.0/5.0/5.0, Foo=1.0/7.0/4.0}\n",
+        ),
+        (vec!["--top", "1", "--by", "count"], "{Qux=2.0/2.0/2.0}\n"),
+        (vec!["--top", "0"], "{}\n"),
+        // Asking for more stations than there are writes every one of them
+        (
+            vec!["--top", "10", "--by", "max"],
+            "{Foo=1.0/7.0/4.0, Bar=5.0/5.0/5.0, Qux=2.0/2.0/2.0, Baz=-3.0/-1.0/-2.0}\n",
+        ),
+    ] {
+        let output = run(&[&[path][..], &args].concat());
+
+        assert!(output.status.success(), "{:?}", args);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{:?}",
+            args
+        );
+    }
+
+    for args in [["--top", "x"], ["--by", "name"]] {
+        let output = run(&[path, "--top", "1", args[0], args[1]]);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+}
+
+// Names are matched exactly, and a station that isn't in the input is written with no statistics
+// unless it's asked to be an error
+#[test]
+fn only_writes_the_named_stations() {
+    let file = TempFile::new("only", b"Foo;1.0\nBar;5.0\nQux;2.0\nFoo;7.0\n");
+    let path = file.0.to_str().unwrap();
+
+    for (args, expected) in [
+        (
+            vec!["--only", "Foo,Qux"],
+            "{Foo=1.0/7.0/4.0, Qux=2.0/2.0/2.0}\n",
+        ),
+        (vec!["--only", "foo"], "{foo=NA/NA/NA}\n"),
+        (
+            vec!["--only", "Foo,Nowhere", "--missing", "na"],
+            "{Foo=1.0/7.0/4.0, Nowhere=NA/NA/NA}\n",
+        ),
+        (
+            vec!["--only", "Foo,Nowhere", "--format", "csv"],
+            "city,min,max,mean,count\nFoo,1.0,7.0,4.0,2\nNowhere,,,,0\n",
+        ),
+    ] {
+        let output = run(&[&[path][..], &args].concat());
+
+        assert!(output.status.success(), "{:?}", args);
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{:?}",
+            args
+        );
+    }
+
+    let output = run(&[path, "--only", "Foo,Nowhere", "--missing", "error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("Nowhere does not appear in the input"));
+}
+
+// The file gets exactly what stdout would have, and stdout gets nothing
+#[test]
+fn output_writes_the_results_to_a_file() {
+    let file = TempFile::new("output-input", b"Foo;1.0\nBar;2.0\nFoo;3.0\n");
+    let path = file.0.to_str().unwrap();
+    let destination = TempFile::new("output-results", b"left over from before");
+
+    for format in ["1brc", "json", "csv"] {
+        let output = run(&[
+            path,
+            "--format",
+            format,
+            "--output",
+            destination.0.to_str().unwrap(),
+        ]);
+
+        assert!(output.status.success(), "{}", format);
+        assert!(output.stdout.is_empty());
+        assert_eq!(
+            std::fs::read(&destination.0).unwrap(),
+            run(&[path, "--format", format]).stdout,
+            "{}",
+            format
+        );
+    }
+
+    // Failing to create or write to the file is an error rather than a panic
+    let mut unwritable = vec!["/nonexistent/results.txt"];
+    if cfg!(target_os = "linux") {
+        unwritable.push("/dev/full");
+    }
+    for destination in unwritable {
+        let output = run(&[path, "--output", destination]);
+
+        assert_eq!(output.status.code(), Some(1), "{}", destination);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            !stderr.is_empty() && !stderr.contains("panicked"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+// However the file is split between threads, the last report has every byte of it processed
+#[test]
+fn progress_reaches_the_size_of_the_file() {
+    let contents = common::generated(200_000, 29);
+    let file = TempFile::new("progress", &contents);
+    let path = file.0.to_str().unwrap();
+
+    for threads in ["1", "4"] {
+        let output = run(&[path, "--progress", "--threads", threads]);
+
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        let last = stderr.trim_end().rsplit('\r').next().unwrap();
+        assert_eq!(
+            last,
+            format!(
+                "Processed {} of {} bytes (100.0%)",
+                contents.len(),
+                contents.len()
+            ),
+            "{} threads",
+            threads
+        );
+    }
+
+    // A stream's size isn't known up front, so only the bytes read are reported
+    let mut child = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+        .args(["-", "--progress"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(&mut child.stdin.take().unwrap(), &contents).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(
+        stderr.trim_end().rsplit('\r').next().unwrap(),
+        format!("Processed {} bytes", contents.len())
+    );
+}
+
+// Files named after a subcommand are aggregated like any other once they follow a `--`, as are
+// files whose names look like an option
+#[test]
+fn files_named_after_a_subcommand_follow_a_double_dash() {
+    let dir = std::env::temp_dir().join(format!("one-brc-{}-subcommands", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    for name in ["map", "reduce", "serve", "generate", "convert", "--top"] {
+        std::fs::write(dir.join(name), format!("{};1.0\n", name)).unwrap();
+    }
+
+    let aggregate = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", args);
+
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    for name in ["map", "reduce", "serve", "generate", "convert"] {
+        assert_eq!(
+            aggregate(&["--", name]),
+            format!("{{{}=1.0/1.0/1.0}}\n", name)
+        );
+    }
+    assert_eq!(
+        aggregate(&["--", "--top", "map"]),
+        "{--top=1.0/1.0/1.0, map=1.0/1.0/1.0}\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
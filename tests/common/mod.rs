@@ -0,0 +1,119 @@
+// Each of the integration tests only uses some of these
+#![allow(dead_code)]
+
+use std::{
+    collections::BTreeMap,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use one_brc::{Format, Measurement, OutputOptions};
+
+// A file in the temporary directory that's removed again once the test is done with it
+pub struct TempFile(pub PathBuf);
+
+impl TempFile {
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("one-brc-{}-{}.txt", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+
+        Self(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+
+        // The CLI caches where it split a file next to it
+        let mut chunks = self.0.clone().into_os_string();
+        chunks.push(".chunks");
+        let _ = std::fs::remove_file(chunks);
+    }
+}
+
+// A deterministic file of generated measurements
+pub fn generated(rows: u64, seed: u64) -> Vec<u8> {
+    let mut contents = Vec::new();
+    one_brc::generate(&mut contents, rows, seed).unwrap();
+
+    contents
+}
+
+/*
+ * The simplest aggregation we can write, which the optimised pipeline has to agree with. It's
+ * deliberately kept apart from the library: every line is split with the standard library, and
+ * the measurements are summed as whole tenths, which the generated files always have.
+ */
+pub fn reference(contents: &[u8]) -> String {
+    // min, max, sum and count, all in tenths
+    let mut stations = BTreeMap::<&str, (i64, i64, i64, i64)>::new();
+
+    for line in std::str::from_utf8(contents).unwrap().lines() {
+        let (station, measurement) = line.rsplit_once(';').unwrap();
+        let tenths = measurement.replace('.', "").parse::<i64>().unwrap();
+
+        let entry = stations
+            .entry(station)
+            .or_insert((i64::MAX, i64::MIN, 0, 0));
+        entry.0 = entry.0.min(tenths);
+        entry.1 = entry.1.max(tenths);
+        entry.2 += tenths;
+        entry.3 += 1;
+    }
+
+    let tenths = |t: i64| {
+        format!(
+            "{}{}.{}",
+            if t < 0 { "-" } else { "" },
+            t.abs() / 10,
+            t.abs() % 10
+        )
+    };
+
+    let mut entries = Vec::new();
+    for (station, (min, max, sum, count)) in stations {
+        // The mean rounds halves towards positive infinity
+        let mean = (2 * sum + count).div_euclid(2 * count);
+
+        entries.push(format!(
+            "{}={}/{}/{}/{}",
+            station,
+            tenths(min),
+            tenths(max),
+            tenths(mean),
+            count
+        ));
+    }
+
+    format!("{{{}}}\n", entries.join(", "))
+}
+
+// Writes the results the same way as the reference, with each station's count
+pub fn format(results: &BTreeMap<String, Measurement>) -> String {
+    let mut output = BufWriter::new(Vec::new());
+    Format::OneBrc
+        .write(
+            &mut output,
+            results,
+            &OutputOptions {
+                count: true,
+                ..OutputOptions::default()
+            },
+        )
+        .unwrap();
+    output.flush().unwrap();
+
+    String::from_utf8(output.into_inner().unwrap()).unwrap()
+}
+
+// Appends a line to `contents` for a padding station whose name is just long enough to bring the
+// length to `remainder` modulo `divisor`
+pub fn pad(contents: &mut Vec<u8>, divisor: usize, remainder: usize) {
+    let line = "Padding;1.0\n".len();
+    let extra = (divisor + remainder - (contents.len() + line) % divisor) % divisor;
+
+    contents.extend_from_slice(format!("Padding{};1.0\n", "x".repeat(extra)).as_bytes());
+    assert_eq!(contents.len() % divisor, remainder);
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use std::io::Write;
+
+use common::{format, generated, reference, TempFile};
+use flate2::{write::GzEncoder, Compression};
+use one_brc::{aggregate_file_with, Options};
+
+const ROWS: u64 = 50_000;
+
+// Compresses `contents` as a member per block of `block_len` bytes, which pays no attention to
+// where the lines end, along with an index of where each block starts in the style of bgzip's
+fn blocked(contents: &[u8], block_len: usize) -> (Vec<u8>, Vec<u8>) {
+    let mut compressed = Vec::new();
+    let mut entries = Vec::new();
+
+    for (i, block) in contents.chunks(block_len).enumerate() {
+        if i > 0 {
+            entries.push((compressed.len() as u64, (i * block_len) as u64));
+        }
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(block).unwrap();
+        compressed.extend_from_slice(&encoder.finish().unwrap());
+    }
+
+    let mut index = (entries.len() as u64).to_le_bytes().to_vec();
+    for (compressed, text) in entries {
+        index.extend_from_slice(&compressed.to_le_bytes());
+        index.extend_from_slice(&text.to_le_bytes());
+    }
+
+    (compressed, index)
+}
+
+// The file, and its index alongside it where the index is looked for
+fn indexed(name: &str, compressed: &[u8], index: &[u8]) -> (TempFile, TempFile) {
+    let file = TempFile::new(name, compressed);
+    let mut index_path = file.0.clone().into_os_string();
+    index_path.push(".gzi");
+    std::fs::write(&index_path, index).unwrap();
+
+    (file, TempFile(index_path.into()))
+}
+
+#[test]
+fn matches_reference_with_or_without_an_index() {
+    let mut contents = generated(ROWS, 30);
+    // A line that's longer than a block, so that whole runs can fall inside it
+    contents.extend_from_slice(format!("{};1.0\n", "x".repeat(100_000)).as_bytes());
+    contents.extend_from_slice(&generated(ROWS, 31));
+
+    for (block_len, parallelism) in [(65_280, 4), (997, 3), (contents.len(), 4)] {
+        let (compressed, index) = blocked(&contents, block_len);
+        let options = Options {
+            parallelism,
+            chunks_per_thread: 64,
+            ..Options::default()
+        };
+
+        let plain = TempFile::new("gzip-plain", &compressed);
+        let results = aggregate_file_with(&plain.0, &options).unwrap();
+        assert_eq!(format(&results), reference(&contents));
+
+        let (file, _index) = indexed("gzip-indexed", &compressed, &index);
+        let results = aggregate_file_with(&file.0, &options).unwrap();
+        assert_eq!(format(&results), reference(&contents), "{}", block_len);
+    }
+}
+
+#[test]
+fn the_last_line_counts_without_a_trailing_newline() {
+    let mut contents = generated(ROWS, 32);
+    contents.pop();
+
+    let (compressed, index) = blocked(&contents, 10_000);
+    let (file, _index) = indexed("gzip-trailing", &compressed, &index);
+    let results = aggregate_file_with(&file.0, &Options::default()).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn refuses_an_index_of_another_file() {
+    let contents = generated(ROWS, 33);
+    let (compressed, _) = blocked(&contents, 10_000);
+    let (_, index) = blocked(&contents, 12_345);
+
+    let (file, index_file) = indexed("gzip-mismatched", &compressed, &index);
+    assert!(aggregate_file_with(&file.0, &Options::default()).is_err());
+
+    std::fs::write(&index_file.0, b"not an index").unwrap();
+    let error = aggregate_file_with(&file.0, &Options::default())
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("isn't a valid gzip index"));
+}
+
+// With blocks shorter than the lines, every line straddles two of them and is stitched back
+// together, and each has to be counted once, as it would be in a plain file sampled as a whole
+#[test]
+fn sampled_lines_that_straddle_blocks_are_counted_once() {
+    let mut contents = Vec::new();
+    for i in 0..1000 {
+        contents
+            .extend_from_slice(format!("{}{};{}.0\n", "x".repeat(100), i % 7, i % 50).as_bytes());
+    }
+    let (compressed, index) = blocked(&contents, 50);
+    let (file, _index) = indexed("gzip-sampled", &compressed, &index);
+    let plain = TempFile::new("gzip-sampled-plain", &contents);
+
+    let options = Options {
+        parallelism: 4,
+        chunks_per_thread: 1024,
+        sample: Some(10),
+        ..Options::default()
+    };
+    let count = |results: &std::collections::BTreeMap<String, one_brc::Measurement>| {
+        results
+            .values()
+            .map(|measurement| measurement.count())
+            .sum::<u64>()
+    };
+
+    let expected = count(&aggregate_file_with(&plain.0, &options).unwrap());
+    let counted = count(&aggregate_file_with(&file.0, &options).unwrap());
+
+    assert!(expected.abs_diff(1000) < 10, "{}", expected);
+    assert!(
+        counted.abs_diff(expected) < 10,
+        "{} against {}",
+        counted,
+        expected
+    );
+}
@@ -0,0 +1,435 @@
+mod common;
+
+use std::{collections::BTreeMap, io, sync::atomic::Ordering};
+
+use one_brc::{
+    aggregate_stream, aggregate_stream_with, fold_case, try_parse_line, try_parse_line_strict,
+    Casing, LineError, OnError, Options,
+};
+
+// The measurement never contains the delimiter, so searching from the end of the line finds the
+// right one even when the station's name contains it too
+#[test]
+fn station_names_can_contain_the_delimiter() {
+    let results = aggregate_stream("Foo;Bar;12.3\nFoo;Bar;-1.0\nBaz;2.0\n".as_bytes()).unwrap();
+
+    assert_eq!(
+        results.keys().collect::<Vec<_>>(),
+        ["Baz", "Foo;Bar"].iter().collect::<Vec<_>>()
+    );
+    assert_eq!(results["Foo;Bar"].count(), 2);
+    assert_eq!(results["Foo;Bar"].min(), -1.0);
+    assert_eq!(results["Foo;Bar"].max(), 12.3);
+}
+
+#[test]
+fn a_line_without_a_delimiter_is_an_error() {
+    let error = aggregate_stream("Foo;1.0\nBar\n".as_bytes()).err().unwrap();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("byte 8"), "{}", error);
+}
+
+#[test]
+fn a_measurement_that_isnt_a_number_is_an_error() {
+    let error = aggregate_stream("Foo;abc\n".as_bytes()).err().unwrap();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn malformed_lines_can_be_skipped() {
+    let options = Options {
+        on_error: OnError::Skip,
+        ..Options::default()
+    };
+
+    let results =
+        aggregate_stream_with("Foo;1.0\nBar\nFoo;abc\nFoo;3.0\n".as_bytes(), &options).unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results["Foo"].count(), 2);
+    assert_eq!(
+        options.skipped.load(std::sync::atomic::Ordering::Relaxed),
+        2
+    );
+}
+
+#[test]
+fn validation_finds_every_malformed_line() {
+    let input = b"Foo;1.0\nBar\nBaz;abc\nQu\xffx;2.0\nFoo;2.0\n";
+
+    let malformed = one_brc::validate_stream(&input[..], &Options::default()).unwrap();
+
+    assert_eq!(
+        malformed
+            .iter()
+            .map(|malformed| malformed.offset)
+            .collect::<Vec<_>>(),
+        [8, 12, 20]
+    );
+}
+
+// The delimiter is searched for from the very end of the line, so even lines shorter than the
+// usual "X;0.0" are split without reading out of bounds
+#[test]
+fn the_shortest_lines_are_parsed() {
+    let results = aggregate_stream("A;5\nA;-0.0\nB;.5\n".as_bytes()).unwrap();
+
+    assert_eq!(results["A"].count(), 2);
+    assert_eq!(results["A"].max(), 5.0);
+    assert_eq!(results["B"].min(), 0.5);
+}
+
+#[test]
+fn lines_too_short_for_a_measurement_are_errors() {
+    for line in ["A;\n", ";\n", "\n", "5\n"] {
+        let error = aggregate_stream(line.as_bytes()).err().unwrap();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", line);
+    }
+}
+
+// Measurements are parsed digit by digit rather than as floats, so the non-finite values a float
+// parser would accept are malformed, and can't poison the station they're for
+#[test]
+fn non_finite_measurements_are_malformed() {
+    for measurement in ["NaN", "nan", "inf", "-inf", "infinity"] {
+        let contents = format!("Foo;1.0\nFoo;{}\nFoo;3.0\n", measurement);
+
+        let error = aggregate_stream(contents.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", measurement);
+
+        let options = Options {
+            on_error: OnError::Skip,
+            ..Options::default()
+        };
+        let results = aggregate_stream_with(contents.as_bytes(), &options).unwrap();
+        assert_eq!(results["Foo"].to_string(), "1.0/3.0/2.0", "{}", measurement);
+    }
+}
+
+#[test]
+fn stations_differing_in_case_can_be_combined() {
+    let contents = "Tokyo;1.0\ntokyo;3.0\nTOKYO;2.0\nTokyo;6.0\nOsaka;5.0\n";
+
+    let lower = fold_case(
+        aggregate_stream(contents.as_bytes()).unwrap(),
+        Casing::Lower,
+    );
+    assert_eq!(lower.keys().collect::<Vec<_>>(), ["osaka", "tokyo"]);
+    assert_eq!(lower["tokyo"].to_string(), "1.0/6.0/3.0");
+    assert_eq!(lower["tokyo"].count(), 4);
+
+    // Tokyo is spelled that way twice, more than any other way
+    let common = fold_case(
+        aggregate_stream(contents.as_bytes()).unwrap(),
+        Casing::Common,
+    );
+    assert_eq!(common.keys().collect::<Vec<_>>(), ["Osaka", "Tokyo"]);
+    assert_eq!(common["Tokyo"].count(), 4);
+}
+
+// There's nothing to split or map in an empty file, which has to come out as no stations at all
+// rather than a division by zero or an empty map being refused
+#[test]
+fn an_empty_input_has_no_stations() {
+    let file = common::TempFile::new("empty", b"");
+
+    for parallelism in [1, 4] {
+        assert!(one_brc::chunks(b"", parallelism).is_empty());
+        assert!(one_brc::aggregate_file(&file.0, parallelism)
+            .unwrap()
+            .is_empty());
+    }
+    assert!(aggregate_stream(io::empty()).unwrap().is_empty());
+}
+
+// Only the first of every two lines is aggregated, and each one counts twice
+#[test]
+fn sampled_lines_stand_for_the_ones_skipped() {
+    let sampled = |every| {
+        let options = Options {
+            sample: Some(every),
+            ..Options::default()
+        };
+
+        aggregate_stream_with("Foo;1.0\nFoo;3.0\n".repeat(50).as_bytes(), &options).unwrap()
+    };
+
+    assert_eq!(sampled(1)["Foo"].to_string(), "1.0/3.0/2.0");
+    assert_eq!(sampled(1)["Foo"].count(), 100);
+
+    assert_eq!(sampled(2)["Foo"].to_string(), "1.0/1.0/1.0");
+    assert_eq!(sampled(2)["Foo"].count(), 100);
+}
+
+// Scaled up by a rate this high, the sampled lines stand for more than a count can hold, which
+// saturates rather than overflowing, however many chunks' results are merged into it
+#[test]
+fn sampled_counts_saturate_at_absurd_rates() {
+    let contents = "Foo;-12.5\nFoo;99.9\n".repeat(50_000);
+    let file = common::TempFile::new("absurd-sample", contents.as_bytes());
+    let options = Options {
+        parallelism: 4,
+        min_parallel_bytes: 0,
+        sample: Some(usize::MAX),
+        ..Options::default()
+    };
+
+    let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+    assert_eq!(results["Foo"].count(), u64::MAX);
+    assert_eq!(results["Foo"].min(), -12.5);
+}
+
+// The usual measurements of one or two digits and a decimal place have a fast path of their own,
+// which has to agree with parsing them digit by digit for every one of them
+#[test]
+fn every_measurement_with_a_decimal_place_is_parsed_exactly() {
+    let mut contents = String::new();
+    for tenths in -9999i32..=9999 {
+        let measurement = format!(
+            "{}{}.{}",
+            if tenths < 0 { "-" } else { "" },
+            tenths.abs() / 10,
+            tenths.abs() % 10
+        );
+        contents.push_str(&format!("{};{}\n", measurement, measurement));
+    }
+    let results = aggregate_stream(contents.as_bytes()).unwrap();
+
+    assert_eq!(results.len(), 19999);
+    for (measurement, result) in &results {
+        let expected = measurement.parse::<f64>().unwrap();
+
+        assert_eq!(result.min(), expected, "{}", measurement);
+        assert_eq!(result.max(), expected, "{}", measurement);
+    }
+}
+
+// Lines that nearly look like the usual ones, made up of the bytes that matter to parsing them, have
+// to end up either malformed or with the same value as the rules for any measurement give
+#[test]
+fn lines_like_the_usual_ones_are_parsed_by_the_same_rules() {
+    // A plain decimal number, truncated to thousandths
+    fn thousandths(measurement: &str) -> Option<i64> {
+        let (negative, digits) = match measurement.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, measurement),
+        };
+        let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_digits = |s: &str| s.bytes().all(|byte| byte.is_ascii_digit());
+        if !is_digits(integer) || !is_digits(fraction) || integer.len() > 6 {
+            return None;
+        }
+        // There has to be at least one digit, on one side of the decimal point or the other
+        if integer.is_empty() && fraction.is_empty() {
+            return None;
+        }
+
+        let fraction = format!("{:0<3}", &fraction[..fraction.len().min(3)]);
+        let value = format!("{}{}", integer, fraction).parse::<i64>().unwrap();
+
+        Some(if negative { -value } else { value })
+    }
+
+    // A xorshift generator, so that the lines are the same every run
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let mut contents = String::new();
+    let mut expected = BTreeMap::new();
+    let mut malformed = 0;
+    for i in 0..100_000 {
+        let len = 1 + next() % 6;
+        let tail = (0..len)
+            .map(|_| b"0123456789.-;x"[(next() % 14) as usize] as char)
+            .collect::<String>();
+        let line = format!("S{};{}", i, tail);
+
+        let (station, measurement) = line.rsplit_once(';').unwrap();
+        match thousandths(measurement) {
+            Some(value) => {
+                expected.insert(station.to_owned(), value);
+            }
+            None => malformed += 1,
+        }
+        contents.push_str(&line);
+        contents.push('\n');
+    }
+
+    let options = Options {
+        on_error: OnError::Skip,
+        ..Options::default()
+    };
+    let results = aggregate_stream_with(contents.as_bytes(), &options).unwrap();
+
+    assert_eq!(options.skipped.load(Ordering::Relaxed), malformed);
+    assert_eq!(
+        results
+            .iter()
+            .map(|(station, result)| (station.clone(), (result.min() * 1000.0).round() as i64))
+            .collect::<BTreeMap<_, _>>(),
+        expected
+    );
+}
+
+#[test]
+fn malformed_lines_say_what_is_wrong_with_them() {
+    assert_eq!(try_parse_line(b"Foo;12.3", b';'), Ok(("Foo", 12300)));
+    assert_eq!(
+        try_parse_line(b"Foo\xff;1.0", b';'),
+        Err(LineError::InvalidUtf8)
+    );
+    assert_eq!(
+        try_parse_line(b"Foo 1.0", b';'),
+        Err(LineError::NoDelimiter)
+    );
+    assert_eq!(
+        try_parse_line(b"Foo;", b';'),
+        Err(LineError::InvalidMeasurement)
+    );
+    assert_eq!(try_parse_line(b"-12.3", b';'), Err(LineError::NoDelimiter));
+
+    // The lead byte of 'é' as the delimiter would split the line partway through the character
+    assert_eq!(
+        try_parse_line("é1.0".as_bytes(), 0xc3),
+        Err(LineError::NoDelimiter)
+    );
+}
+
+// The seeds of the fuzz target are a delimiter followed by a line, which ought to all be valid
+#[test]
+fn the_fuzzing_corpus_is_made_of_valid_lines() {
+    let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/parse_line");
+    for entry in std::fs::read_dir(corpus).unwrap() {
+        let seed = std::fs::read(entry.unwrap().path()).unwrap();
+        let (&delimiter, line) = seed.split_first().unwrap();
+
+        assert!(try_parse_line(line, delimiter).is_ok(), "{:?}", seed);
+    }
+}
+
+#[test]
+fn strict_parsing_rejects_each_break_of_the_challenges_rules() {
+    let long = format!("{};1.0", "a".repeat(101));
+    for (line, error) in [
+        (";1.0", LineError::NameLength),
+        (long.as_str(), LineError::NameLength),
+        ("Foo;Bar;1.0", LineError::NameContainsDelimiter),
+        ("Foo;1", LineError::NotOneDecimal),
+        ("Foo;1.25", LineError::NotOneDecimal),
+        ("Foo;.5", LineError::NotOneDecimal),
+        ("Foo;100.0", LineError::OutOfRange),
+        ("Foo;-100.0", LineError::OutOfRange),
+        ("Foo;1.0\r", LineError::InvalidMeasurement),
+        ("Foo 1.0", LineError::NoDelimiter),
+    ] {
+        assert_eq!(
+            try_parse_line_strict(line.as_bytes(), b';'),
+            Err(error),
+            "{:?}",
+            line
+        );
+    }
+
+    let longest = format!("{};-99.9", "é".repeat(50));
+    for line in ["Foo;0.0", "Foo;-0.0", "Foo;99.9", longest.as_str()] {
+        assert!(
+            try_parse_line_strict(line.as_bytes(), b';').is_ok(),
+            "{:?}",
+            line
+        );
+    }
+}
+
+// A line that breaks the rules fails the aggregation with where it is, even though it would
+// otherwise have been aggregated
+#[test]
+fn strict_aggregation_fails_on_the_first_break_of_the_rules() {
+    let contents = "Foo;1.0\nBar;12.34\nBaz;100.0\n";
+    assert!(aggregate_stream(contents.as_bytes()).is_ok());
+
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let error = aggregate_stream_with(contents.as_bytes(), &options)
+        .err()
+        .unwrap();
+
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("at byte 8"), "{}", error);
+    assert!(
+        error.to_string().contains("exactly one decimal place"),
+        "{}",
+        error
+    );
+}
+
+// Exports with the measurement first aggregate just like the same lines the usual way around,
+// including station names that contain the delimiter
+#[test]
+fn value_first_lines_aggregate_like_value_last_ones() {
+    let value_last = String::from_utf8(common::generated(10_000, 30)).unwrap() + "Foo;Bar;-1.5\r\n";
+    let value_first = value_last
+        .lines()
+        .map(|line| {
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let (station, measurement) = line.rsplit_once(';').unwrap();
+            format!("{};{}\n", measurement, station)
+        })
+        .collect::<String>();
+
+    let options = Options {
+        value_first: true,
+        ..Options::default()
+    };
+    let results = aggregate_stream_with(value_first.as_bytes(), &options).unwrap();
+
+    assert!(results.contains_key("Foo;Bar"));
+    assert_eq!(
+        common::format(&results),
+        common::format(&aggregate_stream(value_last.as_bytes()).unwrap())
+    );
+
+    // The value first lines are malformed the usual way around, and vice versa
+    assert!(aggregate_stream(value_first.as_bytes()).is_err());
+    assert!(aggregate_stream_with(value_last.as_bytes(), &options).is_err());
+}
+
+// Files written on Windows end their lines with \r\n, which has to give the same results as \n,
+// whether the file is mapped and split up or streamed, and whichever parser reads the line
+#[test]
+fn crlf_lines_aggregate_like_lf_ones() {
+    let lf = String::from_utf8(common::generated(100_000, 30)).unwrap()
+        + "Foo;1.25\nFoo;-0.5\nFoo;12\nBar;Baz;-3.125\n";
+    let crlf = lf.replace('\n', "\r\n");
+    assert_ne!(lf, crlf);
+
+    let expected = common::format(&aggregate_stream(lf.as_bytes()).unwrap());
+    assert_eq!(
+        common::format(&aggregate_stream(crlf.as_bytes()).unwrap()),
+        expected
+    );
+
+    let lf_file = common::TempFile::new("lf", lf.as_bytes());
+    let crlf_file = common::TempFile::new("crlf", crlf.as_bytes());
+    for file in [&lf_file, &crlf_file] {
+        let results = one_brc::aggregate_file(&file.0, 4).unwrap();
+        assert_eq!(common::format(&results), expected);
+    }
+
+    // Without a newline after it, the last line still ends in a \r
+    let unterminated = crlf.strip_suffix('\n').unwrap();
+    assert_eq!(
+        common::format(&aggregate_stream(unterminated.as_bytes()).unwrap()),
+        expected
+    );
+}
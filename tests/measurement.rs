@@ -0,0 +1,109 @@
+use one_brc::{aggregate_stream_with, merge, Measurement, Options};
+
+#[test]
+fn an_empty_measurement_takes_on_its_first_value() {
+    let mut measurement = Measurement::default();
+    assert_eq!(measurement.to_string(), "NA/NA/NA");
+
+    measurement.record(12_300);
+
+    assert_eq!(measurement.to_string(), "12.3/12.3/12.3");
+    assert_eq!(measurement.count(), 1);
+}
+
+// 1.0 and 2.0 are seen twice each, and the tie goes to the lower of the two whichever half of the
+// measurements is aggregated into the other
+#[test]
+fn the_mode_breaks_ties_towards_the_lowest() {
+    let options = Options {
+        histogram: true,
+        ..Options::default()
+    };
+    let first = aggregate_stream_with("Foo;2.0\nFoo;1.0\nFoo;3.0\n".as_bytes(), &options).unwrap();
+    let second = aggregate_stream_with("Foo;2.0\nFoo;1.0\n".as_bytes(), &options).unwrap();
+
+    for (mut into, other) in [(first.clone(), &second), (second.clone(), &first)] {
+        merge(&mut into, other);
+        assert_eq!(into["Foo"].mode(), Some(1.0));
+    }
+    assert_eq!(first["Foo"].mode(), Some(1.0));
+    assert_eq!(Measurement::default().mode(), None);
+}
+
+fn with_histogram(input: &str) -> Measurement {
+    let options = Options {
+        histogram: true,
+        ..Options::default()
+    };
+
+    aggregate_stream_with(input.as_bytes(), &options).unwrap()["Foo"].clone()
+}
+
+#[test]
+fn the_median_is_the_middle_sample() {
+    assert_eq!(
+        with_histogram("Foo;20.0\nFoo;1.0\nFoo;3.0\nFoo;10.0\nFoo;2.0\n").median(),
+        Some(3.0)
+    );
+    assert_eq!(with_histogram("Foo;-4.5\n").median(), Some(-4.5));
+}
+
+// With an even count the two middle samples are averaged, rounding half towards positive infinity
+#[test]
+fn the_median_of_an_even_count_is_the_mean_of_the_middle_two() {
+    assert_eq!(
+        with_histogram("Foo;4.0\nFoo;1.0\nFoo;3.0\nFoo;2.0\n").median(),
+        Some(2.5)
+    );
+    assert_eq!(with_histogram("Foo;1.0\nFoo;1.1\n").median(), Some(1.1));
+    assert_eq!(with_histogram("Foo;-1.0\nFoo;-1.1\n").median(), Some(-1.0));
+}
+
+#[test]
+fn percentiles_at_a_sample_are_that_sample() {
+    let measurement = with_histogram("Foo;5.0\nFoo;1.0\nFoo;4.0\nFoo;2.0\nFoo;3.0\n");
+
+    assert_eq!(measurement.percentile(0.0), Some(1.0));
+    assert_eq!(measurement.percentile(25.0), Some(2.0));
+    assert_eq!(measurement.percentile(50.0), Some(3.0));
+    assert_eq!(measurement.percentile(100.0), Some(5.0));
+}
+
+// Like numpy's default, a rank between two samples is interpolated between their values
+#[test]
+fn percentiles_between_samples_are_interpolated() {
+    let measurement = with_histogram("Foo;5.0\nFoo;1.0\nFoo;4.0\nFoo;2.0\nFoo;3.0\n");
+    let close = |percentile: f64, expected: f64| {
+        let actual = measurement.percentile(percentile).unwrap();
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "p{}: {}",
+            percentile,
+            actual
+        );
+    };
+
+    close(10.0, 1.4);
+    close(90.0, 4.6);
+
+    let measurement = with_histogram("Foo;0.0\nFoo;10.0\n");
+    assert_eq!(measurement.percentile(25.0), Some(2.5));
+}
+
+// The population standard deviation divides by the count, not the count less one, which would give
+// 1.291 here
+#[test]
+fn the_stddev_is_the_population_stddev() {
+    let measurement = with_histogram("Foo;1.0\nFoo;2.0\nFoo;3.0\nFoo;4.0\n");
+    assert!(
+        (measurement.stddev() - 1.25f64.sqrt()).abs() < 1e-9,
+        "{}",
+        measurement.stddev()
+    );
+    assert_eq!(format!("{:.3}", measurement.stddev()), "1.118");
+
+    assert_eq!(
+        with_histogram("Foo;-7.5\nFoo;-7.5\nFoo;-7.5\n").stddev(),
+        0.0
+    );
+}
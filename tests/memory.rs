@@ -0,0 +1,64 @@
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use common::{generated, TempFile};
+use one_brc::{aggregate_file, aggregate_stream};
+
+// Counts how many bytes are allocated at any moment, across every thread
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+// Every run's results, chunks and buffers have to be freed once it's done, rather than piling up
+// run after run. This is the only test in the file, so nothing else allocates alongside it.
+#[test]
+fn repeated_runs_free_everything_they_allocate() {
+    let contents = generated(200_000, 50);
+    let file = TempFile::new("memory", &contents);
+
+    let run = || {
+        let results = aggregate_file(&file.0, 4).unwrap();
+        assert!(!results.is_empty());
+        drop(results);
+
+        let results = aggregate_stream(contents.as_slice()).unwrap();
+        assert!(!results.is_empty());
+    };
+
+    // The first run can set up whatever the standard library only allocates once
+    run();
+    let baseline = LIVE.load(Ordering::Relaxed);
+
+    for i in 0..5 {
+        run();
+
+        let live = LIVE.load(Ordering::Relaxed);
+        assert!(
+            live <= baseline + 64 * 1024,
+            "run {} left {} bytes allocated, up from {}, with a {} byte input",
+            i,
+            live,
+            baseline,
+            contents.len()
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use one_brc::{aggregate_stream, Format, OutputOptions, Sort};
+
+fn written(format: Format, input: &str) -> String {
+    let results = aggregate_stream(input.as_bytes()).unwrap();
+
+    let mut output = Vec::new();
+    format
+        .write(&mut output, &results, &OutputOptions::default())
+        .unwrap();
+
+    String::from_utf8(output).unwrap()
+}
+
+const INPUT: &str = "Hamburg;12.0\nBulawayo;8.9\nPalembang;38.8\nHamburg;34.2\nBulawayo;-4.3\n";
+
+#[test]
+fn writes_the_challenges_format() {
+    assert_eq!(
+        written(Format::OneBrc, INPUT),
+        "{Bulawayo=-4.3/8.9/2.3, Hamburg=12.0/34.2/23.1, Palembang=38.8/38.8/38.8}\n"
+    );
+}
+
+#[test]
+fn writes_four_tab_separated_columns_per_station() {
+    let written = written(Format::Tsv, INPUT);
+
+    assert!(written.ends_with('\n'));
+    for line in written.lines() {
+        assert_eq!(line.split('\t').count(), 4, "{:?}", line);
+    }
+    assert_eq!(
+        written,
+        "Bulawayo\t-4.3\t8.9\t2.3\nHamburg\t12.0\t34.2\t23.1\nPalembang\t38.8\t38.8\t38.8\n"
+    );
+}
+
+// A tab in a name would otherwise start a column of its own, and with a tab as the delimiter the
+// name can hold one as well, as only the last tab on a line separates it from its measurement
+#[test]
+fn tsv_escapes_tabs_in_names() {
+    let written = written(Format::Tsv, "Tab\there;1.0\nBack\\slash;2.0\n");
+    assert_eq!(
+        written,
+        "Back\\\\slash\t2.0\t2.0\t2.0\nTab\\there\t1.0\t1.0\t1.0\n"
+    );
+
+    let options = one_brc::Options {
+        delimiter: b'\t',
+        ..one_brc::Options::default()
+    };
+    let results = one_brc::aggregate_stream_with("Tab\there\t1.0\n".as_bytes(), &options).unwrap();
+    let mut output = Vec::new();
+    Format::Tsv
+        .write(&mut output, &results, &OutputOptions::default())
+        .unwrap();
+    let written = String::from_utf8(output).unwrap();
+
+    assert_eq!(written, "Tab\\there\t1.0\t1.0\t1.0\n");
+    for line in written.lines() {
+        assert_eq!(line.split('\t').count(), 4, "{:?}", line);
+    }
+}
+
+#[test]
+fn sorts_accented_names_among_unaccented_ones() {
+    let results = aggregate_stream(
+        "Zwolle;1.0\nZürich;2.0\nÉvora;3.0\nzagreb;4.0\nFaro;5.0\nZagreb;6.0\nDublin;7.0\n"
+            .as_bytes(),
+    )
+    .unwrap();
+    let names = |sort: Sort| {
+        let mut results = Vec::from_iter(results.iter());
+        results.sort_by(sort.comparator());
+        results
+            .into_iter()
+            .map(|(city, _)| city.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        names(Sort::Name),
+        ["Dublin", "Faro", "Zagreb", "Zwolle", "Zürich", "zagreb", "Évora"]
+    );
+    assert_eq!(
+        names(Sort::Unicode),
+        ["Dublin", "Évora", "Faro", "Zagreb", "zagreb", "Zürich", "Zwolle"]
+    );
+}
+
+// Enough stations for the results to be split between several workers, which have to come back
+// together exactly as if they'd been written in one go
+#[test]
+fn writes_the_same_in_parallel() {
+    let input = (0..200_000)
+        .map(|i| format!("Station{};{}.{}\n", i, i % 100, i % 10))
+        .collect::<String>();
+    let results = aggregate_stream(input.as_bytes()).unwrap();
+    let ordered = Vec::from_iter(&results);
+
+    for format in [Format::OneBrc, Format::Json, Format::Csv, Format::Tsv] {
+        let mut expected = Vec::new();
+        format
+            .write(
+                &mut expected,
+                ordered.iter().copied(),
+                &OutputOptions::default(),
+            )
+            .unwrap();
+
+        let mut written = Vec::new();
+        format
+            .write_parallel(&mut written, &ordered, &OutputOptions::default(), 3)
+            .unwrap();
+
+        assert!(written == expected, "{:?}", format);
+    }
+}
+
+// Names with commas and quotes have to be quoted for a CSV reader to get them back
+#[test]
+fn csv_reads_back_with_a_csv_reader() {
+    let input = "Hamburg;12.0\nWashington, D.C.;8.9\nThe \"Big\" Apple;-4.3\nQuote\";1.0\n";
+    let written = written(Format::Csv, input);
+
+    let mut reader = csv::Reader::from_reader(written.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["city", "min", "max", "mean", "count"]
+    );
+
+    let rows = reader
+        .records()
+        .map(|record| record.unwrap().iter().map(str::to_owned).collect())
+        .collect::<Vec<Vec<String>>>();
+    assert_eq!(
+        rows,
+        [
+            ["Hamburg", "12.0", "12.0", "12.0", "1"],
+            ["Quote\"", "1.0", "1.0", "1.0", "1"],
+            ["The \"Big\" Apple", "-4.3", "-4.3", "-4.3", "1"],
+            ["Washington, D.C.", "8.9", "8.9", "8.9", "1"],
+        ]
+    );
+}
+
+// Quotes, backslashes and control characters are escaped, so a JSON parser gets the names back
+#[test]
+fn json_escapes_names_for_a_json_parser() {
+    let names = [
+        "\"Quoted\"",
+        "Back\\slash",
+        "Tab\there",
+        "Carriage\rreturn",
+        "Bell\x07",
+        "Unit\x1fseparator",
+        "São Paulo",
+    ];
+    let input: String = names.iter().map(|name| format!("{};1.0\n", name)).collect();
+    let written = written(Format::Json, &input);
+
+    let parsed: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&written).unwrap();
+    let mut expected = names.to_vec();
+    expected.sort();
+    assert_eq!(parsed.keys().collect::<Vec<_>>(), expected);
+
+    for escaped in [
+        r#""\"Quoted\"""#,
+        r#""Back\\slash""#,
+        r#""Tab\there""#,
+        r#""Carriage\rreturn""#,
+        r#""Bell\u0007""#,
+        r#""Unit\u001fseparator""#,
+        r#""São Paulo""#,
+    ] {
+        assert!(written.contains(escaped), "{} in {}", escaped, written);
+    }
+}
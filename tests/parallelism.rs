@@ -0,0 +1,144 @@
+mod common;
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use common::{format, generated, TempFile};
+
+/*
+ * Every line has to end up in exactly one chunk however the file is split, so the results should
+ * never depend on the number of threads. Files of a few different lengths shift where the chunk
+ * boundaries fall relative to the lines.
+ */
+#[test]
+fn results_are_the_same_for_any_parallelism() {
+    for (seed, rows) in [(10, 100_000), (11, 123_457), (12, 250_001)] {
+        let file = TempFile::new(&format!("parallelism-{}", seed), &generated(rows, seed));
+
+        let expected = format(&one_brc::aggregate_file(&file.0, 1).unwrap());
+        for parallelism in [2, 3, 4, 7] {
+            let results = one_brc::aggregate_file(&file.0, parallelism).unwrap();
+
+            assert_eq!(
+                format(&results),
+                expected,
+                "{} rows split between {} threads",
+                rows,
+                parallelism
+            );
+        }
+    }
+}
+
+// However many chunks each thread is given, and whichever thread ends up taking each of them, every
+// chunk is aggregated exactly once
+#[test]
+fn results_are_the_same_for_any_number_of_chunks_per_thread() {
+    let file = TempFile::new("chunks-per-thread", &generated(400_000, 14));
+
+    let expected = format(&one_brc::aggregate_file(&file.0, 1).unwrap());
+    for chunks_per_thread in [1, 2, 4, 16] {
+        let options = one_brc::Options {
+            parallelism: 3,
+            chunks_per_thread,
+            ..one_brc::Options::default()
+        };
+
+        assert_eq!(
+            format(&one_brc::aggregate_file_with(&file.0, &options).unwrap()),
+            expected,
+            "{} chunks per thread",
+            chunks_per_thread
+        );
+    }
+}
+
+#[test]
+fn stopping_before_starting_aggregates_nothing() {
+    let file = TempFile::new("stopped", &generated(100_000, 15));
+    let options = one_brc::Options {
+        parallelism: 2,
+        stop: Some(Arc::new(AtomicBool::new(true))),
+        ..one_brc::Options::default()
+    };
+
+    assert!(one_brc::aggregate_file_with(&file.0, &options)
+        .unwrap()
+        .is_empty());
+}
+
+// However far the threads get before they're stopped, what they've aggregated is only ever part of
+// what a full run aggregates
+#[test]
+fn stopping_partway_aggregates_part_of_the_file() {
+    let file = TempFile::new("stopped-partway", &generated(1_000_000, 16));
+    let full = one_brc::aggregate_file(&file.0, 1).unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let options = one_brc::Options {
+        parallelism: 3,
+        stop: Some(stop.clone()),
+        ..one_brc::Options::default()
+    };
+    let stopper = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(5));
+        stop.store(true, Ordering::Relaxed);
+    });
+    let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+    stopper.join().unwrap();
+
+    for (city, measurement) in &results {
+        let all = &full[city];
+        assert!(measurement.count() <= all.count(), "{}", city);
+        assert!(
+            measurement.min() >= all.min() && measurement.max() <= all.max(),
+            "{}",
+            city
+        );
+    }
+}
+
+// A file of a few lines can't be split between many threads, which mustn't be given empty chunks
+// or chunks starting past the end of it
+#[test]
+fn tiny_files_are_aggregated_on_many_threads() {
+    for (contents, expected) in [
+        (&b"Foo;1.0\n"[..], "{Foo=1.0/1.0/1.0/1}\n"),
+        (b"Foo;1.0", "{Foo=1.0/1.0/1.0/1}\n"),
+        (
+            b"Foo;1.0\nBar;-2.5\nFoo;3.0\n",
+            "{Bar=-2.5/-2.5/-2.5/1, Foo=1.0/3.0/2.0/2}\n",
+        ),
+        (
+            b"Foo;1.0\nBar;-2.5\nFoo;3.0",
+            "{Bar=-2.5/-2.5/-2.5/1, Foo=1.0/3.0/2.0/2}\n",
+        ),
+    ] {
+        let lines = contents
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .count();
+        let chunks = one_brc::chunks(contents, 8);
+        assert!(chunks.len() <= lines, "{:?}", chunks);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty()), "{:?}", chunks);
+
+        let file = TempFile::new("parallelism-tiny", contents);
+        for chunks_per_thread in [1, 16] {
+            let options = one_brc::Options {
+                parallelism: 8,
+                chunks_per_thread,
+                // Files this small are otherwise aggregated without any threads at all
+                min_parallel_bytes: 0,
+                ..one_brc::Options::default()
+            };
+            let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+            assert_eq!(format(&results), expected, "{:?}", contents);
+        }
+    }
+}
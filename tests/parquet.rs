@@ -0,0 +1,132 @@
+#![cfg(feature = "parquet")]
+
+mod common;
+
+use std::{fs::File, process::Command};
+
+use arrow_array::{Array, Float64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::DataType;
+use common::{generated, TempFile};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_one-brc"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn read(path: &std::path::Path) -> RecordBatch {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 1);
+
+    batches.into_iter().next().unwrap()
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> &'a T {
+    batch
+        .column_by_name(name)
+        .unwrap()
+        .as_any()
+        .downcast_ref::<T>()
+        .unwrap()
+}
+
+#[test]
+fn the_table_holds_the_same_values_as_the_csv() {
+    let file = TempFile::new("parquet", &generated(20_000, 40));
+    let output = TempFile::new("parquet-output", b"");
+    let path = file.0.to_str().unwrap();
+
+    let written = run(&[
+        path,
+        "--format",
+        "parquet",
+        "--output",
+        output.0.to_str().unwrap(),
+    ]);
+    assert!(written.status.success());
+
+    let batch = read(&output.0);
+    let types = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| (field.name().clone(), field.data_type().clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            ("station".to_owned(), DataType::Utf8),
+            ("min".to_owned(), DataType::Float64),
+            ("max".to_owned(), DataType::Float64),
+            ("mean".to_owned(), DataType::Float64),
+            ("count".to_owned(), DataType::UInt64),
+        ]
+    );
+
+    let csv = String::from_utf8(run(&[path, "--format", "csv"]).stdout).unwrap();
+    let rows = csv.lines().skip(1).collect::<Vec<_>>();
+    assert_eq!(batch.num_rows(), rows.len());
+
+    let stations = column::<StringArray>(&batch, "station");
+    let counts = column::<UInt64Array>(&batch, "count");
+    for (i, row) in rows.iter().enumerate() {
+        // Only the station can be quoted, as it's the only field that can hold a comma
+        let mut fields = row.rsplitn(5, ',').collect::<Vec<_>>();
+        fields.reverse();
+        let station = match fields[0].strip_prefix('"') {
+            Some(quoted) => quoted.strip_suffix('"').unwrap().replace("\"\"", "\""),
+            None => fields[0].to_owned(),
+        };
+        assert_eq!(stations.value(i), station);
+
+        for (name, field) in ["min", "max", "mean"].into_iter().zip(&fields[1..4]) {
+            let value = column::<Float64Array>(&batch, name).value(i);
+            assert_eq!(
+                value,
+                field.parse::<f64>().unwrap(),
+                "{} of {}",
+                name,
+                station
+            );
+        }
+        assert_eq!(counts.value(i), fields[4].parse::<u64>().unwrap());
+    }
+}
+
+#[test]
+fn stations_without_measurements_have_null_statistics() {
+    let file = TempFile::new("parquet-missing", b"Foo;1.5\nFoo;2.5\n");
+    let output = TempFile::new("parquet-missing-output", b"");
+
+    let written = run(&[
+        file.0.to_str().unwrap(),
+        "--only",
+        "Bar,Foo",
+        "--missing",
+        "na",
+        "--format",
+        "parquet",
+        "--output",
+        output.0.to_str().unwrap(),
+    ]);
+    assert!(written.status.success());
+
+    let batch = read(&output.0);
+    let stations = column::<StringArray>(&batch, "station");
+    let means = column::<Float64Array>(&batch, "mean");
+    let counts = column::<UInt64Array>(&batch, "count");
+
+    assert_eq!(stations.value(0), "Bar");
+    assert!(means.is_null(0));
+    assert_eq!(counts.value(0), 0);
+
+    assert_eq!(stations.value(1), "Foo");
+    assert_eq!(means.value(1), 2.0);
+    assert_eq!(counts.value(1), 2);
+}
@@ -0,0 +1,292 @@
+mod common;
+
+use common::{format, generated, pad, reference, TempFile};
+
+// Enough rows for the file to be split into several chunks, rather than fewer than asked for
+const ROWS: u64 = 200_000;
+const PARALLELISM: usize = 4;
+
+#[test]
+fn matches_reference_when_evenly_divisible() {
+    let mut contents = generated(ROWS, 1);
+    pad(&mut contents, PARALLELISM, 0);
+    let file = TempFile::new("evenly-divisible", &contents);
+
+    let results = one_brc::aggregate_file(&file.0, PARALLELISM).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn matches_reference_when_unevenly_divisible() {
+    let mut contents = generated(ROWS, 2);
+    pad(&mut contents, PARALLELISM, 3);
+    let file = TempFile::new("unevenly-divisible", &contents);
+
+    let results = one_brc::aggregate_file(&file.0, PARALLELISM).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn matches_reference_from_a_stream() {
+    let contents = generated(ROWS, 3);
+
+    let results = one_brc::aggregate_stream(contents.as_slice()).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn matches_reference_across_several_files() {
+    let contents = generated(ROWS, 4);
+    let middle = contents.len() / 2
+        + contents[contents.len() / 2..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .unwrap()
+        + 1;
+    let first = TempFile::new("first-half", &contents[..middle]);
+    let second = TempFile::new("second-half", &contents[middle..]);
+
+    let results = one_brc::aggregate_files_with(
+        &[first.0.clone(), second.0.clone()],
+        &one_brc::Options::default(),
+    )
+    .unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+#[test]
+fn matches_reference_when_halves_are_merged() {
+    let contents = generated(ROWS, 5);
+    let middle = contents.len() / 2
+        + contents[contents.len() / 2..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .unwrap()
+        + 1;
+    let first = TempFile::new("merged-first-half", &contents[..middle]);
+    let second = TempFile::new("merged-second-half", &contents[middle..]);
+
+    let mut results = one_brc::aggregate_file(&first.0, PARALLELISM).unwrap();
+    one_brc::merge(
+        &mut results,
+        &one_brc::aggregate_file(&second.0, PARALLELISM).unwrap(),
+    );
+
+    let whole = TempFile::new("merged-whole", &contents);
+    let expected = one_brc::aggregate_file(&whole.0, PARALLELISM).unwrap();
+
+    assert_eq!(format(&results), format(&expected));
+    assert_eq!(format(&results), reference(&contents));
+}
+
+// The limit falls partway through a line, which is left out along with everything after it
+#[test]
+fn matches_reference_for_a_limited_prefix() {
+    let contents = generated(ROWS, 6);
+    let limit = contents.len() / 3;
+    let prefix = &contents[..contents[..limit]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .unwrap()
+        + 1];
+    let file = TempFile::new("limited", &contents);
+    let options = one_brc::Options {
+        parallelism: PARALLELISM,
+        limit_bytes: Some(limit),
+        ..one_brc::Options::default()
+    };
+
+    let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+    assert_eq!(format(&results), reference(prefix));
+
+    let results = one_brc::aggregate_stream_with(contents.as_slice(), &options).unwrap();
+    assert_eq!(format(&results), reference(prefix));
+}
+
+#[test]
+fn borrowed_results_match_owned_ones() {
+    let contents = generated(ROWS, 7);
+    let file = TempFile::new("borrowed", &contents);
+    let options = one_brc::Options {
+        parallelism: PARALLELISM,
+        ..one_brc::Options::default()
+    };
+
+    let mapped = one_brc::MappedFile::open(&file.0, &options).unwrap();
+    let borrowed = mapped.aggregate(&options).unwrap();
+    let owned = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+    assert!(borrowed
+        .keys()
+        .copied()
+        .eq(owned.keys().map(String::as_str)));
+    for (city, measurement) in &borrowed {
+        assert_eq!(measurement.to_string(), owned[*city].to_string());
+        assert_eq!(measurement.count(), owned[*city].count());
+    }
+}
+
+// Streams are read in blocks of about a megabyte, so these lines straddle the edges of several,
+// and the long station name makes for a line that no single block can hold
+#[test]
+fn matches_reference_when_streamed_in_blocks() {
+    let mut contents = generated(ROWS, 8);
+    contents.extend_from_slice(format!("{};1.0\n", "x".repeat(3 << 20)).as_bytes());
+    contents.extend_from_slice(&generated(ROWS, 9));
+
+    let results = one_brc::aggregate_stream(contents.as_slice()).unwrap();
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+// Plenty of exports leave off the last newline, and the end of the file has to end the last line
+// in its place, whichever chunk it falls in
+#[test]
+fn the_last_line_counts_without_a_trailing_newline() {
+    let contents = generated(ROWS, 12);
+    let with = TempFile::new("with-newline", &contents);
+    let without = TempFile::new("without-newline", &contents[..contents.len() - 1]);
+
+    let expected = reference(&contents);
+    for parallelism in [1, PARALLELISM] {
+        assert_eq!(
+            format(&one_brc::aggregate_file(&with.0, parallelism).unwrap()),
+            expected
+        );
+        assert_eq!(
+            format(&one_brc::aggregate_file(&without.0, parallelism).unwrap()),
+            expected
+        );
+    }
+    assert_eq!(
+        format(&one_brc::aggregate_stream(&contents[..contents.len() - 1]).unwrap()),
+        expected
+    );
+}
+
+// Recording every record the callback is given into measurements of our own has to agree with the
+// aggregation, in whichever format the file is in
+#[test]
+fn matches_reference_when_every_record_is_visited() {
+    let contents = generated(ROWS, 13);
+    let mut converted = Vec::new();
+    one_brc::convert(
+        contents.as_slice(),
+        &mut converted,
+        &one_brc::Options::default(),
+    )
+    .unwrap();
+
+    let text = TempFile::new("records-text", &contents);
+    let binary = TempFile::new("records-binary", &converted);
+
+    for file in [&text, &binary] {
+        let results = std::sync::Mutex::new(std::collections::BTreeMap::new());
+        one_brc::for_each_record(&file.0, PARALLELISM, |station, thousandths| {
+            results
+                .lock()
+                .unwrap()
+                .entry(station.to_owned())
+                .or_insert_with(one_brc::Measurement::default)
+                .record(thousandths);
+        })
+        .unwrap();
+
+        assert_eq!(format(&results.into_inner().unwrap()), reference(&contents));
+    }
+}
+
+#[test]
+fn records_are_visited_in_thousandths() {
+    let file = TempFile::new("records-units", b"Foo;12.3\nFoo;-0.05\n");
+
+    let records = std::sync::Mutex::new(Vec::new());
+    one_brc::for_each_record(&file.0, 1, |station, thousandths| {
+        records
+            .lock()
+            .unwrap()
+            .push((station.to_owned(), thousandths));
+    })
+    .unwrap();
+
+    assert_eq!(
+        records.into_inner().unwrap(),
+        [("Foo".to_owned(), 12_300), ("Foo".to_owned(), -50)]
+    );
+}
+
+// Direct reads are only as long as the file, so it's made to end partway through a block
+#[test]
+fn matches_reference_when_read_directly() {
+    let mut contents = generated(ROWS, 27);
+    if contents.len().is_multiple_of(4096) {
+        contents.extend_from_slice(b"Hamburg;12.0\n");
+    }
+    let file = TempFile::new("direct", &contents);
+
+    let options = one_brc::Options {
+        direct: true,
+        ..one_brc::Options::default()
+    };
+    let results = match one_brc::aggregate_file_with(&file.0, &options) {
+        Ok(results) => results,
+        // Not every platform or filesystem that the tests run on can read directly
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => return,
+        Err(e) => panic!("{}", e),
+    };
+
+    assert_eq!(format(&results), reference(&contents));
+}
+
+// Files below the threshold are read and aggregated on the calling thread instead of being split
+#[test]
+fn matches_reference_whether_or_not_a_file_is_split() {
+    let contents = generated(ROWS, 29);
+    let file = TempFile::new("single-pass", &contents);
+
+    for min_parallel_bytes in [0, contents.len(), contents.len() + 1] {
+        let options = one_brc::Options {
+            parallelism: PARALLELISM,
+            min_parallel_bytes,
+            ..one_brc::Options::default()
+        };
+        let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+        assert_eq!(format(&results), reference(&contents));
+    }
+}
+
+// Every chunk interns the stations it sees into ids of its own, in the order it first sees them,
+// so the same station has a different id in each chunk it's in, and the ids have to be resolved
+// back to names to be merged
+#[test]
+fn matches_reference_when_chunks_see_stations_in_different_orders() {
+    use std::io::Write;
+
+    let mut contents = Vec::new();
+    for i in 0..ROWS {
+        // Later lines see the stations in reverse, and only some of them
+        let station = match i < ROWS / 2 {
+            true => i % 20_000,
+            false => (ROWS - i) % 7_919,
+        };
+        writeln!(contents, "Station {};{}.{}", station, i % 99, i % 10).unwrap();
+    }
+    let file = TempFile::new("interned", &contents);
+
+    for chunks_per_thread in [1, 16] {
+        let options = one_brc::Options {
+            parallelism: PARALLELISM,
+            chunks_per_thread,
+            ..one_brc::Options::default()
+        };
+        let results = one_brc::aggregate_file_with(&file.0, &options).unwrap();
+
+        assert_eq!(results.len(), 20_000);
+        assert_eq!(format(&results), reference(&contents));
+    }
+}
@@ -0,0 +1,167 @@
+use one_brc::{aggregate_stream, Precision, Rounding};
+
+// Aggregates a single measurement, which is then its own min, max and mean
+fn rounded(measurement: &str) -> String {
+    let results = aggregate_stream(format!("Station;{}\n", measurement).as_bytes()).unwrap();
+
+    results["Station"].to_string()
+}
+
+// The challenge rounds towards positive infinity, so halves go up whatever their sign
+#[test]
+fn halves_round_towards_positive_infinity() {
+    assert_eq!(rounded("0.05"), "0.1/0.1/0.1");
+    assert_eq!(rounded("-0.05"), "0.0/0.0/0.0");
+    assert_eq!(rounded("2.55"), "2.6/2.6/2.6");
+    assert_eq!(rounded("-2.55"), "-2.5/-2.5/-2.5");
+}
+
+// Each of the three is written with exactly one decimal place, whatever floats would make of them
+#[test]
+fn min_max_and_mean_are_written_to_one_decimal_place() {
+    let results = aggregate_stream("Station;12.3\nStation;-5.1\nStation;3.0\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "-5.1/12.3/3.4");
+
+    let results = aggregate_stream("Station;-5.0999999\nStation;7\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "-5.1/7.0/1.0");
+
+    // Nothing is ever written as -0.0, however it's reached
+    assert_eq!(rounded("-0.0"), "0.0/0.0/0.0");
+    assert_eq!(rounded("-0.04"), "0.0/0.0/0.0");
+    let results = aggregate_stream("Station;-0.1\nStation;0.1\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "-0.1/0.1/0.0");
+}
+
+#[test]
+fn values_either_side_of_a_half_round_to_the_nearest_tenth() {
+    assert_eq!(rounded("2.549"), "2.5/2.5/2.5");
+    assert_eq!(rounded("-2.551"), "-2.6/-2.6/-2.6");
+    assert_eq!(rounded("-0.049"), "0.0/0.0/0.0");
+}
+
+#[test]
+fn the_mean_is_rounded_exactly() {
+    // -0.1 and 0.0 average to exactly -0.05, which floats can't represent
+    let results = aggregate_stream("Station;-0.1\nStation;0.0\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "-0.1/0.0/0.0");
+
+    let results = aggregate_stream("Station;2.5\nStation;2.6\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "2.5/2.6/2.6");
+
+    let results = aggregate_stream("Station;-2.5\nStation;-2.6\n".as_bytes()).unwrap();
+    assert_eq!(results["Station"].to_string(), "-2.6/-2.5/-2.5");
+}
+
+// Summing a million tenths in an f32 drifts well off the true mean, but the sum is kept in integers
+#[test]
+fn the_mean_does_not_drift_over_many_measurements() {
+    let input = "Station;0.1\nStation;12.4\n".repeat(500_000);
+
+    let results = aggregate_stream(input.as_bytes()).unwrap();
+
+    assert_eq!(results["Station"].count(), 1_000_000);
+    assert!((results["Station"].mean() - 6.25).abs() < 1e-9);
+    assert_eq!(results["Station"].to_string(), "0.1/12.4/6.3");
+}
+
+#[test]
+fn each_rounding_mode_rounds_halves_its_own_way() {
+    let cases = [
+        (Rounding::HalfUp, "2.3", "-2.2"),
+        (Rounding::Nearest, "2.2", "-2.2"),
+        (Rounding::Up, "2.3", "-2.2"),
+        (Rounding::Down, "2.2", "-2.3"),
+        (Rounding::TowardZero, "2.2", "-2.2"),
+    ];
+
+    for (rounding, positive, negative) in cases {
+        for (measurement, expected) in [("2.25", positive), ("-2.25", negative)] {
+            let results =
+                aggregate_stream(format!("Station;{}\n", measurement).as_bytes()).unwrap();
+
+            assert_eq!(
+                results["Station"].rounded(rounding).to_string(),
+                format!("{0}/{0}/{0}", expected),
+                "{:?} of {}",
+                rounding,
+                measurement
+            );
+        }
+    }
+}
+
+// Away from a half the directed modes still round, where the nearest ones don't move
+#[test]
+fn directed_rounding_modes_round_values_off_a_half() {
+    let results = aggregate_stream("Station;2.21\nStation;-2.29\n".as_bytes()).unwrap();
+    let rounded = |rounding| results["Station"].rounded(rounding).to_string();
+
+    assert_eq!(rounded(Rounding::HalfUp), "-2.3/2.2/0.0");
+    assert_eq!(rounded(Rounding::Nearest), "-2.3/2.2/0.0");
+    assert_eq!(rounded(Rounding::Up), "-2.2/2.3/0.0");
+    assert_eq!(rounded(Rounding::Down), "-2.3/2.2/-0.1");
+    assert_eq!(rounded(Rounding::TowardZero), "-2.2/2.2/0.0");
+    // 2.35 has an odd tenth, so its even neighbour is above it
+    assert_eq!(
+        aggregate_stream("Station;2.35\n".as_bytes()).unwrap()["Station"]
+            .rounded(Rounding::Nearest)
+            .to_string(),
+        "2.4/2.4/2.4"
+    );
+}
+
+#[test]
+fn measurements_can_be_written_with_more_decimal_places() {
+    let results = aggregate_stream("Station;-1.234\nStation;5.6\n".as_bytes()).unwrap();
+    let displayed = |decimals| {
+        results["Station"]
+            .display(Precision {
+                decimals,
+                ..Precision::default()
+            })
+            .to_string()
+    };
+
+    assert_eq!(displayed(0), "-1/6/2");
+    assert_eq!(displayed(1), results["Station"].to_string());
+    assert_eq!(displayed(1), "-1.2/5.6/2.2");
+    assert_eq!(displayed(2), "-1.23/5.60/2.18");
+    assert_eq!(displayed(3), "-1.234/5.600/2.183");
+}
+
+// Measurements with none, one, two or three decimal places are all kept exactly, and only rounded
+// to a tenth once they're written
+#[test]
+fn measurements_with_0_to_3_decimal_places_are_kept_exactly() {
+    for (measurement, exact, tenths) in [
+        ("12", 12.0, "12.0"),
+        ("-7", -7.0, "-7.0"),
+        ("0", 0.0, "0.0"),
+        ("12.3", 12.3, "12.3"),
+        ("-0.5", -0.5, "-0.5"),
+        ("12.34", 12.34, "12.3"),
+        ("-7.05", -7.05, "-7.0"),
+        ("12.345", 12.345, "12.3"),
+        ("-0.001", -0.001, "0.0"),
+        ("99.999", 99.999, "100.0"),
+    ] {
+        let results = aggregate_stream(format!("Station;{}\n", measurement).as_bytes()).unwrap();
+
+        assert_eq!(results["Station"].min(), exact, "{}", measurement);
+        assert_eq!(results["Station"].max(), exact, "{}", measurement);
+        assert_eq!(
+            results["Station"].to_string(),
+            format!("{0}/{0}/{0}", tenths),
+            "{}",
+            measurement
+        );
+    }
+
+    // Mixing them, the mean is of the exact values rather than of each rounded to a tenth, which
+    // would be 1.05 and round up to 1.1
+    let results =
+        aggregate_stream("Station;1\nStation;1.05\nStation;1.045\nStation;1.05\n".as_bytes())
+            .unwrap();
+    assert!((results["Station"].mean() - 1.03625).abs() < 1e-9);
+    assert_eq!(results["Station"].to_string(), "1.0/1.1/1.0");
+}
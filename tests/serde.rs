@@ -0,0 +1,73 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{format, generated, TempFile};
+use one_brc::{aggregate_file_with, Measurement, Options};
+
+#[test]
+fn measurements_survive_a_round_trip() {
+    let file = TempFile::new("serde", &generated(10_000, 30));
+    let results = aggregate_file_with(
+        &file.0,
+        &Options {
+            histogram: true,
+            ..Options::default()
+        },
+    )
+    .unwrap();
+
+    let json = serde_json::to_string(&results).unwrap();
+    let round_tripped: BTreeMap<String, Measurement> = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(format(&round_tripped), format(&results));
+    for (city, measurement) in &results {
+        let round_tripped = &round_tripped[city];
+        assert_eq!(round_tripped.median(), measurement.median());
+        assert_eq!(round_tripped.stddev(), measurement.stddev());
+    }
+}
+
+#[test]
+fn empty_measurements_survive_a_round_trip() {
+    let json = serde_json::to_string(&Measurement::default()).unwrap();
+    let round_tripped: Measurement = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(round_tripped.count(), 0);
+    assert_eq!(round_tripped.to_string(), "NA/NA/NA");
+}
+
+// Nothing was recorded, so there are no order statistics to read from the histogram
+#[test]
+fn an_empty_histogram_has_no_median_or_percentiles() {
+    let json = format!(
+        r#"{{"sum":0,"sum_sq":0,"count":0,"histogram":{:?}}}"#,
+        vec![0; 1999]
+    );
+    let measurement: Measurement = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(measurement.median(), None);
+    assert_eq!(measurement.percentile(90.0), None);
+}
+
+#[test]
+fn invalid_measurements_are_rejected() {
+    for json in [
+        // A count without a min or max
+        r#"{"sum":0,"sum_sq":0,"count":3}"#,
+        // A min above the max
+        r#"{"min":2000,"max":1000,"sum":3000,"sum_sq":5000000,"count":2}"#,
+        // A histogram with the wrong number of buckets
+        r#"{"min":1000,"max":1000,"sum":1000,"sum_sq":1000000,"count":1,"histogram":[1]}"#,
+        // No sum at all
+        r#"{"sum_sq":0,"count":0}"#,
+    ] {
+        assert!(
+            serde_json::from_str::<Measurement>(json).is_err(),
+            "{}",
+            json
+        );
+    }
+}
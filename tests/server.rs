@@ -0,0 +1,190 @@
+#![cfg(feature = "server")]
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, ChildStderr, Command, Stdio},
+};
+
+use common::TempFile;
+
+// The server, which is killed once the test is done with it however the test went
+struct Server {
+    child: Child,
+    port: u16,
+    // Kept open so that what the server goes on to print has somewhere to go
+    _stderr: BufReader<ChildStderr>,
+}
+
+impl Server {
+    fn start(path: &str) -> Self {
+        Self::start_with(path, &[])
+    }
+
+    fn start_with(path: &str, args: &[&str]) -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+            .args(["serve", path, "--port", "0"])
+            .args(args)
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // It only says where it's listening once the results are ready to be served
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let mut line = String::new();
+        stderr.read_line(&mut line).unwrap();
+        let port = line.trim_end().rsplit(':').next().unwrap().parse().unwrap();
+
+        Self {
+            child,
+            port,
+            _stderr: stderr,
+        }
+    }
+
+    // Sends a request, returning the status code and the body of the response
+    fn request(&self, method: &str, path: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+            method, path
+        )
+        .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+        (status, body.to_owned())
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn stations_are_served_as_json() {
+    let file = TempFile::new("serve", "Foo;1.0\nSão Paulo;10.0\nFoo;3.0\n".as_bytes());
+    let path = file.0.to_str().unwrap();
+    let server = Server::start(path);
+
+    let json = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+        .args([path, "--format", "json"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert_eq!(
+        server.request("GET", "/stations"),
+        (200, String::from_utf8(json).unwrap())
+    );
+
+    assert_eq!(
+        server.request("GET", "/stations/S%C3%A3o%20Paulo"),
+        (
+            200,
+            "{\"São Paulo\":{\"min\":10.0,\"max\":10.0,\"mean\":10.0,\"count\":1}}\n".to_owned()
+        )
+    );
+    assert_eq!(server.request("GET", "/stations/Bar").0, 404);
+    assert_eq!(server.request("GET", "/stations/%ZZ").0, 400);
+    assert_eq!(server.request("POST", "/stations").0, 405);
+}
+
+// The statistics and their decimals are served as they'd be written as JSON
+#[test]
+fn stations_are_served_with_the_statistics_asked_for() {
+    let file = TempFile::new("serve-statistics", b"Foo;1.0\nFoo;2.0\nFoo;4.0\n");
+    let path = file.0.to_str().unwrap();
+    let args = ["--decimals", "2", "--with-stddev", "--stats", "median"];
+    let server = Server::start_with(path, &args);
+
+    let json = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+        .args([path, "--format", "json"])
+        .args(args)
+        .output()
+        .unwrap()
+        .stdout;
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.contains(r#""mean":2.33"#), "{}", json);
+    assert!(json.contains(r#""stddev":"#), "{}", json);
+    assert_eq!(server.request("GET", "/stations"), (200, json));
+}
+
+// Only JSON is served, and always every station in order of name
+#[test]
+fn flags_that_shape_the_output_are_refused() {
+    let file = TempFile::new("serve-refused", b"Foo;1.0\n");
+    let path = file.0.to_str().unwrap();
+
+    for (args, flag) in [
+        (&["--format", "json"][..], "--format"),
+        (&["--sort", "max"], "--sort"),
+        (&["--sort-unicode"], "--sort"),
+        (&["--by", "mean"], "--by"),
+        (&["--top", "1"], "--top"),
+        (&["--summary"], "--summary"),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_one-brc"))
+            .args(["serve", path, "--port", "0"])
+            .args(args)
+            .output()
+            .unwrap();
+
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(
+            String::from_utf8(output.stderr)
+                .unwrap()
+                .contains(&format!("serve can't be combined with {}", flag)),
+            "{:?}",
+            args
+        );
+    }
+}
+
+#[test]
+fn reloading_aggregates_the_input_again() {
+    let file = TempFile::new("serve-reload", b"Foo;1.0\n");
+    let server = Server::start(file.0.to_str().unwrap());
+    assert_eq!(server.request("GET", "/stations/Bar").0, 404);
+
+    std::fs::write(&file.0, b"Foo;1.0\nBar;2.0\n").unwrap();
+    assert_eq!(server.request("GET", "/reload").0, 405);
+    assert_eq!(server.request("POST", "/reload").0, 200);
+    assert_eq!(
+        server.request("GET", "/stations/Bar"),
+        (
+            200,
+            "{\"Bar\":{\"min\":2.0,\"max\":2.0,\"mean\":2.0,\"count\":1}}\n".to_owned()
+        )
+    );
+
+    // A reload that fails leaves the results as they were
+    std::fs::write(&file.0, b"Foo;1.0\nmalformed\n").unwrap();
+    assert_eq!(server.request("POST", "/reload").0, 500);
+    assert_eq!(server.request("GET", "/stations/Bar").0, 200);
+}
+
+#[cfg(unix)]
+#[test]
+fn a_hangup_reloads_too() {
+    let file = TempFile::new("serve-hangup", b"Foo;1.0\n");
+    let server = Server::start(file.0.to_str().unwrap());
+
+    std::fs::write(&file.0, b"Foo;1.0\nBar;2.0\n").unwrap();
+    unsafe { libc::kill(server.child.id() as libc::pid_t, libc::SIGHUP) };
+
+    // The signal is only noticed between requests, so it may take a moment to come through
+    let reloaded = (0..100).any(|_| {
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        server.request("GET", "/stations/Bar").0 == 200
+    });
+    assert!(reloaded);
+}